@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protofish::context::Context;
+
+thread_local! {
+    static CONTEXT: Context =
+        Context::parse([include_str!("../../tests/data/hostile.proto")]).unwrap();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CONTEXT.with(|ctx| {
+        let node = ctx.get_message("hostile.Node").unwrap();
+
+        // Decoding must not panic and re-encoding the recovered values must be stable.
+        let encoded = node.decode(data, ctx).encode(ctx);
+        assert_eq!(node.decode(&encoded, ctx).encode(ctx), encoded);
+    })
+});
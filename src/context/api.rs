@@ -1,4 +1,5 @@
 use super::*;
+use crate::decode::Value;
 
 impl Context
 {
@@ -11,6 +12,8 @@ impl Context
             types_by_name: Default::default(),
             services: Default::default(),
             services_by_name: Default::default(),
+            extensions: Default::default(),
+            extensions_by_name: Default::default(),
         }
     }
 
@@ -79,6 +82,119 @@ impl Context
             .get(full_name)
             .map(|idx| &self.services[*idx])
     }
+
+    /// Iterates the services.
+    pub fn iter_services(&self) -> impl Iterator<Item = &Service>
+    {
+        self.services.iter()
+    }
+
+    /// Resolves an extension reference.
+    ///
+    /// Will **panic** if the extension defined by the `ExtensionRef` does not exist in this
+    /// context. Such panic means the `ExtensionRef` came from a different context. The panic is
+    /// not guaranteed, as an extension with an equal `ExtensionRef` may exist in multiple
+    /// contexts.
+    pub fn resolve_extension(&self, ext_ref: ExtensionRef) -> &Extension
+    {
+        &self.extensions[ext_ref.0 .0]
+    }
+
+    /// Gets an extension by full name.
+    pub fn get_extension(&self, full_name: &str) -> Option<&Extension>
+    {
+        self.extensions_by_name
+            .get(full_name)
+            .map(|idx| &self.extensions[*idx])
+    }
+
+    /// Gets an extension of a message by the field number.
+    pub fn get_extension_by_number(&self, extendee: MessageRef, number: u64) -> Option<&Extension>
+    {
+        self.resolve_message(extendee)
+            .extensions
+            .get(&number)
+            .map(|ext| self.resolve_extension(*ext))
+    }
+}
+
+impl Package
+{
+    /// Package name. None for an anonymous package.
+    pub fn name(&self) -> Option<&str>
+    {
+        self.name.as_deref()
+    }
+
+    /// Path of the file that defined the package.
+    ///
+    /// Only available for files loaded through [`Context::parse_with_loader`].
+    pub fn path(&self) -> Option<&str>
+    {
+        self.path.as_deref()
+    }
+
+    /// Files imported by the package file.
+    pub fn imports(&self) -> &[Import]
+    {
+        &self.imports
+    }
+
+    /// Syntax of the package file.
+    pub fn syntax(&self) -> Syntax
+    {
+        self.syntax
+    }
+
+    /// File options.
+    pub fn options(&self) -> &[ProtoOption]
+    {
+        &self.options
+    }
+
+    /// Resolved features of the package file.
+    pub fn features(&self) -> Features
+    {
+        Features::for_syntax(self.syntax).with_options(&self.options)
+    }
+}
+
+impl Features
+{
+    /// Default features of a syntax or an edition.
+    pub fn for_syntax(syntax: Syntax) -> Self
+    {
+        match syntax {
+            Syntax::Proto2 => Features {
+                field_presence: FieldPresence::Explicit,
+                enum_type: EnumType::Closed,
+                repeated_field_encoding: RepeatedFieldEncoding::Expanded,
+                utf8_validation: Utf8Validation::None,
+                message_encoding: MessageEncoding::LengthPrefixed,
+                json_format: JsonFormat::LegacyBestEffort,
+            },
+            Syntax::Proto3 => Features {
+                field_presence: FieldPresence::Implicit,
+                enum_type: EnumType::Open,
+                repeated_field_encoding: RepeatedFieldEncoding::Packed,
+                utf8_validation: Utf8Validation::Verify,
+                message_encoding: MessageEncoding::LengthPrefixed,
+                json_format: JsonFormat::Allow,
+            },
+            Syntax::Edition2023 | Syntax::Edition2024 => Features {
+                field_presence: FieldPresence::Explicit,
+                ..Features::for_syntax(Syntax::Proto3)
+            },
+        }
+    }
+}
+
+impl Default for Features
+{
+    fn default() -> Self
+    {
+        Features::for_syntax(Syntax::default())
+    }
 }
 
 impl TypeInfo
@@ -133,6 +249,26 @@ impl MessageInfo
             .and_then(|id| self.get_field(*id))
     }
 
+    /// Iterates the extensions of the message.
+    pub fn iter_extensions(&self) -> impl Iterator<Item = ExtensionRef> + '_
+    {
+        self.extensions.values().copied()
+    }
+
+    /// Get a field or an extension field by its number.
+    pub(crate) fn get_field_or_extension<'a>(
+        &'a self,
+        number: u64,
+        ctx: &'a Context,
+    ) -> Option<&'a MessageField>
+    {
+        self.get_field(number).or_else(|| {
+            self.extensions
+                .get(&number)
+                .map(|ext| &ctx.resolve_extension(*ext).field)
+        })
+    }
+
     /// Gets a oneof by a oneof reference.
     pub fn get_oneof(&self, oneof: OneofRef) -> Option<&Oneof>
     {
@@ -140,6 +276,19 @@ impl MessageInfo
     }
 }
 
+impl MessageField
+{
+    /// Default value given with the proto2 `default` option.
+    ///
+    /// Returns `None` if the field has no default value or the value does not match the field
+    /// type.
+    pub fn default_value(&self, ctx: &Context) -> Option<Value>
+    {
+        let option = self.options.iter().find(|o| o.name == "default")?;
+        descriptor::constant_value(&option.value, &self.field_type, ctx)
+    }
+}
+
 impl EnumInfo
 {
     /// Gets a field by value.
@@ -147,7 +296,15 @@ impl EnumInfo
     /// If the field is aliased, an undefined field alias is returned.
     pub fn get_field_by_value(&self, value: i64) -> Option<&EnumField>
     {
-        self.fields_by_value.get(&value)
+        self.fields_by_value
+            .get(&value)
+            .map(|idx| &self.fields[*idx])
+    }
+
+    /// Gets a field by its name.
+    pub fn get_field_by_name(&self, name: &str) -> Option<&EnumField>
+    {
+        self.fields_by_name.get(name).map(|idx| &self.fields[*idx])
     }
 }
 
@@ -181,6 +338,7 @@ impl ValueType
             Self::String => 2,
             Self::Bytes => 2,
             Self::Message(..) => 2,
+            Self::Group(..) => 3,
             Self::Enum(..) => 0,
         }
     }
@@ -1,32 +1,41 @@
 use std::borrow::Cow;
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
 
 use super::*;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub(crate) struct ContextBuilder
 {
     pub(crate) packages: Vec<PackageBuilder>,
+
+    /// True, if the `resolved_imports` of the packages have been filled. Only the imported types
+    /// are visible to a package in that case.
+    pub(crate) imports_resolved: bool,
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, PartialEq, Clone)]
 pub(crate) struct PackageBuilder
 {
-    pub(crate) path: PathBuf,
+    pub(crate) path: Option<String>,
     pub(crate) name: Option<String>,
-    pub(crate) imported_types: Vec<String>,
+    pub(crate) imports: Vec<Import>,
+    /// Package indices of the `imports`.
+    pub(crate) resolved_imports: Vec<usize>,
+    pub(crate) syntax: Syntax,
+    pub(crate) options: Vec<ProtoOption>,
     pub(crate) types: Vec<ProtobufItemBuilder>,
+    pub(crate) extensions: Vec<ExtensionBuilder>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum ProtobufItemBuilder
 {
     Type(ProtobufTypeBuilder),
     Service(ServiceBuilder),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum ProtobufTypeBuilder
 {
     Message(MessageBuilder),
@@ -41,6 +50,12 @@ pub(crate) struct MessageBuilder
     pub(crate) oneofs: Vec<OneofBuilder>,
     pub(crate) inner_types: Vec<InnerTypeBuilder>,
     pub(crate) options: Vec<ProtoOption>,
+    pub(crate) extension_ranges: Vec<RangeInclusive<u64>>,
+    pub(crate) extensions: Vec<ExtensionBuilder>,
+    pub(crate) span: Span,
+
+    /// Numbers of the fields, including the ones within the oneofs, in declaration order.
+    pub(crate) field_order: Vec<u64>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -56,14 +71,16 @@ pub(crate) struct EnumBuilder
     pub(crate) name: String,
     pub(crate) fields: Vec<EnumField>,
     pub(crate) options: Vec<ProtoOption>,
+    pub(crate) span: Span,
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, PartialEq, Clone)]
 pub(crate) struct ServiceBuilder
 {
     pub(crate) name: String,
     pub(crate) rpcs: Vec<RpcBuilder>,
     pub(crate) options: Vec<ProtoOption>,
+    pub(crate) span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -74,6 +91,15 @@ pub(crate) struct FieldBuilder
     pub(crate) name: String,
     pub(crate) number: u64,
     pub(crate) options: Vec<ProtoOption>,
+    pub(crate) span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct ExtensionBuilder
+{
+    /// Name of the extended message relative to the scope of the `extend` block.
+    pub(crate) extendee: String,
+    pub(crate) field: FieldBuilder,
 }
 
 #[derive(Default, Debug, PartialEq, Clone)]
@@ -89,31 +115,263 @@ pub(crate) enum FieldTypeBuilder
 {
     Builtin(ValueType),
     Unknown(String),
+    Group(String),
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, PartialEq, Clone)]
 pub(crate) struct RpcBuilder
 {
     pub(crate) name: String,
     pub(crate) input: RpcArgBuilder,
     pub(crate) output: RpcArgBuilder,
     pub(crate) options: Vec<ProtoOption>,
+    pub(crate) span: Span,
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, PartialEq, Clone)]
 pub(crate) struct RpcArgBuilder
 {
     pub(crate) stream: bool,
     pub(crate) message: String,
+    pub(crate) span: Span,
+}
+
+/// Build error together with the top level item that caused it.
+struct BuildError
+{
+    error: ParseError,
+    item: BuildItem,
+}
+
+/// Top level item of a package.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum BuildItem
+{
+    /// Package and type index of a type or a service.
+    Type(usize, usize),
+
+    /// Package and extension index of an extension declared at the package level.
+    Extension(usize, usize),
+}
+
+impl BuildError
+{
+    fn new(error: ParseError, idx_path: &[usize]) -> Self
+    {
+        Self {
+            error,
+            item: BuildItem::Type(idx_path[0], idx_path[1]),
+        }
+    }
+}
+
+/// Reference from a top level item to a type declared by a top level item.
+struct Reference
+{
+    from: BuildItem,
+    to: BuildItem,
+
+    /// Error reported for the `from` item if the `to` item is dropped.
+    error: ParseError,
+}
+
+impl Reference
+{
+    fn new(from: BuildItem, target: &CacheData, context: &str, span: Span) -> Self
+    {
+        Self {
+            from,
+            to: BuildItem::Type(target.idx_path[0], target.idx_path[1]),
+            error: ParseError::TypeNotFound {
+                name: target.full_name.clone(),
+                context: context.to_string(),
+                span: span.into(),
+            },
+        }
+    }
 }
 
 impl ContextBuilder
 {
+    /// Builds the context skipping the top level items that fail to build.
+    ///
+    /// The items that fail to build are dropped together with the items that refer to them.
+    /// Returns the context built from the remaining items and the errors encountered.
+    pub fn build_lenient(mut self) -> (Context, Vec<ParseError>)
+    {
+        let mut errors = vec![];
+        loop {
+            // Duplicate types are dropped before the references are checked and dropping a type
+            // may change what a name resolves to, so the check is repeated until nothing fails.
+            let failed = self.check(&mut errors);
+            if failed.is_empty() {
+                break;
+            }
+            self.drop_items(&failed);
+        }
+
+        // Errors the check missed leave nothing to build from.
+        match self.build() {
+            Ok(context) => (context, errors),
+            Err(e) => {
+                errors.push(e);
+                (Context::default(), errors)
+            }
+        }
+    }
+
+    /// Checks the top level items for build errors without building them.
+    ///
+    /// The errors are added to the `errors`. Returns the items that failed together with the
+    /// items that refer to them.
+    fn check(&self, errors: &mut Vec<ParseError>) -> BTreeSet<BuildItem>
+    {
+        let mut failed = BTreeSet::new();
+        let mut cache = self.new_cache();
+        for (i, p) in self.packages.iter().enumerate() {
+            for e in p.populate(&mut cache, i) {
+                failed.insert(e.item);
+                errors.push(e.error);
+            }
+        }
+
+        // The references can't be resolved reliably while the cache has duplicates.
+        if !failed.is_empty() {
+            return failed;
+        }
+
+        let mut build_errors = vec![];
+        let mut references = vec![];
+        let extensions = self.build_extensions(&cache, &mut build_errors);
+        for (item, ext) in &extensions {
+            let field_type = match ext.field.field_type {
+                ValueType::Message(m) | ValueType::Group(m) => Some(m.0 .0),
+                ValueType::Enum(e) => Some(e.0 .0),
+                _ => None,
+            };
+            for target in std::iter::once(ext.extendee.0 .0).chain(field_type) {
+                references.push(Reference::new(
+                    *item,
+                    &cache.types[target],
+                    &ext.full_name,
+                    ext.field.span,
+                ));
+            }
+        }
+
+        for (p_idx, p) in self.packages.iter().enumerate() {
+            for (t_idx, t) in p.types.iter().enumerate() {
+                let idx = vec![p_idx, t_idx];
+                let result = match t {
+                    ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(m)) => {
+                        m.check_references(&cache, &mut idx.clone(), &mut references)
+                    }
+                    ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(..)) => Ok(()),
+                    ProtobufItemBuilder::Service(s) => {
+                        let self_data = cache
+                            .type_by_idx_path(&idx)
+                            .expect("Existing service wasn't added to the cache");
+                        s.check_references(self_data, &cache, &mut references)
+                    }
+                };
+                if let Err(e) = result {
+                    build_errors.push(BuildError::new(e, &idx));
+                }
+            }
+        }
+
+        let mut dependents: BTreeMap<BuildItem, Vec<(BuildItem, ParseError)>> = BTreeMap::new();
+        for r in references {
+            dependents.entry(r.to).or_default().push((r.from, r.error));
+        }
+
+        // Fails the item and everything that refers to it.
+        let mut fail = |failed: &mut BTreeSet<BuildItem>, item: BuildItem, error: ParseError| {
+            let mut pending = vec![(item, error)];
+            while let Some((item, error)) = pending.pop() {
+                if failed.insert(item) {
+                    errors.push(error);
+                    let refs = dependents.remove(&item).unwrap_or_default();
+                    pending.extend(refs.into_iter().rev());
+                }
+            }
+        };
+        for e in build_errors {
+            fail(&mut failed, e.item, e.error);
+        }
+
+        // Duplicate extensions are checked last since dropping the failed items may remove the
+        // extensions they conflict with.
+        let mut numbers = BTreeSet::new();
+        let mut names = BTreeSet::new();
+        for (item, ext) in extensions {
+            if failed.contains(&item) {
+                continue;
+            }
+            if !numbers.insert((ext.extendee.0 .0, ext.field.number))
+                || !names.insert(ext.full_name.clone())
+            {
+                fail(
+                    &mut failed,
+                    item,
+                    ParseError::DuplicateExtension {
+                        name: ext.full_name,
+                        span: ext.field.span.into(),
+                    },
+                );
+            }
+        }
+
+        failed
+    }
+
+    /// Removes the top level `items` from the packages.
+    fn drop_items(&mut self, items: &BTreeSet<BuildItem>)
+    {
+        for (p_idx, p) in self.packages.iter_mut().enumerate() {
+            let mut t_idx = 0;
+            p.types.retain(|_| {
+                t_idx += 1;
+                !items.contains(&BuildItem::Type(p_idx, t_idx - 1))
+            });
+
+            let mut e_idx = 0;
+            p.extensions.retain(|_| {
+                e_idx += 1;
+                !items.contains(&BuildItem::Extension(p_idx, e_idx - 1))
+            });
+        }
+    }
+
+    fn new_cache(&self) -> BuildCache
+    {
+        BuildCache {
+            visibility: match self.imports_resolved {
+                true => Some(
+                    (0..self.packages.len())
+                        .map(|i| self.visible_packages(i))
+                        .collect(),
+                ),
+                false => None,
+            },
+            ..Default::default()
+        }
+    }
+
     pub fn build(mut self) -> Result<Context, ParseError>
     {
-        let mut cache = BuildCache::default();
+        let mut cache = self.new_cache();
         for (i, p) in self.packages.iter().enumerate() {
-            p.populate(&mut cache, &mut vec![i])?;
+            if let Some(e) = p.populate(&mut cache, i).into_iter().next() {
+                return Err(e.error);
+            }
+        }
+
+        // Extensions are built before the types are taken out of the builders.
+        let mut errors = vec![];
+        let mut extensions = self.build_extensions(&cache, &mut errors);
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e.error);
         }
 
         // Iterate the types through the cache, since the cache has enough
@@ -141,6 +399,30 @@ impl ContextBuilder
             .map(|s| self.take_service(&s.idx_path).build(s, &cache))
             .collect::<Result<_, _>>()?;
 
+        // Register the extensions to the messages they extend.
+        let mut extensions_by_name = HashMap::new();
+        for (idx, (_, ext)) in extensions.iter_mut().enumerate() {
+            ext.self_ref = ExtensionRef(InternalRef(idx));
+            let extendee = match &mut types[ext.extendee.0 .0] {
+                TypeInfo::Message(m) => m,
+                TypeInfo::Enum(..) => unreachable!("Extension of an enum"),
+            };
+            if extendee
+                .extensions
+                .insert(ext.field.number, ext.self_ref)
+                .is_some()
+                || extensions_by_name
+                    .insert(ext.full_name.clone(), idx)
+                    .is_some()
+            {
+                return Err(ParseError::DuplicateExtension {
+                    name: ext.full_name.clone(),
+                    span: ext.field.span.into(),
+                });
+            }
+        }
+        let extensions = extensions.into_iter().map(|(_, ext)| ext).collect();
+
         let types_by_name = types
             .iter()
             .enumerate()
@@ -159,6 +441,10 @@ impl ContextBuilder
             .map(|(idx, p)| Package {
                 name: p.name,
                 self_ref: PackageRef(InternalRef(idx)),
+                path: p.path,
+                imports: p.imports,
+                syntax: p.syntax,
+                options: p.options,
                 types: Vec::new(),
                 services: Vec::new(),
             })
@@ -191,14 +477,78 @@ impl ContextBuilder
             types_by_name,
             services,
             services_by_name,
+            extensions,
+            extensions_by_name,
         })
     }
 
+    /// Builds the extensions of all packages and messages.
+    ///
+    /// The extensions are returned together with the top level item that declares them. The
+    /// errors are added to the `errors` and the rest of the items are built regardless.
+    fn build_extensions(
+        &self,
+        cache: &BuildCache,
+        errors: &mut Vec<BuildError>,
+    ) -> Vec<(BuildItem, Extension)>
+    {
+        let mut extensions = vec![];
+        for (p_idx, p) in self.packages.iter().enumerate() {
+            let scope = p.name.as_deref().unwrap_or("");
+            let parent = TypeParent::Package(PackageRef(InternalRef(p_idx)));
+            for (e_idx, e) in p.extensions.iter().enumerate() {
+                let item = BuildItem::Extension(p_idx, e_idx);
+                match e.build(scope, p_idx, parent, p.features(), cache) {
+                    Ok(ext) => extensions.push((item, ext)),
+                    Err(error) => errors.push(BuildError { error, item }),
+                }
+            }
+
+            for (t_idx, t) in p.types.iter().enumerate() {
+                if let ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(m)) = t {
+                    let mut idx = vec![p_idx, t_idx];
+                    if let Err(e) = m.build_extensions(cache, &mut idx, &mut extensions) {
+                        errors.push(BuildError::new(e, &[p_idx, t_idx]));
+                    }
+                }
+            }
+        }
+        extensions
+    }
+
     fn take_type(&mut self, idx: &[usize]) -> ProtobufTypeBuilder
     {
         self.packages[idx[0]].take_type(&idx[1..])
     }
 
+    /// Lists the packages whose types are visible to the given package.
+    ///
+    /// These include the package itself, the packages it imports and any packages those
+    /// packages import with `import public`.
+    fn visible_packages(&self, package: usize) -> BTreeSet<usize>
+    {
+        fn add_public(packages: &[PackageBuilder], idx: usize, visible: &mut BTreeSet<usize>)
+        {
+            if !visible.insert(idx) {
+                return;
+            }
+
+            let p = &packages[idx];
+            for (import, resolved) in p.imports.iter().zip(&p.resolved_imports) {
+                if import.kind == ImportKind::Public {
+                    add_public(packages, *resolved, visible);
+                }
+            }
+        }
+
+        let mut visible = BTreeSet::new();
+        visible.insert(package);
+        for resolved in &self.packages[package].resolved_imports {
+            add_public(&self.packages, *resolved, &mut visible);
+        }
+        visible
+    }
+
     fn take_service(&mut self, idx: &[usize]) -> ServiceBuilder
     {
         self.packages[idx[0]].take_service(&idx[1..])
@@ -207,30 +557,43 @@ impl ContextBuilder
 
 impl PackageBuilder
 {
-    fn populate(&self, cache: &mut BuildCache, idx: &mut Vec<usize>) -> Result<(), ParseError>
+    /// Lists the types of the `package` into the build cache.
+    ///
+    /// Returns the errors of the top level items that failed. The rest of the items are listed
+    /// regardless of the errors.
+    fn populate(&self, cache: &mut BuildCache, package: usize) -> Vec<BuildError>
     {
-        let mut path = match &self.name {
+        let package_path: Vec<_> = match &self.name {
             Some(name) => name.split('.').collect(),
             None => vec![],
         };
 
-        idx.push(0);
-        for (i, t) in self.types.iter().enumerate() {
-            *idx.last_mut().unwrap() = i;
+        let features = self.features();
 
-            match t {
+        let mut errors = vec![];
+        for (i, t) in self.types.iter().enumerate() {
+            let mut path = package_path.clone();
+            let mut idx = vec![package, i];
+            let result = match t {
                 ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(m)) => {
-                    m.populate(cache, &mut path, idx)?
+                    m.populate(cache, &mut path, &mut idx, features)
                 }
                 ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(e)) => {
-                    e.populate(cache, &mut path, idx)?
+                    e.populate(cache, &mut path, &mut idx, features)
                 }
-                ProtobufItemBuilder::Service(m) => m.populate(cache, &mut path, idx)?,
+                ProtobufItemBuilder::Service(m) => m.populate(cache, &mut path, &mut idx, features),
+            };
+            if let Err(e) = result {
+                errors.push(BuildError::new(e, &[package, i]));
             }
         }
-        idx.pop();
 
-        Ok(())
+        errors
+    }
+
+    fn features(&self) -> Features
+    {
+        Features::for_syntax(self.syntax).with_options(&self.options)
     }
 
     fn take_type(&mut self, idx: &[usize]) -> ProtobufTypeBuilder
@@ -274,14 +637,28 @@ impl MessageBuilder
 {
     /// Lists types found in this message builder recursively into the build cache.
     ///
-    /// On error the `path` and `idx` will be left in an undefined state.
+    /// The `features` are the resolved features of the parent. On error the `path` and `idx`
+    /// will be left in an undefined state.
     fn populate<'a>(
         &'a self,
         cache: &mut BuildCache,
         path: &mut Vec<&'a str>,
         idx: &mut Vec<usize>,
+        features: Features,
     ) -> Result<(), ParseError>
     {
+        let mut features = features.with_options(&self.options);
+
+        // Map entries are encoded the same way regardless of the features.
+        if self
+            .options
+            .iter()
+            .any(|o| o.name == "map_entry" && o.value == Constant::Bool(true))
+        {
+            features.field_presence = FieldPresence::Implicit;
+            features.message_encoding = MessageEncoding::LengthPrefixed;
+        }
+
         path.push(&self.name);
         let full_name = path.join(".");
         let cache_idx = cache.types.len();
@@ -296,6 +673,7 @@ impl MessageBuilder
         {
             return Err(ParseError::DuplicateType {
                 name: path.join("."),
+                span: self.span.into(),
             });
         }
 
@@ -306,12 +684,13 @@ impl MessageBuilder
             full_name,
             idx_path: idx.clone(),
             final_idx: cache_idx,
+            features,
         });
 
         idx.push(0);
         for (i, t) in self.inner_types.iter().enumerate() {
             *idx.last_mut().unwrap() = i;
-            t.populate(cache, path, idx)?;
+            t.populate(cache, path, idx, features)?;
         }
 
         idx.pop();
@@ -328,6 +707,10 @@ impl MessageBuilder
                 fields: std::mem::take(&mut self.fields),
                 oneofs: std::mem::take(&mut self.oneofs),
                 options: std::mem::take(&mut self.options),
+                extension_ranges: std::mem::take(&mut self.extension_ranges),
+                extensions: vec![],
+                span: self.span,
+                field_order: std::mem::take(&mut self.field_order),
                 inner_types: self
                     .inner_types
                     .iter()
@@ -361,7 +744,15 @@ impl MessageBuilder
         let mut fields: Vec<_> = self
             .fields
             .into_iter()
-            .map(|field| field.build(self_data, cache, None))
+            .map(|field| {
+                field.build(
+                    &self_data.full_name,
+                    self_data.idx_path[0],
+                    cache,
+                    None,
+                    self_data.features,
+                )
+            })
             .collect::<Result<_, _>>()?;
 
         let mut oneofs: Vec<_> = self
@@ -370,10 +761,19 @@ impl MessageBuilder
             .enumerate()
             .map(|(idx, oneof)| {
                 let oneof_ref = OneofRef(InternalRef(idx));
+                let features = self_data.features.with_options(&oneof.options);
                 let mut new_fields: Vec<_> = oneof
                     .fields
                     .into_iter()
-                    .map(|field| field.build(self_data, cache, Some(oneof_ref)))
+                    .map(|field| {
+                        field.build(
+                            &self_data.full_name,
+                            self_data.idx_path[0],
+                            cache,
+                            Some(oneof_ref),
+                            features,
+                        )
+                    })
                     .collect::<Result<_, _>>()?;
                 fields.append(&mut new_fields);
                 Ok(Oneof {
@@ -413,8 +813,139 @@ impl MessageBuilder
             self_ref: MessageRef(InternalRef(self_data.final_idx)),
             inner_types,
             oneofs,
+            options: self.options,
+            extension_ranges: self.extension_ranges,
+            features: self_data.features,
+            span: self.span,
             fields,
             fields_by_name,
+            field_order: self.field_order,
+            extensions: BTreeMap::new(),
+        })
+    }
+
+    /// Resolves the types referred to by the fields of this message and its inner messages.
+    ///
+    /// The references are added to the `references`. On error the `idx` will be left in an
+    /// undefined state.
+    fn check_references(
+        &self,
+        cache: &BuildCache,
+        idx: &mut Vec<usize>,
+        references: &mut Vec<Reference>,
+    ) -> Result<(), ParseError>
+    {
+        let self_data = cache
+            .type_by_idx_path(idx)
+            .expect("Existing type wasn't added to the cache");
+        let item = BuildItem::Type(idx[0], idx[1]);
+        let fields = self
+            .fields
+            .iter()
+            .chain(self.oneofs.iter().flat_map(|o| &o.fields));
+        for field in fields {
+            let scope = &self_data.full_name;
+            if let Some(t) = field.field_type.resolve(scope, idx[0], cache, field.span)? {
+                references.push(Reference::new(item, t, scope, field.span));
+            }
+        }
+
+        idx.push(0);
+        for (i, t) in self.inner_types.iter().enumerate() {
+            if let InnerTypeBuilder::Message(m) = t {
+                *idx.last_mut().unwrap() = i;
+                m.check_references(cache, idx, references)?;
+            }
+        }
+        idx.pop();
+
+        Ok(())
+    }
+
+    /// Builds the extensions declared in this message and its inner messages.
+    ///
+    /// On error the `idx` will be left in an undefined state.
+    fn build_extensions(
+        &self,
+        cache: &BuildCache,
+        idx: &mut Vec<usize>,
+        extensions: &mut Vec<(BuildItem, Extension)>,
+    ) -> Result<(), ParseError>
+    {
+        let self_data = cache
+            .type_by_idx_path(idx)
+            .expect("Existing type wasn't added to the cache");
+        let parent = TypeParent::Message(MessageRef::from(self_data));
+        for e in &self.extensions {
+            let ext = e.build(
+                &self_data.full_name,
+                idx[0],
+                parent,
+                self_data.features,
+                cache,
+            )?;
+            extensions.push((BuildItem::Type(idx[0], idx[1]), ext));
+        }
+
+        idx.push(0);
+        for (i, t) in self.inner_types.iter().enumerate() {
+            if let InnerTypeBuilder::Message(m) = t {
+                *idx.last_mut().unwrap() = i;
+                m.build_extensions(cache, idx, extensions)?;
+            }
+        }
+        idx.pop();
+
+        Ok(())
+    }
+}
+
+impl ExtensionBuilder
+{
+    /// Builds the extension declared within the `scope`. The `features` are the resolved
+    /// features of the scope.
+    fn build(
+        &self,
+        scope: &str,
+        package: usize,
+        parent: TypeParent,
+        mut features: Features,
+        cache: &BuildCache,
+    ) -> Result<Extension, ParseError>
+    {
+        let target = cache
+            .resolve_type(&self.extendee, scope, package)
+            .ok_or_else(|| ParseError::TypeNotFound {
+                name: self.extendee.clone(),
+                context: scope.to_string(),
+                span: self.field.span.into(),
+            })?;
+        if target.item_type != ItemType::Message {
+            return Err(ParseError::InvalidTypeKind {
+                type_name: self.extendee.clone(),
+                context: "extend",
+                expected: ItemType::Message,
+                actual: target.item_type,
+                span: self.field.span.into(),
+            });
+        }
+
+        // Extensions always track their presence.
+        features.field_presence = FieldPresence::Explicit;
+        let field = self
+            .field
+            .clone()
+            .build(scope, package, cache, None, features)?;
+
+        Ok(Extension {
+            full_name: match scope.is_empty() {
+                true => field.name.clone(),
+                false => format!("{}.{}", scope, field.name),
+            },
+            parent,
+            self_ref: ExtensionRef(InternalRef(0)),
+            extendee: MessageRef::from(target),
+            field,
         })
     }
 }
@@ -438,21 +969,51 @@ impl InnerTypeBuilder
 
 impl FieldBuilder
 {
+    /// Builds the field declared within the `scope` of the `package`. The `features` are the
+    /// resolved features of the message or oneof.
     fn build(
         self,
-        self_data: &CacheData,
+        scope: &str,
+        package: usize,
         cache: &BuildCache,
         oneof: Option<OneofRef>,
+        features: Features,
     ) -> Result<MessageField, ParseError>
     {
-        let multiplicity = resolve_multiplicity(self.multiplicity, &self.field_type, &self.options);
+        let features = features.with_options(&self.options);
+        let multiplicity = resolve_multiplicity(
+            self.multiplicity,
+            &self.field_type,
+            &self.options,
+            oneof.is_some(),
+            features,
+        );
+        let json_name = match self.options.iter().find(|o| o.name == "json_name") {
+            Some(ProtoOption {
+                value: Constant::String(s),
+                ..
+            }) => String::from_utf8_lossy(s).into_owned(),
+            _ => default_json_name(&self.name),
+        };
+        let field_type = match self.field_type.build(scope, package, cache, self.span)? {
+            ValueType::Message(m)
+                if features.message_encoding == MessageEncoding::Delimited
+                    && multiplicity != Multiplicity::Map =>
+            {
+                ValueType::Group(m)
+            }
+            vt => vt,
+        };
         Ok(MessageField {
             name: self.name,
+            json_name,
             number: self.number,
             multiplicity,
-            field_type: self.field_type.build(self_data, cache)?,
+            field_type,
             oneof,
             options: self.options,
+            features,
+            span: self.span,
         })
     }
 }
@@ -461,17 +1022,34 @@ fn resolve_multiplicity(
     proto_multiplicity: Multiplicity,
     field_type: &FieldTypeBuilder,
     options: &[ProtoOption],
+    in_oneof: bool,
+    features: Features,
 ) -> Multiplicity
 {
-    // If this isn't a repeated field, the multiplicity follows the proto one (single or optional).
-    if proto_multiplicity != Multiplicity::Repeated {
-        return proto_multiplicity;
+    match proto_multiplicity {
+        // Fields without a label get their presence from the features. Fields within a oneof
+        // track their presence through the oneof.
+        Multiplicity::Single if !in_oneof => {
+            return match features.field_presence {
+                FieldPresence::Explicit => Multiplicity::Optional,
+                FieldPresence::Implicit => Multiplicity::Single,
+                FieldPresence::LegacyRequired => Multiplicity::Required,
+            }
+        }
+
+        // Repeated fields are resolved below.
+        Multiplicity::Repeated => {}
+
+        // Otherwise the multiplicity follows the proto one (single, optional, required or map).
+        m => return m,
     }
 
     // Repeated field.
     match field_type {
         // Non-scalar fields are always repeated.
-        FieldTypeBuilder::Unknown(..) => return Multiplicity::Repeated,
+        FieldTypeBuilder::Unknown(..) | FieldTypeBuilder::Group(..) => {
+            return Multiplicity::Repeated
+        }
         FieldTypeBuilder::Builtin(vt) if vt.wire_type() == 2 => return Multiplicity::Repeated,
 
         // Scalar field.
@@ -486,29 +1064,139 @@ fn resolve_multiplicity(
         };
     }
 
-    Multiplicity::RepeatedPacked
+    match features.repeated_field_encoding {
+        RepeatedFieldEncoding::Packed => Multiplicity::RepeatedPacked,
+        RepeatedFieldEncoding::Expanded => Multiplicity::Repeated,
+    }
 }
 
-impl FieldTypeBuilder
+impl Features
 {
-    fn build(self, self_data: &CacheData, cache: &BuildCache) -> Result<ValueType, ParseError>
+    /// Overrides the features with the `features.*` options.
+    ///
+    /// Unknown features and values are ignored.
+    pub(crate) fn with_options(mut self, options: &[ProtoOption]) -> Self
     {
-        Ok(match self {
-            FieldTypeBuilder::Builtin(vt) => vt,
-            FieldTypeBuilder::Unknown(s) => {
-                let t = cache
-                    .resolve_type(&s, &self_data.full_name)
-                    .ok_or_else(|| ParseError::TypeNotFound {
-                        name: s,
-                        context: self_data.full_name.to_string(),
-                    })?;
-
-                match t.item_type {
-                    ItemType::Message => ValueType::Message(MessageRef(InternalRef(t.final_idx))),
-                    ItemType::Enum => ValueType::Enum(EnumRef(InternalRef(t.final_idx))),
-                    _ => unreachable!("Service as field type"),
+        for option in options {
+            let value = match &option.value {
+                Constant::Ident(value) => value.as_str(),
+                _ => continue,
+            };
+            match (option.name.as_str(), value) {
+                ("features.field_presence", "EXPLICIT") => {
+                    self.field_presence = FieldPresence::Explicit
+                }
+                ("features.field_presence", "IMPLICIT") => {
+                    self.field_presence = FieldPresence::Implicit
+                }
+                ("features.field_presence", "LEGACY_REQUIRED") => {
+                    self.field_presence = FieldPresence::LegacyRequired
+                }
+                ("features.enum_type", "OPEN") => self.enum_type = EnumType::Open,
+                ("features.enum_type", "CLOSED") => self.enum_type = EnumType::Closed,
+                ("features.repeated_field_encoding", "PACKED") => {
+                    self.repeated_field_encoding = RepeatedFieldEncoding::Packed
+                }
+                ("features.repeated_field_encoding", "EXPANDED") => {
+                    self.repeated_field_encoding = RepeatedFieldEncoding::Expanded
+                }
+                ("features.utf8_validation", "VERIFY") => {
+                    self.utf8_validation = Utf8Validation::Verify
                 }
+                ("features.utf8_validation", "NONE") => self.utf8_validation = Utf8Validation::None,
+                ("features.message_encoding", "LENGTH_PREFIXED") => {
+                    self.message_encoding = MessageEncoding::LengthPrefixed
+                }
+                ("features.message_encoding", "DELIMITED") => {
+                    self.message_encoding = MessageEncoding::Delimited
+                }
+                ("features.json_format", "ALLOW") => self.json_format = JsonFormat::Allow,
+                ("features.json_format", "LEGACY_BEST_EFFORT") => {
+                    self.json_format = JsonFormat::LegacyBestEffort
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+}
+
+/// Converts a field name to its JSON name the same way `protoc` does: `foo_bar` -> `fooBar`.
+pub(crate) fn default_json_name(name: &str) -> String
+{
+    let mut json = String::with_capacity(name.len());
+    let mut capitalize = false;
+    for c in name.chars() {
+        match c {
+            '_' => capitalize = true,
+            c if capitalize => {
+                json.push(c.to_ascii_uppercase());
+                capitalize = false;
             }
+            c => json.push(c),
+        }
+    }
+    json
+}
+
+impl FieldTypeBuilder
+{
+    /// Finds the type the field refers to. Builtin types don't refer to any type.
+    fn resolve<'a>(
+        &self,
+        scope: &str,
+        package: usize,
+        cache: &'a BuildCache,
+        span: Span,
+    ) -> Result<Option<&'a CacheData>, ParseError>
+    {
+        let (name, group) = match self {
+            FieldTypeBuilder::Builtin(..) => return Ok(None),
+            FieldTypeBuilder::Unknown(s) => (s, false),
+            FieldTypeBuilder::Group(s) => (s, true),
+        };
+
+        let t = cache
+            .resolve_type(name, scope, package)
+            .filter(|t| !group || t.item_type == ItemType::Message)
+            .ok_or_else(|| ParseError::TypeNotFound {
+                name: name.clone(),
+                context: scope.to_string(),
+                span: span.into(),
+            })?;
+        if t.item_type == ItemType::Service {
+            return Err(ParseError::InvalidTypeKind {
+                type_name: name.clone(),
+                context: "field",
+                expected: ItemType::Message,
+                actual: t.item_type,
+                span: span.into(),
+            });
+        }
+        Ok(Some(t))
+    }
+
+    fn build(
+        self,
+        scope: &str,
+        package: usize,
+        cache: &BuildCache,
+        span: Span,
+    ) -> Result<ValueType, ParseError>
+    {
+        let t = match self.resolve(scope, package, cache, span)? {
+            Some(t) => t,
+            None => match self {
+                FieldTypeBuilder::Builtin(vt) => return Ok(vt),
+                _ => unreachable!("Unresolved type reference"),
+            },
+        };
+
+        Ok(match (self, t.item_type) {
+            (FieldTypeBuilder::Group(..), _) => ValueType::Group(MessageRef::from(t)),
+            (_, ItemType::Message) => ValueType::Message(MessageRef::from(t)),
+            (_, ItemType::Enum) => ValueType::Enum(EnumRef::from(t)),
+            _ => unreachable!("Service as field type"),
         })
     }
 }
@@ -520,11 +1208,12 @@ impl InnerTypeBuilder
         cache: &mut BuildCache,
         path: &mut Vec<&'a str>,
         idx: &mut Vec<usize>,
+        features: Features,
     ) -> Result<(), ParseError>
     {
         match self {
-            InnerTypeBuilder::Message(m) => m.populate(cache, path, idx),
-            InnerTypeBuilder::Enum(e) => e.populate(cache, path, idx),
+            InnerTypeBuilder::Message(m) => m.populate(cache, path, idx, features),
+            InnerTypeBuilder::Enum(e) => e.populate(cache, path, idx, features),
         }
     }
 
@@ -546,7 +1235,8 @@ impl EnumBuilder
         &'a self,
         cache: &mut BuildCache,
         path: &mut Vec<&'a str>,
-        idx: &mut Vec<usize>,
+        idx: &mut [usize],
+        features: Features,
     ) -> Result<(), ParseError>
     {
         path.push(&self.name);
@@ -559,6 +1249,7 @@ impl EnumBuilder
         {
             return Err(ParseError::DuplicateType {
                 name: path.join("."),
+                span: self.span.into(),
             });
         }
         path.pop();
@@ -566,8 +1257,9 @@ impl EnumBuilder
         cache.types.push(CacheData {
             item_type: ItemType::Enum,
             full_name,
-            idx_path: idx.clone(),
+            idx_path: idx.to_vec(),
             final_idx: cache_idx,
+            features: features.with_options(&self.options),
         });
 
         Ok(())
@@ -578,9 +1270,15 @@ impl EnumBuilder
         let fields_by_name = self
             .fields
             .iter()
-            .map(|f| (f.name.clone(), f.value))
+            .enumerate()
+            .map(|(idx, f)| (f.name.clone(), idx))
+            .collect();
+        let fields_by_value = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(idx, f)| (f.value, idx))
             .collect();
-        let fields_by_value = self.fields.into_iter().map(|f| (f.value, f)).collect();
 
         let parent = cache.parent_type(&self_data.idx_path);
 
@@ -589,6 +1287,10 @@ impl EnumBuilder
             full_name: self_data.full_name.to_string(),
             self_ref: EnumRef(InternalRef(self_data.final_idx)),
             parent,
+            options: self.options,
+            features: self_data.features,
+            span: self.span,
+            fields: self.fields,
             fields_by_value,
             fields_by_name,
         })
@@ -613,32 +1315,57 @@ impl ServiceBuilder
         &'a self,
         cache: &mut BuildCache,
         path: &mut Vec<&'a str>,
-        idx: &mut Vec<usize>,
+        idx: &mut [usize],
+        features: Features,
     ) -> Result<(), ParseError>
     {
         path.push(&self.name);
         let full_name = path.join(".");
         let cache_idx = cache.services.len();
-        if let Some(..) = cache
+        if cache
             .items
             .insert(full_name.clone(), (ItemType::Service, cache_idx))
+            .is_some()
         {
             return Err(ParseError::DuplicateType {
                 name: path.join("."),
+                span: self.span.into(),
             });
         }
         path.pop();
 
+        cache
+            .items_by_idx
+            .insert(idx.to_vec(), (ItemType::Service, cache_idx));
         cache.services.push(CacheData {
             item_type: ItemType::Service,
             full_name,
-            idx_path: idx.clone(),
+            idx_path: idx.to_vec(),
             final_idx: cache_idx,
+            features: features.with_options(&self.options),
         });
 
         Ok(())
     }
 
+    /// Resolves the messages referred to by the rpcs and adds them to the `references`.
+    fn check_references(
+        &self,
+        self_data: &CacheData,
+        cache: &BuildCache,
+        references: &mut Vec<Reference>,
+    ) -> Result<(), ParseError>
+    {
+        let item = BuildItem::Type(self_data.idx_path[0], self_data.idx_path[1]);
+        for rpc in &self.rpcs {
+            for arg in [&rpc.input, &rpc.output] {
+                let t = arg.resolve(self_data, cache)?;
+                references.push(Reference::new(item, t, &self_data.full_name, arg.span));
+            }
+        }
+        Ok(())
+    }
+
     fn build(self, self_data: &CacheData, cache: &BuildCache) -> Result<Service, ParseError>
     {
         let rpcs: Vec<_> = self
@@ -664,7 +1391,8 @@ impl ServiceBuilder
             full_name: self_data.full_name.clone(),
             rpcs,
             rpcs_by_name,
-            options: vec![],
+            options: self.options,
+            span: self.span,
         })
     }
 }
@@ -677,37 +1405,51 @@ impl RpcBuilder
             name: self.name,
             input: self.input.build(self_data, cache)?,
             output: self.output.build(self_data, cache)?,
-            options: vec![],
+            options: self.options,
+            span: self.span,
         })
     }
 }
 
 impl RpcArgBuilder
 {
-    fn build(self, rpc_data: &CacheData, cache: &BuildCache) -> Result<RpcArg, ParseError>
+    /// Finds the message used as the argument.
+    fn resolve<'a>(
+        &self,
+        rpc_data: &CacheData,
+        cache: &'a BuildCache,
+    ) -> Result<&'a CacheData, ParseError>
     {
         // Fetch the type data from the cache so we can figure out the type reference.
-        let self_data = match cache.resolve_type(&self.message, &rpc_data.full_name) {
-            Some(data) => data,
-            None => {
-                return Err(ParseError::TypeNotFound {
-                    name: self.message,
-                    context: rpc_data.full_name.clone(),
-                })
-            }
-        };
+        let self_data =
+            match cache.resolve_type(&self.message, &rpc_data.full_name, rpc_data.idx_path[0]) {
+                Some(data) => data,
+                None => {
+                    return Err(ParseError::TypeNotFound {
+                        name: self.message.clone(),
+                        context: rpc_data.full_name.clone(),
+                        span: self.span.into(),
+                    })
+                }
+            };
 
         // All rpc input/output types must be messages.
         if self_data.item_type != ItemType::Message {
             return Err(ParseError::InvalidTypeKind {
-                type_name: self.message,
+                type_name: self.message.clone(),
                 context: "service input/output",
                 expected: ItemType::Message,
                 actual: self_data.item_type,
+                span: self.span.into(),
             });
         }
 
-        let message = MessageRef(InternalRef(self_data.final_idx));
+        Ok(self_data)
+    }
+
+    fn build(self, rpc_data: &CacheData, cache: &BuildCache) -> Result<RpcArg, ParseError>
+    {
+        let message = MessageRef::from(self.resolve(rpc_data, cache)?);
         Ok(RpcArg {
             stream: self.stream,
             message,
@@ -744,6 +1486,9 @@ struct BuildCache
     items_by_idx: BTreeMap<Vec<usize>, (ItemType, usize)>,
     types: Vec<CacheData>,
     services: Vec<CacheData>,
+
+    /// Packages visible to each package or `None` if all packages are visible.
+    visibility: Option<Vec<BTreeSet<usize>>>,
 }
 
 struct CacheData
@@ -752,14 +1497,25 @@ struct CacheData
     idx_path: Vec<usize>,
     final_idx: usize,
     full_name: String,
+
+    /// Resolved features of the item.
+    features: Features,
 }
 
 impl BuildCache
 {
-    fn resolve_type(&self, relative_name: &str, mut current_path: &str) -> Option<&CacheData>
+    /// Resolves a type name relative to the `current_path` as seen from the `package`.
+    fn resolve_type(
+        &self,
+        relative_name: &str,
+        mut current_path: &str,
+        package: usize,
+    ) -> Option<&CacheData>
     {
         if let Some(absolute) = relative_name.strip_prefix('.') {
-            return self.type_by_full_name(absolute);
+            return self
+                .type_by_full_name(absolute)
+                .filter(|t| self.is_visible(package, t));
         }
 
         loop {
@@ -768,7 +1524,10 @@ impl BuildCache
                 false => format!("{}.{}", current_path, relative_name).into(),
             };
 
-            if let Some(t) = self.type_by_full_name(&lookup) {
+            if let Some(t) = self
+                .type_by_full_name(&lookup)
+                .filter(|t| self.is_visible(package, t))
+            {
                 return Some(t);
             }
 
@@ -788,6 +1547,14 @@ impl BuildCache
         }
     }
 
+    fn is_visible(&self, package: usize, data: &CacheData) -> bool
+    {
+        match &self.visibility {
+            Some(visibility) => visibility[package].contains(&data.idx_path[0]),
+            None => true,
+        }
+    }
+
     fn parent_type(&self, current: &[usize]) -> TypeParent
     {
         match current.len() {
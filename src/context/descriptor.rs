@@ -0,0 +1,1392 @@
+use bytes::{Bytes, BytesMut};
+use std::convert::TryFrom;
+use std::sync::OnceLock;
+
+use super::builder::*;
+use super::options::resolve_custom_option;
+use super::*;
+use crate::decode::{EnumValue, FieldValue, MessageValue, UnknownValue, Value};
+use crate::text_format::escape;
+
+/// The types of `google/protobuf/descriptor.proto` in a syntax the parser supports.
+///
+/// Custom options extend the `google.protobuf.*Options` messages defined in this file. The file
+/// can be parsed together with the files that declare custom options to resolve their values.
+pub const DESCRIPTOR_PROTO: &str = include_str!("descriptor.proto");
+
+/// Gets the context containing the `google.protobuf` descriptor types, parsing it on the first
+/// call.
+pub(crate) fn descriptor_context() -> &'static Context
+{
+    static CONTEXT: OnceLock<Context> = OnceLock::new();
+    CONTEXT.get_or_init(|| {
+        Context::parse([DESCRIPTOR_PROTO]).expect("Bundled descriptor.proto failed to parse")
+    })
+}
+
+impl Context
+{
+    /// Creates a decoding context from a serialized `google.protobuf.FileDescriptorSet`.
+    ///
+    /// Descriptor sets are produced by `protoc --descriptor_set_out` and returned by the gRPC
+    /// server reflection. The set must contain all the files that define the referenced types,
+    /// for example by using `--include_imports` with `protoc`.
+    pub fn from_file_descriptor_set(data: &[u8]) -> Result<Self, ParseError>
+    {
+        let descriptor = descriptor_context();
+        let set = descriptor
+            .get_message("google.protobuf.FileDescriptorSet")
+            .expect("FileDescriptorSet missing from descriptor.proto")
+            .decode(data, descriptor);
+        validate(&set)?;
+
+        let set = Descriptor::new(&set, descriptor);
+        let builder = ContextBuilder {
+            packages: set
+                .messages("file")
+                .enumerate()
+                .map(|(i, file)| PackageBuilder::from_descriptor(file, FileId(i)))
+                .collect::<Result<_, _>>()?,
+
+            // The descriptors are produced by a compiler that has already checked the imports
+            // and all the type references are fully qualified.
+            imports_resolved: false,
+        };
+
+        builder.build()
+    }
+}
+
+/// Ensures the descriptor bytes were decoded without errors.
+fn validate(msg: &MessageValue) -> Result<(), ParseError>
+{
+    if msg.garbage.is_some() {
+        return Err(invalid("trailing garbage in a message"));
+    }
+
+    for field in &msg.fields {
+        match &field.value {
+            Value::Message(m) => validate(m)?,
+            Value::Incomplete(..) => return Err(invalid("incomplete value")),
+            Value::LimitExceeded(..) => return Err(invalid("value exceeds a decode limit")),
+            Value::InvalidString(..) => return Err(invalid("string is not valid UTF-8")),
+            Value::Unknown(UnknownValue::Invalid(..)) => return Err(invalid("invalid wire type")),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid(reason: &str) -> ParseError
+{
+    ParseError::InvalidDescriptor {
+        reason: reason.to_string(),
+    }
+}
+
+/// Decoded descriptor message with access to the fields by name.
+#[derive(Clone, Copy)]
+struct Descriptor<'a>
+{
+    value: &'a MessageValue,
+    info: &'a MessageInfo,
+    ctx: &'a Context,
+}
+
+impl<'a> Descriptor<'a>
+{
+    fn new(value: &'a MessageValue, ctx: &'a Context) -> Self
+    {
+        Self {
+            value,
+            info: ctx.resolve_message(value.msg_ref),
+            ctx,
+        }
+    }
+
+    fn values(self, name: &str) -> impl Iterator<Item = &'a Value>
+    {
+        let number = match self.info.get_field_by_name(name) {
+            Some(field) => field.number,
+            None => panic!("Field '{}' not found in '{}'", name, self.info.full_name),
+        };
+        self.value
+            .fields
+            .iter()
+            .filter(move |f| f.number == number)
+            .map(|f| &f.value)
+    }
+
+    /// Gets the value of a singular field. The last value wins if there are several.
+    fn value(self, name: &str) -> Option<&'a Value>
+    {
+        self.values(name).last()
+    }
+
+    fn string(self, name: &str) -> Option<&'a str>
+    {
+        match self.value(name) {
+            Some(Value::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn required_string(self, name: &str) -> Result<&'a str, ParseError>
+    {
+        self.string(name)
+            .ok_or_else(|| invalid(&format!("missing '{}' in '{}'", name, self.info.full_name)))
+    }
+
+    fn int(self, name: &str) -> Option<i64>
+    {
+        self.value(name).and_then(as_int)
+    }
+
+    fn ints(self, name: &str) -> impl Iterator<Item = i64> + 'a
+    {
+        self.values(name).filter_map(as_int)
+    }
+
+    fn bool(self, name: &str) -> bool
+    {
+        matches!(self.value(name), Some(Value::Bool(true)))
+    }
+
+    fn message(self, name: &str) -> Option<Descriptor<'a>>
+    {
+        self.messages(name).last()
+    }
+
+    fn messages(self, name: &str) -> impl Iterator<Item = Descriptor<'a>>
+    {
+        let ctx = self.ctx;
+        self.values(name).filter_map(move |v| match v {
+            Value::Message(m) => Some(Descriptor::new(m, ctx)),
+            _ => None,
+        })
+    }
+
+    fn span(file: FileId) -> Span
+    {
+        Span {
+            file,
+            ..Default::default()
+        }
+    }
+}
+
+fn as_int(value: &Value) -> Option<i64>
+{
+    match value {
+        Value::Int32(v) => Some(*v as i64),
+        Value::Int64(v) => Some(*v),
+        Value::UInt64(v) => Some(*v as i64),
+        Value::Enum(v) => Some(v.value),
+        _ => None,
+    }
+}
+
+impl PackageBuilder
+{
+    fn from_descriptor(file: Descriptor, id: FileId) -> Result<Self, ParseError>
+    {
+        let name = file.string("package").filter(|p| !p.is_empty());
+        let syntax = match (file.string("syntax"), file.int("edition")) {
+            (Some("editions"), Some(1000)) => Syntax::Edition2023,
+            (Some("editions"), Some(1001)) => Syntax::Edition2024,
+            (Some("editions"), edition) => {
+                return Err(invalid(&format!("unsupported edition {:?}", edition)))
+            }
+            (None, _) | (Some(""), _) | (Some("proto2"), _) => Syntax::Proto2,
+            (Some(_), _) => Syntax::Proto3,
+        };
+
+        let public: Vec<_> = file.ints("public_dependency").collect();
+        let weak: Vec<_> = file.ints("weak_dependency").collect();
+        let imports = file
+            .values("dependency")
+            .enumerate()
+            .filter_map(|(i, dep)| match dep {
+                Value::String(path) => Some(Import {
+                    path: path.clone(),
+                    kind: match i as i64 {
+                        i if public.contains(&i) => ImportKind::Public,
+                        i if weak.contains(&i) => ImportKind::Weak,
+                        _ => ImportKind::Default,
+                    },
+                    span: Descriptor::span(id),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        let scope = name.map(|n| format!(".{}", n)).unwrap_or_default();
+        let mut types = vec![];
+        for msg in file.messages("message_type") {
+            types.push(ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(
+                MessageBuilder::from_descriptor(msg, &scope, syntax, id)?,
+            )));
+        }
+        for e in file.messages("enum_type") {
+            types.push(ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(
+                EnumBuilder::from_descriptor(e, id)?,
+            )));
+        }
+        for service in file.messages("service") {
+            types.push(ProtobufItemBuilder::Service(
+                ServiceBuilder::from_descriptor(service, id)?,
+            ));
+        }
+        let extensions = file
+            .messages("extension")
+            .map(|f| ExtensionBuilder::from_descriptor(f, syntax, id))
+            .collect::<Result<_, _>>()?;
+
+        Ok(PackageBuilder {
+            path: file.string("name").map(String::from),
+            name: name.map(String::from),
+            imports,
+            resolved_imports: vec![],
+            syntax,
+            options: options(file.message("options")),
+            types,
+            extensions,
+        })
+    }
+}
+
+impl MessageBuilder
+{
+    /// Builds a message from a `DescriptorProto`.
+    ///
+    /// The `scope` is the fully qualified name of the parent with a leading dot.
+    fn from_descriptor(
+        msg: Descriptor,
+        scope: &str,
+        syntax: Syntax,
+        file: FileId,
+    ) -> Result<Self, ParseError>
+    {
+        let name = msg.required_string("name")?;
+        let full_name = format!("{}.{}", scope, name);
+
+        let mut inner_types = vec![];
+        let mut map_entries = vec![];
+        for nested in msg.messages("nested_type") {
+            let nested = MessageBuilder::from_descriptor(nested, &full_name, syntax, file)?;
+            if nested
+                .options
+                .iter()
+                .any(|o| o.name == "map_entry" && o.value == Constant::Bool(true))
+            {
+                map_entries.push(format!("{}.{}", full_name, nested.name));
+            }
+            inner_types.push(InnerTypeBuilder::Message(nested));
+        }
+        for e in msg.messages("enum_type") {
+            inner_types.push(InnerTypeBuilder::Enum(EnumBuilder::from_descriptor(
+                e, file,
+            )?));
+        }
+
+        let fields = msg
+            .messages("field")
+            .map(|f| {
+                Ok((
+                    FieldBuilder::from_descriptor(f, syntax, &map_entries, file)?,
+                    f.int("oneof_index"),
+                    f.bool("proto3_optional"),
+                ))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+
+        // The proto3 `optional` fields are placed in synthetic oneofs in the descriptors. These
+        // are not real oneofs as far as the parsed context is concerned.
+        let mut oneofs: Vec<_> = msg
+            .messages("oneof_decl")
+            .enumerate()
+            .map(|(idx, oneof)| {
+                let synthetic = fields
+                    .iter()
+                    .any(|(_, oneof, optional)| *oneof == Some(idx as i64) && *optional);
+                Ok((
+                    synthetic,
+                    OneofBuilder {
+                        name: oneof.required_string("name")?.to_string(),
+                        fields: vec![],
+                        options: options(oneof.message("options")),
+                    },
+                ))
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+
+        let field_order = fields.iter().map(|(field, ..)| field.number).collect();
+        let mut message_fields = vec![];
+        for (field, oneof, _) in fields {
+            match oneof.and_then(|i| oneofs.get_mut(i as usize)) {
+                Some((false, oneof)) => oneof.fields.push(field),
+                _ => message_fields.push(field),
+            }
+        }
+
+        let extensions = msg
+            .messages("extension")
+            .map(|f| ExtensionBuilder::from_descriptor(f, syntax, file))
+            .collect::<Result<_, _>>()?;
+
+        // Descriptors store the extension ranges with exclusive ends.
+        let extension_ranges = msg
+            .messages("extension_range")
+            .map(|range| {
+                let start = range.int("start").unwrap_or_default() as u64;
+                let end = range.int("end").unwrap_or_default() as u64;
+                start..=end.saturating_sub(1)
+            })
+            .collect();
+
+        Ok(MessageBuilder {
+            name: name.to_string(),
+            fields: message_fields,
+            extension_ranges,
+            extensions,
+            oneofs: oneofs
+                .into_iter()
+                .filter(|(synthetic, _)| !synthetic)
+                .map(|(_, oneof)| oneof)
+                .collect(),
+            inner_types,
+            options: options(msg.message("options")),
+            span: Descriptor::span(file),
+            field_order,
+        })
+    }
+}
+
+impl ExtensionBuilder
+{
+    fn from_descriptor(field: Descriptor, syntax: Syntax, file: FileId)
+        -> Result<Self, ParseError>
+    {
+        Ok(ExtensionBuilder {
+            extendee: field.required_string("extendee")?.to_string(),
+            field: FieldBuilder::from_descriptor(field, syntax, &[], file)?,
+        })
+    }
+}
+
+impl FieldBuilder
+{
+    fn from_descriptor(
+        field: Descriptor,
+        syntax: Syntax,
+        map_entries: &[String],
+        file: FileId,
+    ) -> Result<Self, ParseError>
+    {
+        let name = field.required_string("name")?;
+        let type_name = field.string("type_name");
+        let field_type = match (field.int("type"), type_name) {
+            (Some(1), _) => FieldTypeBuilder::Builtin(ValueType::Double),
+            (Some(2), _) => FieldTypeBuilder::Builtin(ValueType::Float),
+            (Some(3), _) => FieldTypeBuilder::Builtin(ValueType::Int64),
+            (Some(4), _) => FieldTypeBuilder::Builtin(ValueType::UInt64),
+            (Some(5), _) => FieldTypeBuilder::Builtin(ValueType::Int32),
+            (Some(6), _) => FieldTypeBuilder::Builtin(ValueType::Fixed64),
+            (Some(7), _) => FieldTypeBuilder::Builtin(ValueType::Fixed32),
+            (Some(8), _) => FieldTypeBuilder::Builtin(ValueType::Bool),
+            (Some(9), _) => FieldTypeBuilder::Builtin(ValueType::String),
+            (Some(12), _) => FieldTypeBuilder::Builtin(ValueType::Bytes),
+            (Some(13), _) => FieldTypeBuilder::Builtin(ValueType::UInt32),
+            (Some(15), _) => FieldTypeBuilder::Builtin(ValueType::SFixed32),
+            (Some(16), _) => FieldTypeBuilder::Builtin(ValueType::SFixed64),
+            (Some(17), _) => FieldTypeBuilder::Builtin(ValueType::SInt32),
+            (Some(18), _) => FieldTypeBuilder::Builtin(ValueType::SInt64),
+
+            // Message, enum and group types refer to the type by name. The type may be missing
+            // from descriptors that haven't been fully resolved.
+            (Some(10), Some(name)) => FieldTypeBuilder::Group(name.to_string()),
+            (Some(11), Some(name)) | (Some(14), Some(name)) | (None, Some(name)) => {
+                FieldTypeBuilder::Unknown(name.to_string())
+            }
+            (ty, _) => {
+                return Err(invalid(&format!(
+                    "invalid type {:?} for field '{}'",
+                    ty, name
+                )))
+            }
+        };
+
+        let multiplicity = match field.int("label") {
+            Some(3) => match type_name {
+                Some(t) if map_entries.iter().any(|e| e == t) => Multiplicity::Map,
+                _ => Multiplicity::Repeated,
+            },
+            Some(1) if syntax == Syntax::Proto2 || field.bool("proto3_optional") => {
+                Multiplicity::Optional
+            }
+            Some(2) => Multiplicity::Required,
+            _ => Multiplicity::Single,
+        };
+
+        // Custom JSON names are written as options in the proto files.
+        let mut options = options(field.message("options"));
+        if let Some(json) = field.string("json_name") {
+            if json != default_json_name(name) {
+                options.push(ProtoOption {
+                    name: "json_name".to_string(),
+                    value: Constant::String(Bytes::from(json.to_string())),
+                });
+            }
+        }
+
+        // Default values are written as options in the proto files as well.
+        if let Some(default) = field.string("default_value") {
+            options.push(ProtoOption {
+                name: "default".to_string(),
+                value: default_constant(default, &field_type),
+            });
+        }
+
+        Ok(FieldBuilder {
+            multiplicity,
+            field_type,
+            name: name.to_string(),
+            number: field.int("number").unwrap_or_default() as u64,
+            options,
+            span: Descriptor::span(file),
+        })
+    }
+}
+
+impl EnumBuilder
+{
+    fn from_descriptor(e: Descriptor, file: FileId) -> Result<Self, ParseError>
+    {
+        Ok(EnumBuilder {
+            name: e.required_string("name")?.to_string(),
+            fields: e
+                .messages("value")
+                .map(|v| {
+                    Ok(EnumField {
+                        name: v.required_string("name")?.to_string(),
+                        value: v.int("number").unwrap_or_default(),
+                        options: options(v.message("options")),
+                    })
+                })
+                .collect::<Result<_, ParseError>>()?,
+            options: options(e.message("options")),
+            span: Descriptor::span(file),
+        })
+    }
+}
+
+impl ServiceBuilder
+{
+    fn from_descriptor(service: Descriptor, file: FileId) -> Result<Self, ParseError>
+    {
+        Ok(ServiceBuilder {
+            name: service.required_string("name")?.to_string(),
+            rpcs: service
+                .messages("method")
+                .map(|method| {
+                    Ok(RpcBuilder {
+                        name: method.required_string("name")?.to_string(),
+                        input: RpcArgBuilder {
+                            stream: method.bool("client_streaming"),
+                            message: method.required_string("input_type")?.to_string(),
+                            span: Descriptor::span(file),
+                        },
+                        output: RpcArgBuilder {
+                            stream: method.bool("server_streaming"),
+                            message: method.required_string("output_type")?.to_string(),
+                            span: Descriptor::span(file),
+                        },
+                        options: options(method.message("options")),
+                        span: Descriptor::span(file),
+                    })
+                })
+                .collect::<Result<_, ParseError>>()?,
+            options: options(service.message("options")),
+            span: Descriptor::span(file),
+        })
+    }
+}
+
+/// Converts one of the `*Options` messages into a list of options.
+///
+/// Only the options defined in `descriptor.proto` are included. Custom options are stored as
+/// extensions, which appear as unknown fields here. The fields of the `FeatureSet` become
+/// `features.*` options the same way they are written in the proto files.
+fn options(descriptor: Option<Descriptor>) -> Vec<ProtoOption>
+{
+    let descriptor = match descriptor {
+        Some(d) => d,
+        None => return vec![],
+    };
+
+    let mut output = vec![];
+    for field in &descriptor.value.fields {
+        let info = match descriptor.info.get_field(field.number) {
+            Some(info) => info,
+            None => continue,
+        };
+
+        let value = match &field.value {
+            Value::Message(m) if info.name == "uninterpreted_option" => {
+                output.extend(uninterpreted_option(Descriptor::new(m, descriptor.ctx)));
+                continue;
+            }
+            Value::Message(m) if info.name == "features" => {
+                let features = options(Some(Descriptor::new(m, descriptor.ctx)));
+                output.extend(features.into_iter().map(|o| ProtoOption {
+                    name: format!("features.{}", o.name),
+                    value: o.value,
+                }));
+                continue;
+            }
+            Value::Bool(v) => Constant::Bool(*v),
+            Value::Double(v) => Constant::Float(*v),
+            Value::String(v) => Constant::String(Bytes::from(v.clone())),
+            Value::Enum(v) => match descriptor
+                .ctx
+                .resolve_enum(v.enum_ref)
+                .get_field_by_value(v.value)
+            {
+                Some(f) => Constant::Ident(f.name.clone()),
+                None => Constant::Integer(v.value),
+            },
+            other => match as_int(other) {
+                Some(v) => Constant::Integer(v),
+                None => continue,
+            },
+        };
+
+        output.push(ProtoOption {
+            name: info.name.clone(),
+            value,
+        });
+    }
+    output
+}
+
+/// Converts an `UninterpretedOption` into an option the same way the parser would.
+fn uninterpreted_option(option: Descriptor) -> Option<ProtoOption>
+{
+    let name = option
+        .messages("name")
+        .map(|part| {
+            let name = part.string("name_part").unwrap_or_default();
+            match part.bool("is_extension") {
+                true => format!("({})", name),
+                false => name.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+
+    let value = if let Some(ident) = option.string("identifier_value") {
+        match ident {
+            "true" => Constant::Bool(true),
+            "false" => Constant::Bool(false),
+            ident => Constant::Ident(ident.to_string()),
+        }
+    } else if let Some(Value::UInt64(v)) = option.value("positive_int_value") {
+        Constant::Integer(*v as i64)
+    } else if let Some(Value::Int64(v)) = option.value("negative_int_value") {
+        Constant::Integer(*v)
+    } else if let Some(Value::Double(v)) = option.value("double_value") {
+        Constant::Float(*v)
+    } else if let Some(Value::Bytes(v)) = option.value("string_value") {
+        Constant::String(v.clone())
+    } else if let Some(fields) = option.string("aggregate_value") {
+        Constant::parse_aggregate(fields)?
+    } else {
+        return None;
+    };
+
+    Some(ProtoOption { name, value })
+}
+
+impl Context
+{
+    /// Serializes the context as a `google.protobuf.FileDescriptorSet`.
+    ///
+    /// Each package becomes a file in the descriptor set. Packages that were not loaded by a path
+    /// are named `file<N>.proto` after their index in the context. The dependencies of each file
+    /// include the imports of the package and any other file that defines a type the package
+    /// refers to. The files are ordered so that the dependencies precede the files that depend
+    /// on them.
+    ///
+    /// Custom options are stored as extension fields of the options messages if the context
+    /// declares the extensions, which requires `google/protobuf/descriptor.proto` to be part of
+    /// the context. Other custom options are stored as uninterpreted options.
+    pub fn to_file_descriptor_set(&self) -> BytesMut
+    {
+        let descriptor = descriptor_context();
+        let file_names: Vec<String> = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, p)| match &p.path {
+                Some(path) => path.clone(),
+                None => format!("file{}.proto", idx),
+            })
+            .collect();
+
+        let dependencies: Vec<_> = self
+            .packages
+            .iter()
+            .map(|p| self.dependencies(p, &file_names))
+            .collect();
+
+        // Order the files so that the dependencies come first.
+        fn visit(
+            idx: usize,
+            deps: &[Vec<(usize, ImportKind)>],
+            visited: &mut [bool],
+            order: &mut Vec<usize>,
+        )
+        {
+            if visited[idx] {
+                return;
+            }
+            visited[idx] = true;
+            for (dep, _) in &deps[idx] {
+                visit(*dep, deps, visited, order);
+            }
+            order.push(idx);
+        }
+        let mut visited = vec![false; self.packages.len()];
+        let mut order = vec![];
+        for idx in 0..self.packages.len() {
+            visit(idx, &dependencies, &mut visited, &mut order);
+        }
+
+        let mut set = DescriptorBuilder::new(descriptor, self, "google.protobuf.FileDescriptorSet");
+        for idx in order {
+            let mut file = set.child("file");
+            self.write_file(
+                &self.packages[idx],
+                &file_names[idx],
+                &dependencies[idx],
+                &file_names,
+                &mut file,
+            );
+            set.message("file", file);
+        }
+
+        set.value.encode(descriptor)
+    }
+
+    /// Lists the files the package depends on.
+    fn dependencies(&self, package: &Package, file_names: &[String]) -> Vec<(usize, ImportKind)>
+    {
+        let mut dependencies: Vec<(usize, ImportKind)> = package
+            .imports
+            .iter()
+            .filter_map(|import| {
+                file_names
+                    .iter()
+                    .position(|name| *name == import.path)
+                    .map(|idx| (idx, import.kind))
+            })
+            .collect();
+
+        let mut add = |idx: usize| {
+            if idx != package.self_ref.0 .0 && !dependencies.iter().any(|(d, _)| *d == idx) {
+                dependencies.push((idx, ImportKind::Default));
+            }
+        };
+
+        let field_package = |field: &MessageField| match field.field_type {
+            ValueType::Message(m) | ValueType::Group(m) => Some(self.package_of(m.0)),
+            ValueType::Enum(e) => Some(self.package_of(e.0)),
+            _ => None,
+        };
+
+        let mut types: Vec<TypeRef> = package.types.clone();
+        while let Some(ty) = types.pop() {
+            if let TypeRef::Message(m) = ty {
+                let msg = self.resolve_message(m);
+                types.extend(&msg.inner_types);
+                for field in msg.iter_fields() {
+                    if let Some(idx) = field_package(field) {
+                        add(idx);
+                    }
+                }
+            }
+        }
+
+        for ext in self.extensions_of_package(package) {
+            add(self.package_of(ext.extendee.0));
+            if let Some(idx) = field_package(&ext.field) {
+                add(idx);
+            }
+        }
+
+        for service in &package.services {
+            for rpc in &self.services[*service].rpcs {
+                add(self.package_of(rpc.input.message.0));
+                add(self.package_of(rpc.output.message.0));
+            }
+        }
+
+        dependencies
+    }
+
+    /// Lists the extensions declared in the package or in any of its messages.
+    fn extensions_of_package<'a>(
+        &'a self,
+        package: &'a Package,
+    ) -> impl Iterator<Item = &'a Extension>
+    {
+        self.extensions.iter().filter(move |ext| {
+            let idx = match ext.parent {
+                TypeParent::Package(p) => p.0 .0,
+                TypeParent::Message(m) => self.package_of(m.0),
+            };
+            idx == package.self_ref.0 .0
+        })
+    }
+
+    /// Finds the package index that contains the type.
+    fn package_of(&self, mut ty: InternalRef) -> usize
+    {
+        loop {
+            let parent = match self.types.get(ty.0) {
+                Some(TypeInfo::Message(m)) => m.parent,
+                Some(TypeInfo::Enum(e)) => e.parent,
+                None => panic!("Type did not exist in this context"),
+            };
+            match parent {
+                TypeParent::Package(p) => return p.0 .0,
+                TypeParent::Message(m) => ty = m.0,
+            }
+        }
+    }
+
+    fn write_file(
+        &self,
+        package: &Package,
+        name: &str,
+        dependencies: &[(usize, ImportKind)],
+        file_names: &[String],
+        file: &mut DescriptorBuilder,
+    )
+    {
+        file.string("name", name);
+        if let Some(name) = &package.name {
+            file.string("package", name);
+        }
+
+        for (i, (dep, kind)) in dependencies.iter().enumerate() {
+            file.string("dependency", &file_names[*dep]);
+            match kind {
+                ImportKind::Public => file.int("public_dependency", i as i64),
+                ImportKind::Weak => file.int("weak_dependency", i as i64),
+                ImportKind::Default => {}
+            }
+        }
+
+        for ty in &package.types {
+            match ty {
+                TypeRef::Message(m) => {
+                    let mut msg = file.child("message_type");
+                    self.write_message(self.resolve_message(*m), package.syntax, &mut msg);
+                    file.message("message_type", msg);
+                }
+                TypeRef::Enum(e) => {
+                    let mut en = file.child("enum_type");
+                    write_enum(self.resolve_enum(*e), &mut en);
+                    file.message("enum_type", en);
+                }
+            }
+        }
+
+        for service in &package.services {
+            let mut s = file.child("service");
+            write_service(self, &self.services[*service], &mut s);
+            file.message("service", s);
+        }
+
+        let parent = TypeParent::Package(package.self_ref);
+        for ext in self.extensions.iter().filter(|ext| ext.parent == parent) {
+            let mut f = file.child("extension");
+            self.write_extension(ext, package.syntax, &mut f);
+            file.message("extension", f);
+        }
+
+        file.options("options", &package.options);
+
+        match package.syntax {
+            Syntax::Proto2 => {}
+            Syntax::Proto3 => file.string("syntax", "proto3"),
+            Syntax::Edition2023 => {
+                file.string("syntax", "editions");
+                file.int("edition", 1000);
+            }
+            Syntax::Edition2024 => {
+                file.string("syntax", "editions");
+                file.int("edition", 1001);
+            }
+        }
+    }
+
+    fn write_message(&self, info: &MessageInfo, syntax: Syntax, msg: &mut DescriptorBuilder)
+    {
+        msg.string("name", &info.name);
+
+        // The proto3 optional fields are placed in synthetic oneofs after the real ones.
+        let mut synthetic_oneofs = vec![];
+        for field in info.field_order.iter().filter_map(|n| info.get_field(*n)) {
+            let mut f = msg.child("field");
+            self.write_field(field, syntax, &mut f);
+
+            match field.oneof {
+                Some(oneof) => f.int("oneof_index", oneof.0 .0 as i64),
+                None if field.multiplicity == Multiplicity::Optional
+                    && syntax == Syntax::Proto3 =>
+                {
+                    f.int(
+                        "oneof_index",
+                        (info.oneofs.len() + synthetic_oneofs.len()) as i64,
+                    );
+                    f.set("proto3_optional", Value::Bool(true));
+                    synthetic_oneofs.push(format!("_{}", field.name));
+                }
+                None => {}
+            }
+            msg.message("field", f);
+        }
+
+        let parent = TypeParent::Message(info.self_ref);
+        for ext in self.extensions.iter().filter(|ext| ext.parent == parent) {
+            let mut f = msg.child("extension");
+            self.write_extension(ext, syntax, &mut f);
+            msg.message("extension", f);
+        }
+
+        for inner in &info.inner_types {
+            match inner {
+                TypeRef::Message(m) => {
+                    let mut nested = msg.child("nested_type");
+                    self.write_message(self.resolve_message(*m), syntax, &mut nested);
+                    msg.message("nested_type", nested);
+                }
+                TypeRef::Enum(e) => {
+                    let mut en = msg.child("enum_type");
+                    write_enum(self.resolve_enum(*e), &mut en);
+                    msg.message("enum_type", en);
+                }
+            }
+        }
+
+        for range in &info.extension_ranges {
+            let mut r = msg.child("extension_range");
+            r.int("start", *range.start() as i64);
+            r.int("end", *range.end() as i64 + 1);
+            msg.message("extension_range", r);
+        }
+
+        msg.options("options", &info.options);
+
+        for oneof in &info.oneofs {
+            let mut o = msg.child("oneof_decl");
+            o.string("name", &oneof.name);
+            o.options("options", &oneof.options);
+            msg.message("oneof_decl", o);
+        }
+        for name in synthetic_oneofs {
+            let mut o = msg.child("oneof_decl");
+            o.string("name", &name);
+            msg.message("oneof_decl", o);
+        }
+    }
+
+    fn write_extension(&self, ext: &Extension, syntax: Syntax, f: &mut DescriptorBuilder)
+    {
+        self.write_field(&ext.field, syntax, f);
+        let extendee = &self.resolve_message(ext.extendee).full_name;
+        f.string("extendee", &format!(".{}", extendee));
+    }
+
+    /// Writes the field details shared by message fields and extensions.
+    fn write_field(&self, field: &MessageField, syntax: Syntax, f: &mut DescriptorBuilder)
+    {
+        // Editions express the presence and the group encoding with the features, which are
+        // included in the field options.
+        let editions = matches!(syntax, Syntax::Edition2023 | Syntax::Edition2024);
+
+        f.string("name", &field.name);
+        f.int("number", field.number as i64);
+        f.int(
+            "label",
+            match field.multiplicity {
+                Multiplicity::Required if !editions => 2,
+                Multiplicity::Single | Multiplicity::Optional | Multiplicity::Required => 1,
+                Multiplicity::Repeated | Multiplicity::RepeatedPacked | Multiplicity::Map => 3,
+            },
+        );
+
+        let (field_type, type_name) = match &field.field_type {
+            ValueType::Double => (1, None),
+            ValueType::Float => (2, None),
+            ValueType::Int64 => (3, None),
+            ValueType::UInt64 => (4, None),
+            ValueType::Int32 => (5, None),
+            ValueType::Fixed64 => (6, None),
+            ValueType::Fixed32 => (7, None),
+            ValueType::Bool => (8, None),
+            ValueType::String => (9, None),
+            ValueType::Group(m) if editions => (11, Some(&self.resolve_message(*m).full_name)),
+            ValueType::Group(m) => (10, Some(&self.resolve_message(*m).full_name)),
+            ValueType::Message(m) => (11, Some(&self.resolve_message(*m).full_name)),
+            ValueType::Bytes => (12, None),
+            ValueType::UInt32 => (13, None),
+            ValueType::Enum(e) => (14, Some(&self.resolve_enum(*e).full_name)),
+            ValueType::SFixed32 => (15, None),
+            ValueType::SFixed64 => (16, None),
+            ValueType::SInt32 => (17, None),
+            ValueType::SInt64 => (18, None),
+        };
+        f.int("type", field_type);
+        if let Some(type_name) = type_name {
+            f.string("type_name", &format!(".{}", type_name));
+        }
+
+        f.string("json_name", &field.json_name);
+        if let Some(default) = field.options.iter().find(|o| o.name == "default") {
+            f.string(
+                "default_value",
+                &default_string(&default.value, &field.field_type),
+            );
+        }
+
+        // The `json_name` and `default` options are stored in the fields above. Make sure the
+        // packing survives even if the default for the syntax would differ.
+        let mut options: Vec<_> = field
+            .options
+            .iter()
+            .filter(|o| o.name != "json_name" && o.name != "default")
+            .cloned()
+            .collect();
+        if !options.iter().any(|o| o.name == "packed") {
+            let packed = match (&field.multiplicity, syntax) {
+                (Multiplicity::RepeatedPacked, Syntax::Proto2) => Some(true),
+                (Multiplicity::Repeated, Syntax::Proto3) if field.field_type.wire_type() != 2 => {
+                    Some(false)
+                }
+                _ => None,
+            };
+            if let Some(packed) = packed {
+                options.push(ProtoOption {
+                    name: "packed".to_string(),
+                    value: Constant::Bool(packed),
+                });
+            }
+        }
+        f.options("options", &options);
+    }
+}
+
+fn write_enum(info: &EnumInfo, e: &mut DescriptorBuilder)
+{
+    e.string("name", &info.name);
+    for field in &info.fields {
+        let mut v = e.child("value");
+        v.string("name", &field.name);
+        v.int("number", field.value);
+        v.options("options", &field.options);
+        e.message("value", v);
+    }
+
+    e.options("options", &info.options);
+}
+
+fn write_service(ctx: &Context, service: &Service, s: &mut DescriptorBuilder)
+{
+    s.string("name", &service.name);
+    for rpc in &service.rpcs {
+        let mut m = s.child("method");
+        m.string("name", &rpc.name);
+        m.string(
+            "input_type",
+            &format!(".{}", ctx.resolve_message(rpc.input.message).full_name),
+        );
+        m.string(
+            "output_type",
+            &format!(".{}", ctx.resolve_message(rpc.output.message).full_name),
+        );
+        m.options("options", &rpc.options);
+        if rpc.input.stream {
+            m.set("client_streaming", Value::Bool(true));
+        }
+        if rpc.output.stream {
+            m.set("server_streaming", Value::Bool(true));
+        }
+        s.message("method", m);
+    }
+    s.options("options", &service.options);
+}
+
+/// Descriptor message under construction with access to the fields by name.
+struct DescriptorBuilder<'a>
+{
+    value: MessageValue,
+    info: &'a MessageInfo,
+    ctx: &'a Context,
+
+    /// Context being exported, used for resolving the custom options.
+    source: &'a Context,
+}
+
+impl<'a> DescriptorBuilder<'a>
+{
+    fn new(ctx: &'a Context, source: &'a Context, name: &str) -> Self
+    {
+        let info = ctx
+            .get_message(name)
+            .unwrap_or_else(|| panic!("'{}' missing from descriptor.proto", name));
+        Self::for_message(ctx, source, info)
+    }
+
+    fn for_message(ctx: &'a Context, source: &'a Context, info: &'a MessageInfo) -> Self
+    {
+        Self {
+            value: MessageValue {
+                msg_ref: info.self_ref,
+                fields: vec![],
+                garbage: None,
+            },
+            info,
+            ctx,
+            source,
+        }
+    }
+
+    fn field(&self, name: &str) -> &'a MessageField
+    {
+        match self.info.get_field_by_name(name) {
+            Some(field) => field,
+            None => panic!("Field '{}' not found in '{}'", name, self.info.full_name),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: Value)
+    {
+        let number = self.field(name).number;
+        self.value.fields.push(FieldValue { number, value });
+    }
+
+    fn string(&mut self, name: &str, value: &str)
+    {
+        self.set(name, Value::String(value.to_string()));
+    }
+
+    fn int(&mut self, name: &str, value: i64)
+    {
+        let value = match &self.field(name).field_type {
+            ValueType::Int32 => Value::Int32(value as i32),
+            ValueType::Int64 => Value::Int64(value),
+            ValueType::UInt64 => Value::UInt64(value as u64),
+            ValueType::Enum(e) => Value::Enum(EnumValue {
+                enum_ref: *e,
+                value,
+            }),
+            t => panic!("Field '{}' is not an integer: {:?}", name, t),
+        };
+        self.set(name, value);
+    }
+
+    /// Creates a builder for a message field.
+    fn child(&self, name: &str) -> DescriptorBuilder<'a>
+    {
+        match &self.field(name).field_type {
+            ValueType::Message(m) => {
+                Self::for_message(self.ctx, self.source, self.ctx.resolve_message(*m))
+            }
+            t => panic!("Field '{}' is not a message: {:?}", name, t),
+        }
+    }
+
+    fn message(&mut self, name: &str, value: DescriptorBuilder)
+    {
+        self.set(name, Value::Message(Box::new(value.value)));
+    }
+
+    /// Sets one of the `*Options` messages.
+    ///
+    /// Options defined in `descriptor.proto` are set to their fields and the `features.*`
+    /// options to the `FeatureSet`. Custom options are resolved against the extensions declared
+    /// in the exported context and stored as extension fields the same way `protoc` stores them.
+    /// Options that cannot be resolved are stored as uninterpreted options.
+    fn options(&mut self, name: &str, options: &[ProtoOption])
+    {
+        if options.is_empty() {
+            return;
+        }
+
+        let mut msg = self.child(name);
+        let feature_set = msg
+            .info
+            .get_field_by_name("features")
+            .map(|_| msg.child("features"));
+        let mut features = vec![];
+        let mut extensions = vec![];
+        for option in options {
+            let feature = option.name.strip_prefix("features.").filter(
+                |f| matches!(&feature_set, Some(set) if set.info.get_field_by_name(f).is_some()),
+            );
+            if let Some(feature) = feature {
+                features.push(ProtoOption {
+                    name: feature.to_string(),
+                    value: option.value.clone(),
+                });
+                continue;
+            }
+
+            let value = msg
+                .info
+                .get_field_by_name(&option.name)
+                .and_then(|f| constant_value(&option.value, &f.field_type, msg.ctx));
+            if let Some(value) = value {
+                msg.set(&option.name, value);
+                continue;
+            }
+
+            match resolve_custom_option(option, &msg.info.full_name, msg.source) {
+                Some(extension) => extensions.push(extension),
+                None => {
+                    let mut uninterpreted = msg.child("uninterpreted_option");
+                    write_uninterpreted_option(option, &mut uninterpreted);
+                    msg.message("uninterpreted_option", uninterpreted);
+                }
+            }
+        }
+
+        // The extensions are not known to the descriptor context so they are carried over as
+        // unknown fields.
+        for extension in extensions {
+            let data = extension.encode(msg.source);
+            msg.value
+                .fields
+                .extend(msg.info.decode(&data, msg.ctx).fields);
+        }
+        msg.options("features", &features);
+        self.message(name, msg);
+    }
+}
+
+/// Converts an option constant into a value of the given type.
+///
+/// Returns `None` if the constant does not match the type or is out of its range.
+pub(crate) fn constant_value(constant: &Constant, ty: &ValueType, ctx: &Context) -> Option<Value>
+{
+    Some(match (ty, constant) {
+        (ValueType::Bool, Constant::Bool(v)) => Value::Bool(*v),
+        (ValueType::Int32, Constant::Integer(v)) => Value::Int32(i32::try_from(*v).ok()?),
+        (ValueType::Int64, Constant::Integer(v)) => Value::Int64(*v),
+        (ValueType::UInt32, Constant::Integer(v)) => Value::UInt32(u32::try_from(*v).ok()?),
+        (ValueType::UInt64, Constant::Integer(v)) => Value::UInt64(u64::try_from(*v).ok()?),
+        (ValueType::SInt32, Constant::Integer(v)) => Value::SInt32(i32::try_from(*v).ok()?),
+        (ValueType::SInt64, Constant::Integer(v)) => Value::SInt64(*v),
+        (ValueType::Fixed32, Constant::Integer(v)) => Value::Fixed32(u32::try_from(*v).ok()?),
+        (ValueType::Fixed64, Constant::Integer(v)) => Value::Fixed64(u64::try_from(*v).ok()?),
+        (ValueType::SFixed32, Constant::Integer(v)) => Value::SFixed32(i32::try_from(*v).ok()?),
+        (ValueType::SFixed64, Constant::Integer(v)) => Value::SFixed64(*v),
+        (ValueType::Double, Constant::Float(v)) => Value::Double(*v),
+        (ValueType::Double, Constant::Integer(v)) => Value::Double(*v as f64),
+        (ValueType::Float, Constant::Float(v)) => Value::Float(*v as f32),
+        (ValueType::Float, Constant::Integer(v)) => Value::Float(*v as f32),
+        (ValueType::String, Constant::String(v)) => {
+            Value::String(String::from_utf8_lossy(v).into_owned())
+        }
+        (ValueType::Bytes, Constant::String(v)) => Value::Bytes(v.clone()),
+        (ValueType::Enum(e), Constant::Ident(name)) => Value::Enum(EnumValue {
+            enum_ref: *e,
+            value: ctx.resolve_enum(*e).get_field_by_name(name)?.value,
+        }),
+        (ValueType::Enum(e), Constant::Integer(v)) => Value::Enum(EnumValue {
+            enum_ref: *e,
+            value: i32::try_from(*v).ok()?.into(),
+        }),
+        _ => return None,
+    })
+}
+
+/// Converts the `default_value` of a field descriptor into an option constant.
+///
+/// The descriptors store the values as text: strings as is, bytes with C-style escapes and
+/// enums by the value name.
+fn default_constant(value: &str, field_type: &FieldTypeBuilder) -> Constant
+{
+    let float = |v: &str| match v {
+        "inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        "nan" => f64::NAN,
+        v => v.parse().unwrap_or_default(),
+    };
+
+    match field_type {
+        FieldTypeBuilder::Builtin(ValueType::String) => {
+            Constant::String(Bytes::from(value.to_string()))
+        }
+        FieldTypeBuilder::Builtin(ValueType::Bytes) => Constant::String(unescape(value)),
+        FieldTypeBuilder::Builtin(ValueType::Bool) => Constant::Bool(value == "true"),
+        FieldTypeBuilder::Builtin(ValueType::Double)
+        | FieldTypeBuilder::Builtin(ValueType::Float) => Constant::Float(float(value)),
+        FieldTypeBuilder::Builtin(_) => match value.parse::<i64>() {
+            Ok(v) => Constant::Integer(v),
+            Err(_) => Constant::Integer(value.parse::<u64>().unwrap_or_default() as i64),
+        },
+        FieldTypeBuilder::Unknown(..) | FieldTypeBuilder::Group(..) => {
+            Constant::Ident(value.to_string())
+        }
+    }
+}
+
+/// Converts a `default` option into the `default_value` of a field descriptor.
+fn default_string(constant: &Constant, field_type: &ValueType) -> String
+{
+    match (constant, field_type) {
+        (Constant::String(v), ValueType::Bytes) => escape(v),
+        (Constant::String(v), _) => String::from_utf8_lossy(v).into_owned(),
+        (Constant::Integer(v), ValueType::UInt64) | (Constant::Integer(v), ValueType::Fixed64) => {
+            (*v as u64).to_string()
+        }
+        (Constant::Integer(v), _) => v.to_string(),
+        (Constant::Float(v), _) if v.is_nan() => "nan".to_string(),
+        (Constant::Float(v), _) if v.is_infinite() => match *v > 0.0 {
+            true => "inf".to_string(),
+            false => "-inf".to_string(),
+        },
+        (Constant::Float(v), _) => v.to_string(),
+        (Constant::Bool(v), _) => v.to_string(),
+        (Constant::Ident(v), _) => v.clone(),
+        (Constant::Aggregate(fields), _) => aggregate_text(fields),
+    }
+}
+
+/// Formats the fields of an aggregate option value in the text format.
+fn aggregate_text(fields: &[(String, Constant)]) -> String
+{
+    fields
+        .iter()
+        .map(|(name, value)| match value {
+            Constant::String(v) => format!("{}: \"{}\"", name, escape(v)),
+            Constant::Aggregate(inner) => format!("{} {{ {} }}", name, aggregate_text(inner)),
+            v => format!("{}: {}", name, default_string(v, &ValueType::Double)),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reverses `escape`. Hexadecimal escapes are accepted as well.
+fn unescape(value: &str) -> Bytes
+{
+    let mut output = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes().peekable();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            output.push(b);
+            continue;
+        }
+
+        let escaped = match bytes.next() {
+            Some(e) => e,
+            None => break,
+        };
+        let simple = match escaped {
+            b'0'..=b'7' | b'x' | b'X' => None,
+            b'n' => Some(b'\n'),
+            b'r' => Some(b'\r'),
+            b't' => Some(b'\t'),
+            b'a' => Some(0x07),
+            b'b' => Some(0x08),
+            b'f' => Some(0x0c),
+            b'v' => Some(0x0b),
+            other => Some(other),
+        };
+        if let Some(b) = simple {
+            output.push(b);
+            continue;
+        }
+
+        // Octal escapes have up to three digits and hexadecimal escapes up to two.
+        let (radix, mut value) = match escaped {
+            b'x' | b'X' => (16, 0),
+            digit => (8, (digit - b'0') as u32),
+        };
+        for _ in 0..2 {
+            match bytes.peek().and_then(|d| (*d as char).to_digit(radix)) {
+                Some(d) => {
+                    value = value * radix + d;
+                    bytes.next();
+                }
+                None => break,
+            }
+        }
+        output.push(value as u8);
+    }
+    Bytes::from(output)
+}
+
+fn write_uninterpreted_option(option: &ProtoOption, msg: &mut DescriptorBuilder)
+{
+    // Split the name into parts: `(foo.bar).baz` -> `(foo.bar)`, `baz`.
+    let mut parts = vec![];
+    let mut rest = option.name.as_str();
+    while !rest.is_empty() {
+        let (part, is_extension, remainder) = match rest.strip_prefix('(') {
+            Some(ext) => match ext.find(')') {
+                Some(end) => (&ext[..end], true, &ext[end + 1..]),
+                None => (ext, true, ""),
+            },
+            None => match rest.find('.') {
+                Some(end) => (&rest[..end], false, &rest[end..]),
+                None => (rest, false, ""),
+            },
+        };
+        parts.push((part, is_extension));
+        rest = remainder.strip_prefix('.').unwrap_or(remainder);
+    }
+
+    for (part, is_extension) in parts {
+        let mut name = msg.child("name");
+        name.string("name_part", part);
+        name.set("is_extension", Value::Bool(is_extension));
+        msg.message("name", name);
+    }
+
+    match &option.value {
+        Constant::Ident(v) => msg.string("identifier_value", v),
+        Constant::Bool(v) => msg.string("identifier_value", &v.to_string()),
+        Constant::Integer(v) if *v >= 0 => msg.set("positive_int_value", Value::UInt64(*v as u64)),
+        Constant::Integer(v) => msg.set("negative_int_value", Value::Int64(*v)),
+        Constant::Float(v) => msg.set("double_value", Value::Double(*v)),
+        Constant::String(v) => msg.set("string_value", Value::Bytes(v.clone())),
+        Constant::Aggregate(fields) => msg.string("aggregate_value", &aggregate_text(fields)),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn bundled_descriptor()
+    {
+        let ctx = descriptor_context();
+        let field = ctx
+            .get_message("google.protobuf.FileDescriptorProto")
+            .unwrap()
+            .get_field_by_name("public_dependency")
+            .unwrap();
+        assert_eq!(field.multiplicity, Multiplicity::Repeated);
+
+        // The context is parsed once.
+        assert!(std::ptr::eq(ctx, descriptor_context()));
+    }
+}
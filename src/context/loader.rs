@@ -0,0 +1,189 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+use std::path::PathBuf;
+
+use super::builder::*;
+use super::*;
+
+/// Import path of the bundled `descriptor.proto`.
+const DESCRIPTOR_PATH: &str = "google/protobuf/descriptor.proto";
+
+/// Source of proto files for resolving `import` statements.
+///
+/// See [`Context::parse_with_loader`].
+pub trait FileLoader
+{
+    /// Loads the contents of a file.
+    ///
+    /// The `path` is the path as written in the `import` statement or given as a root file.
+    /// Missing files should be reported with an error of kind [`io::ErrorKind::NotFound`].
+    fn load(&mut self, path: &str) -> io::Result<String>;
+}
+
+/// Loads files from the file system relative to a list of include paths.
+///
+/// The include paths are searched in order, similar to the `--proto_path` arguments of `protoc`.
+#[derive(Debug, Clone, Default)]
+pub struct FileSystemLoader
+{
+    include_paths: Vec<PathBuf>,
+}
+
+impl FileSystemLoader
+{
+    /// Create a new loader that searches the given include paths.
+    pub fn new<I, P>(include_paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            include_paths: include_paths.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl FileLoader for FileSystemLoader
+{
+    fn load(&mut self, path: &str) -> io::Result<String>
+    {
+        for include in &self.include_paths {
+            match std::fs::read_to_string(include.join(path)) {
+                Ok(content) => return Ok(content),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("'{}' was not found in the include paths", path),
+        ))
+    }
+}
+
+/// In-memory loader that maps file paths to file contents.
+impl<K, V> FileLoader for HashMap<K, V>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+{
+    fn load(&mut self, path: &str) -> io::Result<String>
+    {
+        self.get(path)
+            .map(|content| content.as_ref().to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+}
+
+impl Context
+{
+    /// Parses the root files and the files they import and creates a decoding context.
+    ///
+    /// The files are loaded through the `loader` by their paths. `import`, `import public` and
+    /// `import weak` statements are followed recursively. Each file may only refer to the types
+    /// defined in the file itself, in the files it imports and in the files those files import
+    /// with `import public`.
+    ///
+    /// Missing files and import cycles are reported as errors. The only exception is
+    /// `google/protobuf/descriptor.proto`, which falls back to the bundled [`DESCRIPTOR_PROTO`] if
+    /// the loader does not find it.
+    pub fn parse_with_loader<T, S, L>(root_files: T, loader: &mut L) -> Result<Self, ParseError>
+    where
+        T: IntoIterator<Item = S>,
+        S: AsRef<str>,
+        L: FileLoader + ?Sized,
+    {
+        let mut state = LoadState {
+            loader,
+            packages: vec![],
+            paths: vec![],
+            loaded: HashMap::new(),
+            stack: vec![],
+        };
+
+        let result = root_files
+            .into_iter()
+            .try_for_each(|root| state.load(root.as_ref(), Span::default()).map(|_| ()))
+            .and_then(|()| {
+                let builder = ContextBuilder {
+                    packages: state.packages.drain(..).map(Option::unwrap).collect(),
+                    imports_resolved: true,
+                };
+                builder.build()
+            });
+
+        result.map_err(|e| e.with_paths(&state.paths))
+    }
+}
+
+struct LoadState<'a, L: ?Sized>
+{
+    loader: &'a mut L,
+
+    /// Packages by file index. Files are `None` while their imports are being loaded.
+    packages: Vec<Option<PackageBuilder>>,
+
+    /// Paths by file index.
+    paths: Vec<String>,
+    loaded: HashMap<String, usize>,
+
+    /// Files currently being loaded for detecting import cycles.
+    stack: Vec<usize>,
+}
+
+impl<L: FileLoader + ?Sized> LoadState<'_, L>
+{
+    /// Loads a file and its imports recursively. Returns the package index of the file.
+    fn load(&mut self, path: &str, span: Span) -> Result<usize, ParseError>
+    {
+        if let Some(&idx) = self.loaded.get(path) {
+            if self.stack.contains(&idx) {
+                return Err(ParseError::ImportCycle {
+                    path: path.to_string(),
+                    span: span.into(),
+                });
+            }
+            return Ok(idx);
+        }
+
+        let content = match self.loader.load(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound && path == DESCRIPTOR_PATH => {
+                Ok(DESCRIPTOR_PROTO.to_string())
+            }
+            result => result,
+        };
+        let content = content.map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ParseError::FileNotFound {
+                path: path.to_string(),
+                span: span.into(),
+            },
+            _ => ParseError::FileLoadError {
+                path: path.to_string(),
+                source: e,
+                span: span.into(),
+            },
+        })?;
+
+        let idx = self.paths.len();
+        self.paths.push(path.to_string());
+        let mut package = PackageBuilder::parse_str(&content, FileId(idx))?;
+        package.path = Some(path.to_string());
+
+        self.packages.push(None);
+        self.loaded.insert(path.to_string(), idx);
+        self.stack.push(idx);
+
+        for import in &package.imports {
+            let resolved = self.load(&import.path, import.span)?;
+            package.resolved_imports.push(resolved);
+        }
+
+        self.stack.pop();
+        self.packages[idx] = Some(package);
+
+        Ok(idx)
+    }
+}
@@ -1,14 +1,24 @@
 //! Decoding context built from the proto-files.
 
 use bytes::Bytes;
-use snafu::{ResultExt, Snafu};
+use snafu::Snafu;
 use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
 
 mod api;
 mod builder;
+mod descriptor;
+mod loader;
 mod modify_api;
+mod options;
 mod parse;
 
+pub use descriptor::DESCRIPTOR_PROTO;
+pub use loader::{FileLoader, FileSystemLoader};
+
+#[cfg(feature = "json")]
+pub(crate) use builder::default_json_name;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct InternalRef(usize);
 
@@ -32,6 +42,118 @@ pub struct ServiceRef(InternalRef);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OneofRef(InternalRef);
 
+/// A reference to an extension. Can be resolved to `Extension` through a `Context`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtensionRef(InternalRef);
+
+/// Source file identifier.
+///
+/// Files are identified by their index in the list of files given to the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub usize);
+
+/// Line and column within a source file. Both are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Position
+{
+    /// Line number.
+    pub line: usize,
+
+    /// Column number.
+    pub column: usize,
+}
+
+/// Location of an item in the source files.
+///
+/// Spans are metadata about where an item was defined and the context items leave them out when
+/// compared. This allows comparing a context parsed from files with one created by hand or parsed
+/// from differently formatted files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span
+{
+    /// File that contains the item.
+    pub file: FileId,
+
+    /// Start of the item.
+    pub start: Position,
+
+    /// End of the item.
+    pub end: Position,
+}
+
+impl std::fmt::Display for Span
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        write!(
+            f,
+            "file {}, line {}, column {}",
+            self.file.0, self.start.line, self.start.column
+        )
+    }
+}
+
+/// Implements `PartialEq` for a context item comparing all the fields except the `span`.
+macro_rules! eq_without_span {
+    ($item:ident { $($field:ident),* }) => {
+        impl PartialEq for $item
+        {
+            fn eq(&self, other: &Self) -> bool
+            {
+                // Fails to compile if a field is missing from the list.
+                let $item { $($field,)* span: _ } = self;
+                $(*$field == other.$field)&&*
+            }
+        }
+    };
+}
+
+/// Location of an error in the source files.
+///
+/// Dereferences to the [`Span`] of the error.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ErrorSpan
+{
+    /// Location of the error.
+    pub span: Span,
+
+    /// Path of the file. Only available for files loaded through [`Context::parse_with_loader`].
+    pub path: Option<String>,
+}
+
+impl From<Span> for ErrorSpan
+{
+    fn from(span: Span) -> Self
+    {
+        Self { span, path: None }
+    }
+}
+
+impl std::ops::Deref for ErrorSpan
+{
+    type Target = Span;
+
+    fn deref(&self) -> &Span
+    {
+        &self.span
+    }
+}
+
+impl std::fmt::Display for ErrorSpan
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match &self.path {
+            Some(path) => write!(
+                f,
+                "{}, line {}, column {}",
+                path, self.start.line, self.start.column
+            ),
+            None => self.span.fmt(f),
+        }
+    }
+}
+
 /// Protofish error type.
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
@@ -39,38 +161,48 @@ pub struct OneofRef(InternalRef);
 pub enum ParseError
 {
     /// Syntax error in the input files.
-    #[snafu(display("Parsing error: {}", source))]
+    #[snafu(display("Parsing error: {} ({})", source, span))]
     SyntaxError
     {
         /// Source error.
         source: Box<dyn std::error::Error + Send + Sync>,
+
+        /// Location of the error.
+        span: ErrorSpan,
     },
 
     /// Duplicate type.
-    #[snafu(display("Duplicate type: {}", name))]
+    #[snafu(display("Duplicate type: {} ({})", name, span))]
     DuplicateType
     {
         /// Type.
         name: String,
+
+        /// Location of the duplicate definition.
+        span: ErrorSpan,
     },
 
     /// Unknown type reference.
-    #[snafu(display("Unknown type '{}' in '{}'", name, context))]
+    #[snafu(display("Unknown type '{}' in '{}' ({})", name, context, span))]
     TypeNotFound
     {
         /// Type name.
         name: String,
         /// Type that referred to the unknown type.
         context: String,
+
+        /// Location of the type reference.
+        span: ErrorSpan,
     },
 
     /// Wrong kind of type used in a specific context.
     #[snafu(display(
-        "Invalid type '{}' ({:?}) for {}, expected {:?}",
+        "Invalid type '{}' ({:?}) for {}, expected {:?} ({})",
         type_name,
         actual,
         context,
-        expected
+        expected,
+        span
     ))]
     InvalidTypeKind
     {
@@ -85,9 +217,93 @@ pub enum ParseError
 
         /// Actual item type.
         actual: ItemType,
+
+        /// Location of the type reference.
+        span: ErrorSpan,
+    },
+
+    /// Imported file could not be found.
+    #[snafu(display("File not found: '{}' ({})", path, span))]
+    FileNotFound
+    {
+        /// Path of the file.
+        path: String,
+
+        /// Location of the `import` statement.
+        span: ErrorSpan,
+    },
+
+    /// Imported file could not be loaded.
+    #[snafu(display("Failed to load '{}': {} ({})", path, source, span))]
+    FileLoadError
+    {
+        /// Path of the file.
+        path: String,
+
+        /// Source error.
+        source: std::io::Error,
+
+        /// Location of the `import` statement.
+        span: ErrorSpan,
+    },
+
+    /// File imports itself either directly or through other files.
+    #[snafu(display("Import cycle: '{}' imports itself ({})", path, span))]
+    ImportCycle
+    {
+        /// Path of the file that was imported recursively.
+        path: String,
+
+        /// Location of the `import` statement that closes the cycle.
+        span: ErrorSpan,
+    },
+
+    /// Extension with the same name or the same field number for the same message already
+    /// exists.
+    #[snafu(display("Duplicate extension: {} ({})", name, span))]
+    DuplicateExtension
+    {
+        /// Full name of the extension.
+        name: String,
+
+        /// Location of the duplicate definition.
+        span: ErrorSpan,
+    },
+
+    /// Serialized descriptor was not valid.
+    #[snafu(display("Invalid descriptor: {}", reason))]
+    InvalidDescriptor
+    {
+        /// Description of the problem.
+        reason: String,
     },
 }
 
+impl ParseError
+{
+    /// Fills the paths of the error spans from the `paths` of the files by their index.
+    ///
+    /// Spans without a location, such as the ones of the root files, are left as they are.
+    pub(crate) fn with_paths(mut self, paths: &[String]) -> Self
+    {
+        let span = match &mut self {
+            ParseError::SyntaxError { span, .. }
+            | ParseError::DuplicateType { span, .. }
+            | ParseError::TypeNotFound { span, .. }
+            | ParseError::InvalidTypeKind { span, .. }
+            | ParseError::FileNotFound { span, .. }
+            | ParseError::FileLoadError { span, .. }
+            | ParseError::ImportCycle { span, .. }
+            | ParseError::DuplicateExtension { span, .. } => span,
+            ParseError::InvalidDescriptor { .. } => return self,
+        };
+        if span.span != Span::default() {
+            span.path = paths.get(span.file.0).cloned();
+        }
+        self
+    }
+}
+
 /// Error modifying the context.
 #[derive(Debug, Snafu)]
 #[non_exhaustive]
@@ -99,6 +315,13 @@ pub enum InsertError
         /// The previous type that conflicts with the new one.
         original: TypeRef,
     },
+
+    /// An extension conflicts with an existing extension by name or by field number.
+    ExtensionExists
+    {
+        /// The previous extension that conflicts with the new one.
+        original: ExtensionRef,
+    },
 }
 
 /// Error modifying a type.
@@ -169,6 +392,8 @@ pub struct Context
     types_by_name: HashMap<String, usize>,
     services: Vec<Service>,
     services_by_name: HashMap<String, usize>,
+    extensions: Vec<Extension>,
+    extensions_by_name: HashMap<String, usize>,
 }
 
 /// Package details.
@@ -181,6 +406,18 @@ pub struct Package
     /// Package self reference.
     self_ref: PackageRef,
 
+    /// Path of the file that defined the package, if the file was loaded by a path.
+    path: Option<String>,
+
+    /// Files imported by the package file.
+    imports: Vec<Import>,
+
+    /// Syntax of the package file.
+    syntax: Syntax,
+
+    /// File options.
+    options: Vec<ProtoOption>,
+
     /// Top level types.
     types: Vec<TypeRef>,
 
@@ -188,6 +425,154 @@ pub struct Package
     services: Vec<usize>,
 }
 
+/// Syntax of a proto file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Syntax
+{
+    /// `syntax = "proto2"`, or no syntax statement.
+    Proto2,
+
+    /// `syntax = "proto3"`.
+    #[default]
+    Proto3,
+
+    /// `edition = "2023"`.
+    Edition2023,
+
+    /// `edition = "2024"`.
+    Edition2024,
+}
+
+/// Resolved Protobuf Editions features.
+///
+/// Features start from the defaults of the file syntax or edition and are overridden by the
+/// `features.*` options of the enclosing file, messages, oneofs and finally the item itself.
+/// Files using the `syntax` statement behave as if they had the features of the matching
+/// edition.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub struct Features
+{
+    /// Presence tracking of singular fields.
+    pub field_presence: FieldPresence,
+
+    /// Whether enums accept values that are not defined in them.
+    pub enum_type: EnumType,
+
+    /// Encoding of repeated scalar fields.
+    pub repeated_field_encoding: RepeatedFieldEncoding,
+
+    /// Validation of string fields.
+    pub utf8_validation: Utf8Validation,
+
+    /// Encoding of message fields.
+    pub message_encoding: MessageEncoding,
+
+    /// Support for the JSON mapping.
+    pub json_format: JsonFormat,
+}
+
+/// `features.field_presence`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FieldPresence
+{
+    /// `EXPLICIT`: singular fields track presence like proto2 `optional` fields.
+    Explicit,
+
+    /// `IMPLICIT`: singular fields do not track presence like proto3 fields without a label.
+    Implicit,
+
+    /// `LEGACY_REQUIRED`: singular fields are required like proto2 `required` fields.
+    LegacyRequired,
+}
+
+/// `features.enum_type`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EnumType
+{
+    /// `OPEN`: unknown values are stored in the enum fields like in proto3.
+    Open,
+
+    /// `CLOSED`: unknown values are treated as unknown fields like in proto2.
+    Closed,
+}
+
+/// `features.repeated_field_encoding`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RepeatedFieldEncoding
+{
+    /// `PACKED`: repeated scalar fields are packed.
+    Packed,
+
+    /// `EXPANDED`: repeated scalar fields are encoded one value at a time.
+    Expanded,
+}
+
+/// `features.utf8_validation`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Utf8Validation
+{
+    /// `VERIFY`: string fields must contain valid UTF-8.
+    Verify,
+
+    /// `NONE`: string fields are not validated.
+    None,
+}
+
+/// `features.message_encoding`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MessageEncoding
+{
+    /// `LENGTH_PREFIXED`: message fields are length-delimited.
+    LengthPrefixed,
+
+    /// `DELIMITED`: message fields are encoded as groups.
+    Delimited,
+}
+
+/// `features.json_format`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum JsonFormat
+{
+    /// `ALLOW`: the types must be compatible with the JSON mapping.
+    Allow,
+
+    /// `LEGACY_BEST_EFFORT`: the JSON mapping is best effort like in proto2.
+    LegacyBestEffort,
+}
+
+/// A single `import` statement.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Import
+{
+    /// Path of the imported file as written in the `import` statement.
+    pub path: String,
+
+    /// Kind of the import.
+    pub kind: ImportKind,
+
+    /// Location of the `import` statement.
+    pub span: Span,
+}
+
+eq_without_span!(Import { path, kind });
+
+/// Import kind.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ImportKind
+{
+    /// Regular `import`.
+    Default,
+
+    /// `import public`. Types of the imported file are visible to the files that import the
+    /// current file.
+    Public,
+
+    /// `import weak`.
+    Weak,
+}
+
 /// Message or enum type.
 #[derive(Debug, PartialEq)]
 pub enum TypeInfo
@@ -200,7 +585,7 @@ pub enum TypeInfo
 }
 
 /// Message details
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 #[non_exhaustive]
 pub struct MessageInfo
 {
@@ -222,11 +607,45 @@ pub struct MessageInfo
     /// References to the inner types defined within this message.
     pub inner_types: Vec<TypeRef>,
 
+    /// Message options.
+    pub options: Vec<ProtoOption>,
+
+    /// Field numbers reserved for extensions with the proto2 `extensions` statement.
+    pub extension_ranges: Vec<RangeInclusive<u64>>,
+
+    /// Resolved features of the message.
+    pub features: Features,
+
+    /// Location of the message definition.
+    pub span: Span,
+
     // Using BTreeMap here to ensure ordering.
     fields: BTreeMap<u64, MessageField>,
     fields_by_name: BTreeMap<String, u64>,
+
+    /// Field numbers in the order the fields were declared.
+    field_order: Vec<u64>,
+
+    /// Extensions of this message by field number.
+    extensions: BTreeMap<u64, ExtensionRef>,
 }
 
+eq_without_span!(MessageInfo {
+    name,
+    full_name,
+    parent,
+    self_ref,
+    oneofs,
+    inner_types,
+    options,
+    extension_ranges,
+    features,
+    fields,
+    fields_by_name,
+    field_order,
+    extensions
+});
+
 /// Reference to a type parent.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TypeParent
@@ -239,7 +658,7 @@ pub enum TypeParent
 }
 
 /// Enum details
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 #[non_exhaustive]
 pub struct EnumInfo
 {
@@ -255,18 +674,46 @@ pub struct EnumInfo
     /// `EnumRef` that references this enum.
     pub self_ref: EnumRef,
 
-    fields_by_value: BTreeMap<i64, EnumField>,
-    fields_by_name: BTreeMap<String, i64>,
+    /// Enum options.
+    pub options: Vec<ProtoOption>,
+
+    /// Resolved features of the enum.
+    pub features: Features,
+
+    /// Location of the enum definition.
+    pub span: Span,
+
+    /// Fields in the order they were declared, including the aliases.
+    fields: Vec<EnumField>,
+    fields_by_value: BTreeMap<i64, usize>,
+    fields_by_name: BTreeMap<String, usize>,
 }
 
+eq_without_span!(EnumInfo {
+    name,
+    full_name,
+    parent,
+    self_ref,
+    options,
+    features,
+    fields,
+    fields_by_value,
+    fields_by_name
+});
+
 /// Message field details.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 #[non_exhaustive]
 pub struct MessageField
 {
     /// Field name.
     pub name: String,
 
+    /// Field name used in the JSON mapping.
+    ///
+    /// Either the `json_name` option of the field or the field name converted to lowerCamelCase.
+    pub json_name: String,
+
     /// Field number.
     pub number: u64,
 
@@ -281,6 +728,49 @@ pub struct MessageField
 
     /// Index to the ´oneof` structure in the parent type if this field is part of a `oneof`.
     pub oneof: Option<OneofRef>,
+
+    /// Resolved features of the field.
+    ///
+    /// The features have already been applied to the `multiplicity` and `field_type` of the
+    /// field: message fields with `DELIMITED` encoding are [groups](ValueType::Group).
+    pub features: Features,
+
+    /// Location of the field definition.
+    pub span: Span,
+}
+
+eq_without_span!(MessageField {
+    name,
+    json_name,
+    number,
+    field_type,
+    multiplicity,
+    options,
+    oneof,
+    features
+});
+
+/// Extension field details.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Extension
+{
+    /// Full extension name, including the package and parent type names.
+    pub full_name: String,
+
+    /// Package or message that contains the `extend` block.
+    pub parent: TypeParent,
+
+    /// `ExtensionRef` that references this extension.
+    pub self_ref: ExtensionRef,
+
+    /// The message that is extended.
+    pub extendee: MessageRef,
+
+    /// The extension field.
+    ///
+    /// The field number is a field number of the extended message.
+    pub field: MessageField,
 }
 
 /// Defines the multiplicity of the field values.
@@ -298,8 +788,20 @@ pub enum Multiplicity
 
     /// Field is optional.
     Optional,
+
+    /// Field is a proto2 `required` field.
+    Required,
+
+    /// Field is a `map<K, V>` field.
+    ///
+    /// The field type refers to the synthesized map entry message that contains the `key` and
+    /// `value` fields. On the wire the field is repeated like any other message field.
+    Map,
 }
 
+/// The largest valid field number.
+pub(crate) const MAX_FIELD_NUMBER: u64 = (1 << 29) - 1;
+
 /// Message `oneof` details.
 #[derive(Debug, PartialEq)]
 #[non_exhaustive]
@@ -385,12 +887,15 @@ pub enum ValueType
     /// A message type.
     Message(MessageRef),
 
+    /// A message type encoded as a group.
+    Group(MessageRef),
+
     /// An enum type.
     Enum(EnumRef),
 }
 
 /// Service details
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 #[non_exhaustive]
 pub struct Service
 {
@@ -412,11 +917,24 @@ pub struct Service
     /// Options.
     pub options: Vec<ProtoOption>,
 
+    /// Location of the service definition.
+    pub span: Span,
+
     rpcs_by_name: HashMap<String, usize>,
 }
 
+eq_without_span!(Service {
+    name,
+    full_name,
+    self_ref,
+    parent,
+    rpcs,
+    options,
+    rpcs_by_name
+});
+
 /// Rpc operation
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 #[non_exhaustive]
 pub struct Rpc
 {
@@ -431,8 +949,18 @@ pub struct Rpc
 
     /// Options.
     pub options: Vec<ProtoOption>,
+
+    /// Location of the rpc definition.
+    pub span: Span,
 }
 
+eq_without_span!(Rpc {
+    name,
+    input,
+    output,
+    options
+});
+
 /// Rpc operation input or output details.
 #[derive(Debug, PartialEq)]
 #[non_exhaustive]
@@ -477,6 +1005,13 @@ pub enum Constant
 
     /// A boolean constant.
     Bool(bool),
+
+    /// A message literal `{ name: "foo" items { id: 1 } items { id: 2 } }`.
+    ///
+    /// The fields are stored by name in the order they were written. Repeated fields appear once
+    /// for each value, including the values written as lists. Extension names are kept in
+    /// brackets: `[foo.bar]`.
+    Aggregate(Vec<(String, Constant)>),
 }
 
 #[cfg(test)]
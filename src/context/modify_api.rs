@@ -41,19 +41,7 @@ impl Context
         let internal_ref = InternalRef(self.types.len());
         let parent = ty.parent();
 
-        let full_name = match parent {
-            TypeParent::Package(p) => {
-                let package = &self.packages[p.0 .0];
-                match &package.name {
-                    Some(package_name) => format!("{}.{}", package_name, ty.name()),
-                    None => ty.name().to_string(),
-                }
-            }
-            TypeParent::Message(m) => {
-                let msg = &self.types[m.0 .0];
-                format!("{}.{}", msg.full_name(), ty.name())
-            }
-        };
+        let full_name = self.scoped_name(parent, ty.name());
 
         match &mut ty {
             TypeInfo::Message(m) => m.full_name = full_name.clone(),
@@ -106,6 +94,59 @@ impl Context
 
         Ok(internal_ref)
     }
+
+    /// Insert a new extension to the context.
+    ///
+    /// The extension is registered to the extended message so its fields are decoded with the
+    /// extension type.
+    pub fn insert_extension(&mut self, mut ext: Extension) -> Result<ExtensionRef, InsertError>
+    {
+        let ext_ref = ExtensionRef(InternalRef(self.extensions.len()));
+        ext.full_name = self.scoped_name(ext.parent, &ext.field.name);
+
+        if let Some(original) = self.extensions_by_name.get(&ext.full_name) {
+            return Err(InsertError::ExtensionExists {
+                original: ExtensionRef(InternalRef(*original)),
+            });
+        }
+
+        let extendee = match self.types.get_mut(ext.extendee.0 .0) {
+            Some(TypeInfo::Message(msg)) => msg,
+            _ => panic!("Extendee did not exist in this context"),
+        };
+        if let Some(original) = extendee.extensions.get(&ext.field.number) {
+            return Err(InsertError::ExtensionExists {
+                original: *original,
+            });
+        }
+
+        // From here on, we're modifying the context.
+
+        extendee.extensions.insert(ext.field.number, ext_ref);
+        ext.self_ref = ext_ref;
+        self.extensions_by_name
+            .insert(ext.full_name.clone(), ext_ref.0 .0);
+        self.extensions.push(ext);
+
+        Ok(ext_ref)
+    }
+
+    fn scoped_name(&self, parent: TypeParent, name: &str) -> String
+    {
+        match parent {
+            TypeParent::Package(p) => {
+                let package = &self.packages[p.0 .0];
+                match &package.name {
+                    Some(package_name) => format!("{}.{}", package_name, name),
+                    None => name.to_string(),
+                }
+            }
+            TypeParent::Message(m) => {
+                let msg = &self.types[m.0 .0];
+                format!("{}.{}", msg.full_name(), name)
+            }
+        }
+    }
 }
 
 impl Package
@@ -116,6 +157,10 @@ impl Package
         Self {
             name,
             self_ref: PackageRef(InternalRef(0)),
+            path: None,
+            imports: vec![],
+            syntax: Default::default(),
+            options: vec![],
             types: vec![],
             services: vec![],
         }
@@ -138,9 +183,15 @@ impl MessageInfo
             self_ref: MessageRef(InternalRef(0)),
             oneofs: vec![],
             inner_types: vec![],
+            options: vec![],
+            extension_ranges: vec![],
+            features: Features::default(),
+            span: Span::default(),
 
             fields: BTreeMap::new(),
             fields_by_name: BTreeMap::new(),
+            field_order: vec![],
+            extensions: BTreeMap::new(),
         }
     }
 
@@ -169,6 +220,7 @@ impl MessageInfo
 
         vacant_num.insert(field);
         vacant_name.insert(num);
+        self.field_order.push(num);
 
         Ok(())
     }
@@ -211,12 +263,33 @@ impl MessageField
     pub fn new(name: String, number: u64, field_type: ValueType) -> Self
     {
         Self {
+            json_name: builder::default_json_name(&name),
             name,
             number,
             field_type,
             multiplicity: Multiplicity::Single,
             options: vec![],
             oneof: None,
+            features: Features::default(),
+            span: Span::default(),
+        }
+    }
+}
+
+impl Extension
+{
+    /// Create a new extension of the `extendee` message.
+    ///
+    /// Before inserting the extension into a [`Context`] the `self_ref` and `full_name` fields
+    /// are not valid.
+    pub fn new(field: MessageField, extendee: MessageRef, parent: TypeParent) -> Self
+    {
+        Self {
+            full_name: String::new(),
+            parent,
+            self_ref: ExtensionRef(InternalRef(0)),
+            extendee,
+            field,
         }
     }
 }
@@ -245,6 +318,10 @@ impl EnumInfo
             parent,
             full_name: String::new(),
             self_ref: EnumRef(InternalRef(0)),
+            options: vec![],
+            features: Features::default(),
+            span: Span::default(),
+            fields: vec![],
             fields_by_value: BTreeMap::new(),
             fields_by_name: BTreeMap::new(),
         }
@@ -265,8 +342,9 @@ impl EnumInfo
             (Entry::Vacant(value), Entry::Vacant(name)) => (value, name),
         };
 
-        vacant_value.insert(field);
-        vacant_name.insert(value);
+        vacant_value.insert(self.fields.len());
+        vacant_name.insert(self.fields.len());
+        self.fields.push(field);
 
         Ok(())
     }
@@ -0,0 +1,264 @@
+use super::descriptor::constant_value;
+use super::*;
+use crate::decode::{FieldValue, MessageValue, PackedArray, Value};
+
+/// Implements the typed option accessors for an item with `options`.
+macro_rules! typed_options {
+    ($item:ty, $options_type:literal) => {
+        impl $item
+        {
+            #[doc = concat!("Resolves the options as a `", $options_type, "` message.")]
+            ///
+            /// The options are resolved against the options message and the custom options that
+            /// extend it, which requires `google/protobuf/descriptor.proto` to be part of the
+            /// context. Returns `None` if the options message is not in the context. Options that
+            /// do not match the declared types are left out.
+            pub fn typed_options(&self, ctx: &Context) -> Option<MessageValue>
+            {
+                resolve_options(&self.options, $options_type, ctx)
+            }
+
+            /// Gets the resolved value of an option.
+            ///
+            /// The `name` is either the name of a standard option, such as `deprecated`, or the
+            /// full name of a custom option, such as `validate.rules`. Custom options that are
+            /// set through their fields, such as `(validate.rules).string.min_len = 1`, are
+            /// merged into a single message value. The last value is returned for repeated
+            /// options.
+            pub fn option_value(&self, name: &str, ctx: &Context) -> Option<Value>
+            {
+                let options = self.typed_options(ctx)?;
+                let field = option_field(ctx.resolve_message(options.msg_ref), name, ctx)?;
+                options
+                    .fields
+                    .into_iter()
+                    .filter(|f| f.number == field.number)
+                    .last()
+                    .map(|f| f.value)
+            }
+        }
+    };
+}
+
+typed_options!(Package, "google.protobuf.FileOptions");
+typed_options!(MessageInfo, "google.protobuf.MessageOptions");
+typed_options!(MessageField, "google.protobuf.FieldOptions");
+typed_options!(Oneof, "google.protobuf.OneofOptions");
+typed_options!(EnumInfo, "google.protobuf.EnumOptions");
+typed_options!(EnumField, "google.protobuf.EnumValueOptions");
+typed_options!(Service, "google.protobuf.ServiceOptions");
+typed_options!(Rpc, "google.protobuf.MethodOptions");
+
+/// Part of an option name.
+enum NamePart<'a>
+{
+    /// Field of the options message.
+    Field(&'a str),
+
+    /// Extension in parentheses.
+    Extension(&'a str),
+}
+
+/// Splits an option name such as `(validate.rules).string.min_len` into parts.
+fn parse_name(mut name: &str) -> Option<Vec<NamePart<'_>>>
+{
+    let mut parts = vec![];
+    loop {
+        let rest = match name.strip_prefix('(') {
+            Some(ext) => {
+                let end = ext.find(')')?;
+                parts.push(NamePart::Extension(&ext[..end]));
+                &ext[end + 1..]
+            }
+            None => {
+                let end = name.find('.').unwrap_or(name.len());
+                parts.push(NamePart::Field(&name[..end]));
+                &name[end..]
+            }
+        };
+
+        if rest.is_empty() {
+            return Some(parts);
+        }
+        name = rest.strip_prefix('.')?;
+    }
+}
+
+fn resolve_options(
+    options: &[ProtoOption],
+    options_type: &str,
+    ctx: &Context,
+) -> Option<MessageValue>
+{
+    let info = ctx.get_message(options_type)?;
+    let mut msg = MessageValue {
+        msg_ref: info.self_ref,
+        fields: vec![],
+        garbage: None,
+    };
+
+    for option in options {
+        if let Some(name) = parse_name(&option.name) {
+            set_option(&mut msg, &name, &option.value, ctx);
+        }
+    }
+
+    Some(msg)
+}
+
+/// Resolves an option that is not a field of the options message, such as a custom option.
+///
+/// Returns an options message with only the given option set or `None` if the option does not
+/// match the options message. The options are resolved one at a time since `protoc` serializes
+/// each option separately without merging the values of the same fields.
+pub(crate) fn resolve_custom_option(
+    option: &ProtoOption,
+    options_type: &str,
+    ctx: &Context,
+) -> Option<MessageValue>
+{
+    let info = ctx.get_message(options_type)?;
+    let mut msg = MessageValue {
+        msg_ref: info.self_ref,
+        fields: vec![],
+        garbage: None,
+    };
+    set_option(&mut msg, &parse_name(&option.name)?, &option.value, ctx)?;
+    Some(msg)
+}
+
+/// Sets the option value in the message, merging it with the earlier values of the same fields.
+///
+/// Returns `None` if the option does not match the message.
+fn set_option(
+    msg: &mut MessageValue,
+    name: &[NamePart],
+    value: &Constant,
+    ctx: &Context,
+) -> Option<()>
+{
+    let info = ctx.resolve_message(msg.msg_ref);
+    let (first, rest) = name.split_first()?;
+    let field = match first {
+        NamePart::Field(name) => info.get_field_by_name(name)?,
+        NamePart::Extension(name) => extension_field(info, name, ctx)?,
+    };
+
+    if rest.is_empty() {
+        let mut value = match (value, &field.field_type) {
+            (Constant::Aggregate(fields), ValueType::Message(m)) => {
+                Value::Message(Box::new(aggregate_message(*m, fields, ctx)))
+            }
+            (Constant::Aggregate(fields), ValueType::Group(m)) => {
+                Value::Group(Box::new(aggregate_message(*m, fields, ctx)))
+            }
+            (value, field_type) => constant_value(value, field_type, ctx)?,
+        };
+
+        // Values of packed fields are collected into a single packed array.
+        if field.multiplicity == Multiplicity::RepeatedPacked {
+            let existing = msg
+                .fields
+                .iter_mut()
+                .rev()
+                .find(|f| f.number == field.number);
+            if let Some(Value::Packed(array)) = existing.map(|f| &mut f.value) {
+                let mut values = array.to_values();
+                values.push(value);
+                *array = PackedArray::from_values(&field.field_type, &values)?;
+                return Some(());
+            }
+            if let Some(array) = PackedArray::from_values(&field.field_type, &[value.clone()]) {
+                value = Value::Packed(array);
+            }
+        }
+
+        msg.fields.push(FieldValue {
+            number: field.number,
+            value,
+        });
+        return Some(());
+    }
+
+    let msg_ref = match field.field_type {
+        ValueType::Message(m) | ValueType::Group(m) => m,
+        _ => return None,
+    };
+
+    let existing = msg
+        .fields
+        .iter_mut()
+        .rev()
+        .find(|f| f.number == field.number);
+    if let Some(Value::Message(inner)) | Some(Value::Group(inner)) = existing.map(|f| &mut f.value)
+    {
+        return set_option(inner, rest, value, ctx);
+    }
+
+    let mut inner = MessageValue {
+        msg_ref,
+        fields: vec![],
+        garbage: None,
+    };
+    set_option(&mut inner, rest, value, ctx)?;
+    msg.fields.push(FieldValue {
+        number: field.number,
+        value: match field.field_type {
+            ValueType::Group(..) => Value::Group(Box::new(inner)),
+            _ => Value::Message(Box::new(inner)),
+        },
+    });
+    Some(())
+}
+
+/// Builds a message from an aggregate value. Fields that do not match the message are left out.
+fn aggregate_message(
+    msg_ref: MessageRef,
+    fields: &[(String, Constant)],
+    ctx: &Context,
+) -> MessageValue
+{
+    let mut msg = MessageValue {
+        msg_ref,
+        fields: vec![],
+        garbage: None,
+    };
+
+    for (name, value) in fields {
+        let part = match name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+            Some(ext) => NamePart::Extension(ext),
+            None => NamePart::Field(name),
+        };
+        set_option(&mut msg, &[part], value, ctx);
+    }
+
+    msg
+}
+
+/// Finds a field of the options message by the option name.
+fn option_field<'a>(info: &'a MessageInfo, name: &str, ctx: &'a Context)
+    -> Option<&'a MessageField>
+{
+    match name.strip_prefix('(').and_then(|n| n.strip_suffix(')')) {
+        Some(ext) => extension_field(info, ext, ctx),
+        None => info
+            .get_field_by_name(name)
+            .or_else(|| extension_field(info, name, ctx)),
+    }
+}
+
+/// Finds an extension of the options message by its name.
+///
+/// The name may be relative to the scope the option is used in, in which case the extension is
+/// found by the end of its full name.
+fn extension_field<'a>(info: &MessageInfo, name: &str, ctx: &'a Context)
+    -> Option<&'a MessageField>
+{
+    let name = name.strip_prefix('.').unwrap_or(name);
+    let suffix = format!(".{}", name);
+    let extensions = || info.iter_extensions().map(|e| ctx.resolve_extension(e));
+    extensions()
+        .find(|e| e.full_name == name)
+        .or_else(|| extensions().find(|e| e.full_name.ends_with(&suffix)))
+        .map(|e| &e.field)
+}
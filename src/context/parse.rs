@@ -1,9 +1,12 @@
 use bytes::{BufMut, Bytes, BytesMut};
 use pest::{
+    error::LineColLocation,
     iterators::{Pair, Pairs},
     Parser,
 };
 
+use std::ops::RangeInclusive;
+
 use super::builder::*;
 use super::*;
 
@@ -22,36 +25,113 @@ impl Context
         let builder = ContextBuilder {
             packages: files
                 .into_iter()
-                .map(|f| PackageBuilder::parse_str(f.as_ref()))
+                .enumerate()
+                .map(|(i, f)| PackageBuilder::parse_str(f.as_ref(), FileId(i)))
                 .collect::<Result<_, _>>()?,
+            imports_resolved: false,
         };
 
         builder.build()
     }
+
+    /// Parses the files and creates a decoding context, recovering from errors.
+    ///
+    /// Files that fail to parse are skipped entirely. Top level definitions that fail to build,
+    /// such as messages that refer to unknown types, are left out of the context. Definitions
+    /// that depend on skipped definitions will be skipped as well.
+    ///
+    /// Returns the context built from the remaining definitions together with all the errors
+    /// encountered.
+    pub fn parse_lenient<T, S>(files: T) -> (Self, Vec<ParseError>)
+    where
+        T: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut errors = vec![];
+        let builder = ContextBuilder {
+            packages: files
+                .into_iter()
+                .enumerate()
+                .filter_map(
+                    |(i, f)| match PackageBuilder::parse_str(f.as_ref(), FileId(i)) {
+                        Ok(p) => Some(p),
+                        Err(e) => {
+                            errors.push(e);
+                            None
+                        }
+                    },
+                )
+                .collect(),
+            imports_resolved: false,
+        };
+
+        let (context, build_errors) = builder.build_lenient();
+        errors.extend(build_errors);
+        (context, errors)
+    }
 }
 
 impl PackageBuilder
 {
-    pub fn parse_str(input: &str) -> Result<Self, ParseError>
+    pub fn parse_str(input: &str, file: FileId) -> Result<Self, ParseError>
     {
-        let pairs = ProtoParser::parse(Rule::proto, input)
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-            .context(SyntaxError {})?;
+        let pairs = ProtoParser::parse(Rule::proto, input).map_err(|e| {
+            let ((line, column), (end_line, end_column)) = match e.line_col {
+                LineColLocation::Pos(pos) => (pos, pos),
+                LineColLocation::Span(start, end) => (start, end),
+            };
+            ParseError::SyntaxError {
+                span: Span {
+                    file,
+                    start: Position { line, column },
+                    end: Position {
+                        line: end_line,
+                        column: end_column,
+                    },
+                }
+                .into(),
+                source: Box::new(e),
+            }
+        })?;
 
-        let mut current_package = PackageBuilder::default();
+        // Files without a syntax statement are proto2 files.
+        let mut current_package = PackageBuilder {
+            syntax: Syntax::Proto2,
+            ..Default::default()
+        };
         for pair in pairs {
             for inner in pair.into_inner() {
                 match inner.as_rule() {
-                    Rule::syntax => {}
+                    Rule::syntax => {
+                        current_package.syntax = match inner.into_inner().nth(1).unwrap().as_str() {
+                            "proto2" => Syntax::Proto2,
+                            _ => Syntax::Proto3,
+                        }
+                    }
+                    Rule::edition => {
+                        current_package.syntax = match inner.into_inner().nth(1).unwrap().as_str() {
+                            "2023" => Syntax::Edition2023,
+                            _ => Syntax::Edition2024,
+                        }
+                    }
                     Rule::topLevelDef => current_package
                         .types
-                        .push(ProtobufItemBuilder::parse(inner)),
-                    Rule::import => {}
+                        .push(ProtobufItemBuilder::parse(inner, file)),
+                    Rule::import => current_package.imports.push(Import::parse(inner, file)),
                     Rule::package => {
                         current_package.name =
                             Some(inner.into_inner().next().unwrap().as_str().to_string())
                     }
-                    Rule::option => {}
+                    Rule::option => current_package.options.push(ProtoOption::parse(inner)),
+                    Rule::extend => {
+                        let (extensions, groups) = ExtensionBuilder::parse(inner, file);
+                        current_package.extensions.extend(extensions);
+                        current_package.types.extend(
+                            groups.into_iter().map(|g| {
+                                ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(g))
+                            }),
+                        );
+                    }
                     Rule::EOI => {}
                     r => unreachable!("{:?}: {:?}", r, inner),
                 }
@@ -62,19 +142,46 @@ impl PackageBuilder
     }
 }
 
+impl Import
+{
+    fn parse(p: Pair<Rule>, file: FileId) -> Self
+    {
+        let span = Span::from_pair(file, &p);
+        let mut kind = ImportKind::Default;
+        let mut path = String::new();
+        for inner in p.into_inner() {
+            match inner.as_rule() {
+                Rule::importKind => {
+                    kind = match inner.as_str() {
+                        "public" => ImportKind::Public,
+                        "weak" => ImportKind::Weak,
+                        k => unreachable!("Unknown import kind {}", k),
+                    }
+                }
+                Rule::strLit => {
+                    path = String::from_utf8_lossy(&parse_string_literal(inner)).into_owned()
+                }
+                r => unreachable!("{:?}: {:?}", r, inner),
+            }
+        }
+
+        Import { path, kind, span }
+    }
+}
+
 impl ProtobufItemBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
         let pair = p.into_inner().next().unwrap();
         match pair.as_rule() {
-            Rule::message => {
-                ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(MessageBuilder::parse(pair)))
-            }
+            Rule::message => ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(
+                MessageBuilder::parse(pair, file),
+            )),
             Rule::enum_ => {
-                ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(EnumBuilder::parse(pair)))
+                ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(EnumBuilder::parse(pair, file)))
             }
-            Rule::service => ProtobufItemBuilder::Service(ServiceBuilder::parse(pair)),
+            Rule::service => ProtobufItemBuilder::Service(ServiceBuilder::parse(pair, file)),
             r => unreachable!("{:?}: {:?}", r, pair),
         }
     }
@@ -82,27 +189,64 @@ impl ProtobufItemBuilder
 
 impl MessageBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
+        let span = Span::from_pair(file, &p);
         let mut inner = p.into_inner();
         let name = inner.next().unwrap().as_str().to_string();
+        Self::parse_body(name, inner.next().unwrap(), span, file)
+    }
 
+    fn parse_body(name: String, body: Pair<Rule>, span: Span, file: FileId) -> Self
+    {
         let mut fields = vec![];
         let mut oneofs = vec![];
         let mut inner_types = vec![];
         let mut options = vec![];
-        let body = inner.next().unwrap();
+        let mut extension_ranges = vec![];
+        let mut extensions = vec![];
+        let mut field_order = vec![];
         for p in body.into_inner() {
             match p.as_rule() {
-                Rule::field => fields.push(FieldBuilder::parse(p)),
-                Rule::enum_ => inner_types.push(InnerTypeBuilder::Enum(EnumBuilder::parse(p))),
+                Rule::field => {
+                    let field = FieldBuilder::parse(p, file);
+                    field_order.push(field.number);
+                    fields.push(field);
+                }
+                Rule::group => {
+                    let (field, group) = FieldBuilder::parse_group(p, file);
+                    field_order.push(field.number);
+                    fields.push(field);
+                    inner_types.push(InnerTypeBuilder::Message(group));
+                }
+                Rule::enum_ => {
+                    inner_types.push(InnerTypeBuilder::Enum(EnumBuilder::parse(p, file)))
+                }
                 Rule::message => {
-                    inner_types.push(InnerTypeBuilder::Message(MessageBuilder::parse(p)))
+                    inner_types.push(InnerTypeBuilder::Message(MessageBuilder::parse(p, file)))
+                }
+                Rule::extend => {
+                    let (new_extensions, groups) = ExtensionBuilder::parse(p, file);
+                    extensions.extend(new_extensions);
+                    inner_types.extend(groups.into_iter().map(InnerTypeBuilder::Message));
                 }
                 Rule::option => options.push(ProtoOption::parse(p)),
-                Rule::oneof => oneofs.push(OneofBuilder::parse(p)),
-                Rule::mapField => unimplemented!("Maps are not supported"),
+                Rule::oneof => {
+                    let oneof = OneofBuilder::parse(p, file);
+                    field_order.extend(oneof.fields.iter().map(|f| f.number));
+                    oneofs.push(oneof);
+                }
+                Rule::mapField => {
+                    let (field, entry) = FieldBuilder::parse_map(p, file);
+                    field_order.push(field.number);
+                    fields.push(field);
+                    inner_types.push(InnerTypeBuilder::Message(entry));
+                }
                 Rule::reserved => {} // We don't need to care about reserved field numbers.
+                Rule::extensions => {
+                    let ranges = p.into_inner().next().unwrap();
+                    extension_ranges.extend(ranges.into_inner().map(parse_range));
+                }
                 Rule::emptyStatement => {}
                 r => unreachable!("{:?}: {:?}", r, p),
             }
@@ -114,14 +258,52 @@ impl MessageBuilder
             oneofs,
             inner_types,
             options,
+            extension_ranges,
+            extensions,
+            span,
+            field_order,
+        }
+    }
+}
+
+impl ExtensionBuilder
+{
+    /// Parses an `extend` block.
+    ///
+    /// Returns the extension fields and the message types of the extension groups.
+    pub fn parse(p: Pair<Rule>, file: FileId) -> (Vec<Self>, Vec<MessageBuilder>)
+    {
+        let mut inner = p.into_inner();
+        let extendee = inner.next().unwrap().as_str().to_string();
+
+        let mut extensions = vec![];
+        let mut groups = vec![];
+        for p in inner {
+            let field = match p.as_rule() {
+                Rule::field => FieldBuilder::parse(p, file),
+                Rule::group => {
+                    let (field, group) = FieldBuilder::parse_group(p, file);
+                    groups.push(group);
+                    field
+                }
+                Rule::emptyStatement => continue,
+                r => unreachable!("{:?}: {:?}", r, p),
+            };
+            extensions.push(ExtensionBuilder {
+                extendee: extendee.clone(),
+                field,
+            });
         }
+
+        (extensions, groups)
     }
 }
 
 impl EnumBuilder
 {
-    fn parse(p: Pair<Rule>) -> EnumBuilder
+    fn parse(p: Pair<Rule>, file: FileId) -> EnumBuilder
     {
+        let span = Span::from_pair(file, &p);
         let mut inner = p.into_inner();
         let name = inner.next().unwrap().as_str().to_string();
 
@@ -148,14 +330,16 @@ impl EnumBuilder
             name,
             fields,
             options,
+            span,
         }
     }
 }
 
 impl ServiceBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
+        let span = Span::from_pair(file, &p);
         let mut inner = p.into_inner();
         let name = inner.next().unwrap();
         let mut rpcs = vec![];
@@ -163,7 +347,7 @@ impl ServiceBuilder
         for p in inner {
             match p.as_rule() {
                 Rule::option => options.push(ProtoOption::parse(p)),
-                Rule::rpc => rpcs.push(RpcBuilder::parse(p)),
+                Rule::rpc => rpcs.push(RpcBuilder::parse(p, file)),
                 Rule::emptyStatement => {}
                 r => unreachable!("{:?}: {:?}", r, p),
             }
@@ -173,26 +357,18 @@ impl ServiceBuilder
             name: name.as_str().to_string(),
             rpcs,
             options,
+            span,
         }
     }
 }
 
 impl FieldBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
+        let span = Span::from_pair(file, &p);
         let mut inner = p.into_inner();
-        let multiplicity = match inner.next().unwrap().into_inner().next() {
-            Some(t) => {
-                let multiplicity = t.into_inner().next().unwrap().as_rule();
-                match multiplicity {
-                    Rule::optional => Multiplicity::Optional,
-                    Rule::repeated => Multiplicity::Repeated,
-                    r => unreachable!("{:?}: {:?}", r, multiplicity),
-                }
-            }
-            None => Multiplicity::Single,
-        };
+        let multiplicity = parse_multiplicity(inner.next().unwrap());
         let field_type = parse_field_type(inner.next().unwrap().as_str());
         let name = inner.next().unwrap().as_str().to_string();
         let number = parse_uint_literal(inner.next().unwrap());
@@ -208,11 +384,107 @@ impl FieldBuilder
             name,
             number,
             options,
+            span,
         }
     }
 
-    pub fn parse_oneof(p: Pair<Rule>) -> Self
+    /// Parses a proto2 `group` field.
+    ///
+    /// Returns the group field and the message type of the group. The field name is the group
+    /// name in lower case, similar to the one that `protoc` generates.
+    pub fn parse_group(p: Pair<Rule>, file: FileId) -> (Self, MessageBuilder)
+    {
+        let span = Span::from_pair(file, &p);
+        let mut inner = p.into_inner();
+        let multiplicity = parse_multiplicity(inner.next().unwrap());
+        let _keyword = inner.next().unwrap();
+        let group_name = inner.next().unwrap().as_str().to_string();
+        let number = parse_uint_literal(inner.next().unwrap());
+
+        let mut options = vec![];
+        let mut body = inner.next().unwrap();
+        if body.as_rule() == Rule::fieldOptions {
+            options = ProtoOption::parse_options(body.into_inner());
+            body = inner.next().unwrap();
+        }
+
+        let field = FieldBuilder {
+            multiplicity,
+            field_type: FieldTypeBuilder::Group(group_name.clone()),
+            name: group_name.to_lowercase(),
+            number,
+            options,
+            span,
+        };
+
+        (
+            field,
+            MessageBuilder::parse_body(group_name, body, span, file),
+        )
+    }
+
+    /// Parses a `map<K, V>` field.
+    ///
+    /// Returns the map field itself and the synthesized `<Name>Entry` message type that holds
+    /// the key and value fields, similar to the one that `protoc` generates.
+    pub fn parse_map(p: Pair<Rule>, file: FileId) -> (Self, MessageBuilder)
     {
+        let span = Span::from_pair(file, &p);
+        let mut inner = p.into_inner();
+        let key_type = parse_field_type(inner.next().unwrap().as_str());
+        let value_type = parse_field_type(inner.next().unwrap().as_str());
+        let name = inner.next().unwrap().as_str().to_string();
+        let number = parse_uint_literal(inner.next().unwrap());
+
+        let options = match inner.next() {
+            Some(p) => ProtoOption::parse_options(p.into_inner()),
+            None => vec![],
+        };
+
+        let entry = MessageBuilder {
+            name: map_entry_name(&name),
+            fields: vec![
+                FieldBuilder {
+                    multiplicity: Multiplicity::Single,
+                    field_type: key_type,
+                    name: "key".to_string(),
+                    number: 1,
+                    options: vec![],
+                    span,
+                },
+                FieldBuilder {
+                    multiplicity: Multiplicity::Single,
+                    field_type: value_type,
+                    name: "value".to_string(),
+                    number: 2,
+                    options: vec![],
+                    span,
+                },
+            ],
+            options: vec![ProtoOption {
+                name: "map_entry".to_string(),
+                value: Constant::Bool(true),
+            }],
+            span,
+            field_order: vec![1, 2],
+            ..Default::default()
+        };
+
+        let field = FieldBuilder {
+            multiplicity: Multiplicity::Map,
+            field_type: FieldTypeBuilder::Unknown(entry.name.clone()),
+            name,
+            number,
+            options,
+            span,
+        };
+
+        (field, entry)
+    }
+
+    pub fn parse_oneof(p: Pair<Rule>, file: FileId) -> Self
+    {
+        let span = Span::from_pair(file, &p);
         let mut inner = p.into_inner();
         let field_type = parse_field_type(inner.next().unwrap().as_str());
         let name = inner.next().unwrap().as_str().to_string();
@@ -229,13 +501,14 @@ impl FieldBuilder
             name,
             number,
             options,
+            span,
         }
     }
 }
 
 impl OneofBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
         let mut inner = p.into_inner();
         let name = inner.next().unwrap().as_str().to_string();
@@ -244,7 +517,7 @@ impl OneofBuilder
         for p in inner {
             match p.as_rule() {
                 Rule::option => options.push(ProtoOption::parse(p)),
-                Rule::oneofField => fields.push(FieldBuilder::parse_oneof(p)),
+                Rule::oneofField => fields.push(FieldBuilder::parse_oneof(p, file)),
                 Rule::emptyStatement => {}
                 r => unreachable!("{:?}: {:?}", r, p),
             }
@@ -257,6 +530,54 @@ impl OneofBuilder
     }
 }
 
+fn parse_multiplicity(p: Pair<Rule>) -> Multiplicity
+{
+    match p.into_inner().next() {
+        Some(t) => {
+            let label = t.into_inner().next().unwrap().as_rule();
+            match label {
+                Rule::optional => Multiplicity::Optional,
+                Rule::repeated => Multiplicity::Repeated,
+                Rule::required => Multiplicity::Required,
+                r => unreachable!("{:?}: {:?}", r, label),
+            }
+        }
+        None => Multiplicity::Single,
+    }
+}
+
+/// Parses a field number range `1`, `1 to 5` or `1 to max`.
+fn parse_range(p: Pair<Rule>) -> RangeInclusive<u64>
+{
+    let mut inner = p.into_inner();
+    let start = parse_uint_literal(inner.next().unwrap());
+    let end = match inner.next() {
+        Some(end) if end.as_rule() == Rule::rangeMax => MAX_FIELD_NUMBER,
+        Some(end) => parse_uint_literal(end),
+        None => start,
+    };
+    start..=end
+}
+
+/// Builds the name of the map entry type the same way `protoc` does: `my_field` -> `MyFieldEntry`.
+fn map_entry_name(field_name: &str) -> String
+{
+    let mut name = String::with_capacity(field_name.len() + 5);
+    let mut capitalize = true;
+    for c in field_name.chars() {
+        match c {
+            '_' => capitalize = true,
+            c if capitalize => {
+                name.push(c.to_ascii_uppercase());
+                capitalize = false;
+            }
+            c => name.push(c),
+        }
+    }
+    name.push_str("Entry");
+    name
+}
+
 fn parse_field_type(t: &str) -> FieldTypeBuilder
 {
     FieldTypeBuilder::Builtin(match t {
@@ -281,13 +602,14 @@ fn parse_field_type(t: &str) -> FieldTypeBuilder
 
 impl RpcBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
+        let span = Span::from_pair(file, &p);
         let mut inner = p.into_inner();
         let name = inner.next().unwrap();
 
-        let input = RpcArgBuilder::parse(inner.next().unwrap());
-        let output = RpcArgBuilder::parse(inner.next().unwrap());
+        let input = RpcArgBuilder::parse(inner.next().unwrap(), file);
+        let output = RpcArgBuilder::parse(inner.next().unwrap(), file);
 
         let mut options = vec![];
         for p in inner {
@@ -303,18 +625,40 @@ impl RpcBuilder
             input,
             output,
             options,
+            span,
         }
     }
 }
 
 impl RpcArgBuilder
 {
-    pub fn parse(p: Pair<Rule>) -> Self
+    pub fn parse(p: Pair<Rule>, file: FileId) -> Self
     {
         let mut inner = p.into_inner();
+        let stream = inner.next().unwrap().into_inner().next().is_some();
+        let message = inner.next().unwrap();
         RpcArgBuilder {
-            stream: inner.next().unwrap().into_inner().next().is_some(),
-            message: inner.next().unwrap().as_str().to_string(),
+            stream,
+            span: Span::from_pair(file, &message),
+            message: message.as_str().to_string(),
+        }
+    }
+}
+
+impl Span
+{
+    fn from_pair(file: FileId, p: &Pair<Rule>) -> Self
+    {
+        let span = p.as_span();
+        let (line, column) = span.start_pos().line_col();
+        let (end_line, end_column) = span.end_pos().line_col();
+        Span {
+            file,
+            start: Position { line, column },
+            end: Position {
+                line: end_line,
+                column: end_column,
+            },
         }
     }
 }
@@ -395,18 +739,61 @@ impl Constant
 {
     fn parse(p: Pair<Rule>) -> Self
     {
-        let p = p.into_inner().next().unwrap();
+        Self::parse_value(p.into_inner().next().unwrap())
+    }
+
+    /// Parses the fields of an aggregate value without the surrounding braces, such as the
+    /// `aggregate_value` of an uninterpreted option.
+    pub(crate) fn parse_aggregate(fields: &str) -> Option<Self>
+    {
+        let text = format!("{{{}}}", fields);
+        let mut pairs = ProtoParser::parse(Rule::aggregate, &text).ok()?;
+        Some(Self::parse_value(pairs.next()?))
+    }
+
+    fn parse_value(p: Pair<Rule>) -> Self
+    {
         match p.as_rule() {
             Rule::fullIdent => Constant::Ident(parse_ident(p)),
             Rule::intLit => Constant::Integer(parse_int_literal(p)),
             Rule::floatLit => Constant::Float(parse_float_literal(p)),
             Rule::strLit => Constant::String(parse_string_literal(p)),
             Rule::boolLit => Constant::Bool(p.as_str() == "true"),
+            Rule::aggregateString => {
+                let mut output = BytesMut::new();
+                for s in p.into_inner() {
+                    output.extend_from_slice(&parse_string_literal(s));
+                }
+                Constant::String(output.freeze())
+            }
+            Rule::aggregate => {
+                Constant::Aggregate(p.into_inner().flat_map(parse_aggregate_field).collect())
+            }
             r => unreachable!("{:?}: {:?}", r, p),
         }
     }
 }
 
+/// Parses a field of an aggregate value. Lists produce an entry for each value.
+fn parse_aggregate_field(p: Pair<Rule>) -> Vec<(String, Constant)>
+{
+    let mut inner = p.into_inner();
+    let name = inner.next().unwrap().into_inner().next().unwrap();
+    let name = match name.as_rule() {
+        Rule::aggregateTypeName => format!("[{}]", name.as_str()),
+        _ => name.as_str().to_string(),
+    };
+
+    let value = inner.next().unwrap();
+    match value.as_rule() {
+        Rule::aggregateList => value
+            .into_inner()
+            .map(|v| (name.clone(), Constant::parse_value(v)))
+            .collect(),
+        _ => vec![(name, Constant::parse_value(value))],
+    }
+}
+
 fn parse_ident(p: Pair<Rule>) -> String
 {
     let mut ident = vec![];
@@ -471,14 +858,64 @@ mod test
 {
     use super::*;
 
+    /// Parses the input and resets the spans so the result can be compared with builders
+    /// created by hand.
+    fn parse_without_spans(input: &str, file: FileId) -> Result<PackageBuilder, ParseError>
+    {
+        fn clear_message(m: &mut MessageBuilder)
+        {
+            m.span = Span::default();
+            let oneof_fields = m.oneofs.iter_mut().flat_map(|o| &mut o.fields);
+            let extension_fields = m.extensions.iter_mut().map(|e| &mut e.field);
+            for field in m
+                .fields
+                .iter_mut()
+                .chain(oneof_fields)
+                .chain(extension_fields)
+            {
+                field.span = Span::default();
+            }
+            for inner in &mut m.inner_types {
+                match inner {
+                    InnerTypeBuilder::Message(m) => clear_message(m),
+                    InnerTypeBuilder::Enum(e) => e.span = Span::default(),
+                }
+            }
+        }
+
+        let mut package = PackageBuilder::parse_str(input, file)?;
+        for import in &mut package.imports {
+            import.span = Span::default();
+        }
+        for ext in &mut package.extensions {
+            ext.field.span = Span::default();
+        }
+        for item in &mut package.types {
+            match item {
+                ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(m)) => clear_message(m),
+                ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(e)) => e.span = Span::default(),
+                ProtobufItemBuilder::Service(s) => {
+                    s.span = Span::default();
+                    for rpc in &mut s.rpcs {
+                        rpc.span = Span::default();
+                        rpc.input.span = Span::default();
+                        rpc.output.span = Span::default();
+                    }
+                }
+            }
+        }
+        Ok(package)
+    }
+
     #[test]
     fn empty()
     {
         assert_eq!(
-            PackageBuilder::parse_str(
+            parse_without_spans(
                 r#"
                 syntax = "proto3";
-            "#
+            "#,
+                FileId(0)
             )
             .unwrap(),
             PackageBuilder::default(),
@@ -489,11 +926,12 @@ mod test
     fn package()
     {
         assert_eq!(
-            PackageBuilder::parse_str(
+            parse_without_spans(
                 r#"
                 syntax = "proto3";
                 package Test;
-            "#
+            "#,
+                FileId(0)
             )
             .unwrap(),
             PackageBuilder {
@@ -507,13 +945,16 @@ mod test
     fn bom()
     {
         assert_eq!(
-            PackageBuilder::parse_str(&format!(
-                "\u{FEFF}{}",
-                r#"
+            parse_without_spans(
+                &format!(
+                    "\u{FEFF}{}",
+                    r#"
                 syntax = "proto3";
                 package Test;
             "#
-            ))
+                ),
+                FileId(0)
+            )
             .unwrap(),
             PackageBuilder {
                 name: Some("Test".to_string()),
@@ -526,14 +967,15 @@ mod test
     fn message()
     {
         assert_eq!(
-            PackageBuilder::parse_str(
+            parse_without_spans(
                 r#"
                 syntax = "proto3";
 
                 message MyMessage {
                     int32 value = 1;
                 }
-            "#
+            "#,
+                FileId(0)
             )
             .unwrap(),
             PackageBuilder {
@@ -546,7 +988,72 @@ mod test
                             name: "value".to_string(),
                             number: 1,
                             options: vec![],
+                            span: Span::default(),
+                        }],
+                        field_order: vec![1],
+                        ..Default::default()
+                    }
+                )),],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn map()
+    {
+        assert_eq!(
+            parse_without_spans(
+                r#"
+                syntax = "proto3";
+
+                message MyMessage {
+                    map<string, Foo> my_map = 1;
+                }
+            "#,
+                FileId(0)
+            )
+            .unwrap(),
+            PackageBuilder {
+                types: vec![ProtobufItemBuilder::Type(ProtobufTypeBuilder::Message(
+                    MessageBuilder {
+                        name: "MyMessage".to_string(),
+                        fields: vec![FieldBuilder {
+                            multiplicity: Multiplicity::Map,
+                            field_type: FieldTypeBuilder::Unknown("MyMapEntry".to_string()),
+                            name: "my_map".to_string(),
+                            number: 1,
+                            options: vec![],
+                            span: Span::default(),
                         }],
+                        inner_types: vec![InnerTypeBuilder::Message(MessageBuilder {
+                            name: "MyMapEntry".to_string(),
+                            fields: vec![
+                                FieldBuilder {
+                                    multiplicity: Multiplicity::Single,
+                                    field_type: FieldTypeBuilder::Builtin(ValueType::String),
+                                    name: "key".to_string(),
+                                    number: 1,
+                                    options: vec![],
+                                    span: Span::default(),
+                                },
+                                FieldBuilder {
+                                    multiplicity: Multiplicity::Single,
+                                    field_type: FieldTypeBuilder::Unknown("Foo".to_string()),
+                                    name: "value".to_string(),
+                                    number: 2,
+                                    options: vec![],
+                                    span: Span::default(),
+                                },
+                            ],
+                            options: vec![ProtoOption {
+                                name: "map_entry".to_string(),
+                                value: Constant::Bool(true),
+                            }],
+                            field_order: vec![1, 2],
+                            ..Default::default()
+                        })],
+                        field_order: vec![1],
                         ..Default::default()
                     }
                 )),],
@@ -559,7 +1066,7 @@ mod test
     fn pbenum()
     {
         assert_eq!(
-            PackageBuilder::parse_str(
+            parse_without_spans(
                 r#"
                 syntax = "proto3";
 
@@ -567,7 +1074,8 @@ mod test
                     a = 1;
                     b = -1;
                 }
-            "#
+            "#,
+                FileId(0)
             )
             .unwrap(),
             PackageBuilder {
@@ -598,14 +1106,15 @@ mod test
     fn service()
     {
         assert_eq!(
-            PackageBuilder::parse_str(
+            parse_without_spans(
                 r#"
                 syntax = "proto3";
 
                 service MyService {
                     rpc function( Foo ) returns ( stream Bar );
                 }
-            "#
+            "#,
+                FileId(0)
             )
             .unwrap(),
             PackageBuilder {
@@ -616,10 +1125,12 @@ mod test
                         input: RpcArgBuilder {
                             stream: false,
                             message: "Foo".to_string(),
+                            span: Span::default(),
                         },
                         output: RpcArgBuilder {
                             stream: true,
                             message: "Bar".to_string(),
+                            span: Span::default(),
                         },
                         ..Default::default()
                     },],
@@ -634,7 +1145,7 @@ mod test
     fn options()
     {
         assert_eq!(
-            PackageBuilder::parse_str(
+            parse_without_spans(
                 r#"
                 syntax = "proto3";
 
@@ -652,7 +1163,8 @@ mod test
                     rpc function( Foo ) returns ( stream Bar ) { option o = true; }
                     option sOption = "bar";
                 }
-            "#
+            "#,
+                FileId(0)
             )
             .unwrap(),
             PackageBuilder {
@@ -668,11 +1180,13 @@ mod test
                                 name: "fOption".to_string(),
                                 value: Constant::Ident("bar".to_string()),
                             }],
+                            span: Span::default(),
                         }],
                         options: vec![ProtoOption {
                             name: "mOption".to_string(),
                             value: Constant::String(Bytes::from_static(b"foo")),
                         }],
+                        field_order: vec![1],
                         ..Default::default()
                     })),
                     ProtobufItemBuilder::Type(ProtobufTypeBuilder::Enum(EnumBuilder {
@@ -704,20 +1218,24 @@ mod test
                             input: RpcArgBuilder {
                                 stream: false,
                                 message: "Foo".to_string(),
+                                span: Span::default(),
                             },
                             output: RpcArgBuilder {
                                 stream: true,
                                 message: "Bar".to_string(),
+                                span: Span::default(),
                             },
                             options: vec![ProtoOption {
                                 name: "o".to_string(),
                                 value: Constant::Bool(true),
-                            }]
+                            }],
+                            span: Span::default(),
                         },],
                         options: vec![ProtoOption {
                             name: "sOption".to_string(),
                             value: Constant::String(Bytes::from_static(b"bar")),
-                        }]
+                        }],
+                        span: Span::default(),
                     }),
                 ],
                 ..Default::default()
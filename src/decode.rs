@@ -5,8 +5,15 @@
 
 use crate::context::*;
 use bytes::{Bytes, BytesMut};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
-use std::fmt::Debug;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Maximum length of a 64-bit varint.
+pub(crate) const MAX_VARINT_LEN: usize = 10;
 
 impl Context
 {
@@ -15,6 +22,88 @@ impl Context
     {
         self.resolve_message(msg).decode(data, self)
     }
+
+    /// Decode a message with custom limits.
+    pub fn decode_with_options(
+        &self,
+        msg: MessageRef,
+        data: &[u8],
+        options: &DecodeOptions,
+    ) -> MessageValue
+    {
+        self.resolve_message(msg)
+            .decode_with_options(data, self, options)
+    }
+
+    /// Decode a message from `Bytes` without copying.
+    ///
+    /// The `bytes` values, the unknown and incomplete values and the garbage share the memory of
+    /// the input.
+    pub fn decode_bytes(
+        &self,
+        msg: MessageRef,
+        data: &Bytes,
+        options: &DecodeOptions,
+    ) -> MessageValue
+    {
+        self.resolve_message(msg).decode_bytes(data, self, options)
+    }
+}
+
+/// Limits for decoding untrusted payloads.
+///
+/// Values that would exceed a limit are not decoded but kept as [`Value::LimitExceeded`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DecodeOptions
+{
+    /// Maximum nesting depth of messages and groups. Defaults to 100.
+    pub max_depth: usize,
+
+    /// Maximum number of values of a single field in a message. Each element of a packed array
+    /// counts as a value. Unlimited by default.
+    pub max_repeated: usize,
+
+    /// Maximum number of bytes allocated for the decoded values. Unlimited by default.
+    ///
+    /// Each field is charged the size of a [`FieldValue`] and the length of its encoded data,
+    /// which covers the memory used by the value. Packed arrays are charged the size of the
+    /// largest element for each byte. When the limit is hit, the rest of the message is kept as
+    /// a single value.
+    ///
+    /// Lazy messages share the limit with the decode call that produced them: the bytes
+    /// allocated when decoding a lazy message are charged to the same total.
+    ///
+    /// The limit also applies to the size of a gRPC frame after decompressing it with
+    /// [`Frame::decompress_with_options`](crate::grpc::Frame::decompress_with_options).
+    pub max_total_bytes: usize,
+
+    /// Maximum length of a `string` or `bytes` value. Unlimited by default.
+    pub max_string_len: usize,
+
+    /// Decode the fields of nested messages only when they are accessed. Such messages are
+    /// decoded as [`Value::LazyMessage`]. Disabled by default.
+    ///
+    /// The fields of map entries are always decoded.
+    pub lazy_messages: bool,
+}
+
+/// Limit of the [`DecodeOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecodeLimit
+{
+    /// `max_depth`
+    Depth,
+
+    /// `max_repeated`
+    Repeated,
+
+    /// `max_total_bytes`
+    TotalBytes,
+
+    /// `max_string_len`
+    StringLength,
 }
 
 /// Decoded protocol buffer value.
@@ -52,18 +141,51 @@ pub enum Value
     /// `bytes` value.
     Bytes(Bytes),
 
+    /// `string` value that isn't valid UTF-8.
+    ///
+    /// Contains the raw bytes of the string so it can be encoded back as it was.
+    InvalidString(Bytes),
+
     /// A repeated packed value.
     Packed(PackedArray),
 
     /// Message type value.
     Message(Box<MessageValue>),
 
+    /// Message type value that is decoded when it is accessed.
+    ///
+    /// Used instead of `Message` when [`DecodeOptions::lazy_messages`] is enabled.
+    LazyMessage(Box<LazyMessage>),
+
+    /// Group type value.
+    ///
+    /// Groups are messages delimited by start-group and end-group tags instead of a length
+    /// prefix.
+    Group(Box<MessageValue>),
+
     /// Enum type value.
     Enum(EnumValue),
 
+    /// A single key/value pair of a `map<K, V>` field.
+    ///
+    /// The key or the value may be missing and the fields may appear in either order. The entry
+    /// keeps track of both so it is encoded back as it was.
+    ///
+    /// Map entries that contain anything other than the key and the value, such as unknown
+    /// fields, incomplete values or invalid strings, are decoded as plain `Message` values
+    /// instead to ensure they can be encoded back into the original bytes.
+    MapEntry(Box<MapEntryValue>),
+
     /// Value which was incomplete due to missing bytes in the payload.
     Incomplete(u8, Bytes),
 
+    /// Value which wasn't decoded because it would have exceeded a limit of the
+    /// [`DecodeOptions`].
+    ///
+    /// Contains the wire type and the bytes following the tag. For the `TotalBytes` limit the
+    /// bytes include the rest of the message.
+    LimitExceeded(DecodeLimit, u8, Bytes),
+
     /// Value which wasn't defined in the context.
     ///
     /// The wire type allows the decoder to tell how large an unknown value is. This allows the
@@ -71,7 +193,7 @@ pub enum Value
     Unknown(UnknownValue),
 }
 
-/// Packed scalar fields.
+/// Packed scalar and enum fields.
 #[derive(Debug, PartialEq, Clone)]
 pub enum PackedArray
 {
@@ -101,6 +223,8 @@ pub enum PackedArray
     SFixed64(Vec<i64>),
     /// `bool` value.
     Bool(Vec<bool>),
+    /// `enum` value.
+    Enum(Vec<EnumValue>),
 }
 
 /// Unknown value.
@@ -119,6 +243,11 @@ pub enum UnknownValue
     /// Unknown 32-bit value (wire type = 5).
     Fixed32(u32),
 
+    /// Unknown group (wire type = 3).
+    ///
+    /// Contains the fields between the start-group and the end-group tags.
+    Group(Bytes),
+
     /// Invalid value.
     ///
     /// Invalid value is a value for which the wire type wasn't valid. Encountering invalid wire
@@ -140,6 +269,46 @@ pub struct EnumValue
     pub value: i64,
 }
 
+/// Nested message that is decoded when it is first accessed.
+#[derive(Debug, Clone)]
+pub struct LazyMessage
+{
+    /// Reference to the message type.
+    pub msg_ref: MessageRef,
+
+    /// Encoded message.
+    pub data: Bytes,
+
+    /// Options of the decode call that produced the message.
+    options: DecodeOptions,
+
+    /// Nesting depth of the message.
+    depth: usize,
+
+    /// Bytes charged against `max_total_bytes`, shared with the decode call that produced the
+    /// message.
+    allocated: Arc<AtomicUsize>,
+
+    decoded: OnceLock<MessageValue>,
+}
+
+/// Map entry value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MapEntryValue
+{
+    /// Reference to the synthesized map entry message type.
+    pub entry_ref: MessageRef,
+
+    /// Map key or `None` if the entry omitted it.
+    pub key: Option<Value>,
+
+    /// Map value or `None` if the entry omitted it.
+    pub value: Option<Value>,
+
+    /// `true` if the value preceded the key in the entry.
+    pub value_first: bool,
+}
+
 /// Message value.
 #[derive(Debug, PartialEq, Clone)]
 pub struct MessageValue
@@ -168,9 +337,270 @@ pub struct FieldValue
     pub value: Value,
 }
 
+impl Default for DecodeOptions
+{
+    fn default() -> Self
+    {
+        DecodeOptions {
+            max_depth: 100,
+            max_repeated: usize::MAX,
+            max_total_bytes: usize::MAX,
+            max_string_len: usize::MAX,
+            lazy_messages: false,
+        }
+    }
+}
+
+impl LazyMessage
+{
+    /// Gets the message, decoding it on the first call.
+    ///
+    /// Will **panic** if the message defined by the `MessageRef` does not exist in this context.
+    pub fn get(&self, ctx: &Context) -> &MessageValue
+    {
+        self.decoded.get_or_init(|| self.decode(ctx))
+    }
+
+    /// Converts into the decoded message.
+    ///
+    /// Will **panic** if the message defined by the `MessageRef` does not exist in this context.
+    pub fn into_message(mut self, ctx: &Context) -> MessageValue
+    {
+        match self.decoded.take() {
+            Some(msg) => msg,
+            None => self.decode(ctx),
+        }
+    }
+
+    fn decode(&self, ctx: &Context) -> MessageValue
+    {
+        let mut dec = Decoder {
+            ctx,
+            options: &self.options,
+            source: Source(Some(&self.data)),
+            depth: self.depth,
+            allocated: self.allocated.clone(),
+        };
+        ctx.resolve_message(self.msg_ref)
+            .decode_message(&self.data, &mut dec)
+    }
+}
+
+/// Lazy messages are equal if they have the same type and data regardless of whether they have
+/// been decoded.
+impl PartialEq for LazyMessage
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.msg_ref == other.msg_ref && self.data == other.data
+    }
+}
+
+impl MapEntryValue
+{
+    /// Gets the key, using the default value of the key type if the entry omitted it.
+    ///
+    /// Will **panic** if the `entry_ref` is not a map entry message in this context.
+    pub fn key_or_default(&self, ctx: &Context) -> Cow<'_, Value>
+    {
+        self.field_or_default(&self.key, 1, ctx)
+    }
+
+    /// Gets the value, using the default value of the value type if the entry omitted it.
+    ///
+    /// Will **panic** if the `entry_ref` is not a map entry message in this context.
+    pub fn value_or_default(&self, ctx: &Context) -> Cow<'_, Value>
+    {
+        self.field_or_default(&self.value, 2, ctx)
+    }
+
+    fn field_or_default<'a>(
+        &'a self,
+        field: &'a Option<Value>,
+        number: u64,
+        ctx: &Context,
+    ) -> Cow<'a, Value>
+    {
+        match field {
+            Some(value) => Cow::Borrowed(value),
+            None => {
+                let info = ctx.resolve_message(self.entry_ref);
+                let field = info
+                    .get_field(number)
+                    .expect("Map entry without a key or a value");
+                Cow::Owned(default_value(&field.field_type))
+            }
+        }
+    }
+}
+
+impl DecodeLimit
+{
+    /// Name of the option that sets the limit, such as `max_depth`.
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            DecodeLimit::Depth => "max_depth",
+            DecodeLimit::Repeated => "max_repeated",
+            DecodeLimit::TotalBytes => "max_total_bytes",
+            DecodeLimit::StringLength => "max_string_len",
+        }
+    }
+
+    /// Gets the limit by the name of its option.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        Some(match name {
+            "max_depth" => DecodeLimit::Depth,
+            "max_repeated" => DecodeLimit::Repeated,
+            "max_total_bytes" => DecodeLimit::TotalBytes,
+            "max_string_len" => DecodeLimit::StringLength,
+            _ => return None,
+        })
+    }
+}
+
+/// Input of a decode call.
+///
+/// Values are sliced from the input `Bytes` instead of copied when it is available.
+#[derive(Clone, Copy)]
+struct Source<'a>(Option<&'a Bytes>);
+
+impl Source<'_>
+{
+    fn bytes(self, data: &[u8]) -> Bytes
+    {
+        match self.0 {
+            Some(input) => input.slice_ref(data),
+            None => Bytes::copy_from_slice(data),
+        }
+    }
+}
+
+/// State of a single decode call.
+struct Decoder<'a>
+{
+    ctx: &'a Context,
+    options: &'a DecodeOptions,
+    source: Source<'a>,
+
+    /// Nesting depth of the message being decoded.
+    depth: usize,
+
+    /// Bytes charged against `max_total_bytes`.
+    ///
+    /// Shared with the lazy messages created by the decoder.
+    allocated: Arc<AtomicUsize>,
+}
+
+impl Decoder<'_>
+{
+    /// Charges the memory of a field against the total limit. Returns `false` if the limit is
+    /// exceeded.
+    fn allocate(&mut self, field: Option<&MessageField>, wire_type: u8, raw: &[u8]) -> bool
+    {
+        let size = match field {
+            // The fields of nested messages are charged when they are decoded.
+            Some(f) if is_nested(&f.field_type) && f.field_type.wire_type() == wire_type => 0,
+            Some(f) if is_packed(f, wire_type) => raw.len().saturating_mul(mem::size_of::<u64>()),
+            _ => raw.len(),
+        };
+
+        let size = mem::size_of::<FieldValue>().saturating_add(size);
+        let previous = self
+            .allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |a| {
+                Some(a.saturating_add(size))
+            })
+            .unwrap_or_else(|a| a);
+        previous.saturating_add(size) <= self.options.max_total_bytes
+    }
+
+    /// Checks a complete value against the limits other than the total limit.
+    ///
+    /// `count` is the number of values the field has had so far.
+    fn check(
+        &self,
+        field: Option<&MessageField>,
+        wire_type: u8,
+        raw: &[u8],
+        count: usize,
+    ) -> Option<DecodeLimit>
+    {
+        if count >= self.options.max_repeated {
+            return Some(DecodeLimit::Repeated);
+        }
+
+        let field = field.filter(|f| f.field_type.wire_type() == wire_type)?;
+        match field.field_type {
+            ValueType::Message(..) | ValueType::Group(..)
+                if self.depth >= self.options.max_depth =>
+            {
+                Some(DecodeLimit::Depth)
+            }
+            ValueType::String | ValueType::Bytes => {
+                let mut body = raw;
+                usize::from_unsigned_varint(&mut body)
+                    .filter(|len| *len > self.options.max_string_len)
+                    .map(|_| DecodeLimit::StringLength)
+            }
+            _ => None,
+        }
+    }
+
+    /// Creates a lazy message from the bytes of a message value, including the length prefix.
+    fn lazy_message(&self, msg: MessageRef, mut raw: &[u8]) -> LazyMessage
+    {
+        let _ = usize::from_unsigned_varint(&mut raw);
+        LazyMessage {
+            msg_ref: msg,
+            data: self.source.bytes(raw),
+            options: self.options.clone(),
+            depth: self.depth + 1,
+            allocated: self.allocated.clone(),
+            decoded: OnceLock::new(),
+        }
+    }
+
+    /// Decodes a nested message.
+    fn decode_nested(&mut self, msg: MessageRef, data: &[u8]) -> MessageValue
+    {
+        self.depth += 1;
+        let value = self.ctx.resolve_message(msg).decode_message(data, self);
+        self.depth -= 1;
+        value
+    }
+}
+
+fn is_nested(vt: &ValueType) -> bool
+{
+    matches!(vt, ValueType::Message(..) | ValueType::Group(..))
+}
+
+/// True, if the value of the field should be decoded as a packed array.
+///
+/// Repeated scalar and enum fields accept both packed and unpacked values regardless of the
+/// `packed` option.
+fn is_packed(field: &MessageField, wire_type: u8) -> bool
+{
+    let repeated = matches!(
+        field.multiplicity,
+        Multiplicity::Repeated | Multiplicity::RepeatedPacked
+    );
+    let scalar = matches!(field.field_type.wire_type(), 0 | 1 | 5);
+    repeated && scalar && wire_type == 2
+}
+
+/// True, if the value of the field should be decoded as a lazy message.
+fn is_lazy(field: &MessageField, wire_type: u8, dec: &Decoder) -> bool
+{
+    dec.options.lazy_messages && wire_type == 2 && field.multiplicity != Multiplicity::Map
+}
+
 impl Value
 {
-    fn decode(data: &mut &[u8], vt_raw: u8, vt: &ValueType, ctx: &Context) -> Self
+    fn decode(data: &mut &[u8], number: u64, vt_raw: u8, vt: &ValueType, dec: &mut Decoder)
+        -> Self
     {
         let original = *data;
         let opt = match vt {
@@ -178,20 +608,18 @@ impl Value
                 try_read_8_bytes(data).map(|b| Value::Double(f64::from_le_bytes(b)))
             }
             ValueType::Float => try_read_4_bytes(data).map(|b| Value::Float(f32::from_le_bytes(b))),
-            ValueType::Int32 => i32::from_signed_varint(data).map(Value::Int32),
-            ValueType::Int64 => i64::from_signed_varint(data).map(Value::Int64),
-            ValueType::UInt32 => u32::from_unsigned_varint(data).map(Value::UInt32),
-            ValueType::UInt64 => u64::from_unsigned_varint(data).map(Value::UInt64),
-            ValueType::SInt32 => u32::from_unsigned_varint(data).map(|u| {
-                let (sign, sign_bit) = if u % 2 == 0 { (1i32, 0) } else { (-1i32, 1) };
-                let magnitude = (u / 2) as i32 + sign_bit;
-                Value::SInt32(sign * magnitude)
-            }),
-            ValueType::SInt64 => u64::from_unsigned_varint(data).map(|u| {
-                let (sign, sign_bit) = if u % 2 == 0 { (1i64, 0) } else { (-1i64, 1) };
-                let magnitude = (u / 2) as i64 + sign_bit;
-                Value::SInt64(sign * magnitude)
+            ValueType::Int32 => {
+                read_varint(data, |u| i32::try_from(u as i64).ok().map(Value::Int32))
+            }
+            ValueType::Int64 => read_varint(data, |u| Some(Value::Int64(u as i64))),
+            ValueType::UInt32 => read_varint(data, |u| u32::try_from(u).ok().map(Value::UInt32)),
+            ValueType::UInt64 => read_varint(data, |u| Some(Value::UInt64(u))),
+            ValueType::SInt32 => read_varint(data, |u| {
+                u32::try_from(u)
+                    .ok()
+                    .map(|u| Value::SInt32(zigzag_decode_32(u)))
             }),
+            ValueType::SInt64 => read_varint(data, |u| Some(Value::SInt64(zigzag_decode_64(u)))),
             ValueType::Fixed32 => {
                 try_read_4_bytes(data).map(|b| Value::Fixed32(u32::from_le_bytes(b)))
             }
@@ -204,14 +632,17 @@ impl Value
             ValueType::SFixed64 => {
                 try_read_8_bytes(data).map(|b| Value::SFixed64(i64::from_le_bytes(b)))
             }
-            ValueType::Bool => usize::from_unsigned_varint(data).map(|u| Value::Bool(u != 0)),
-            ValueType::String => read_string(data).map(Value::String),
-            ValueType::Bytes => read_bytes(data).map(Value::Bytes),
-            ValueType::Enum(eref) => i64::from_signed_varint(data).map(|v| {
-                Value::Enum(EnumValue {
+            ValueType::Bool => read_varint(data, |u| Some(Value::Bool(u != 0))),
+            ValueType::String => read_bytes(data).map(|b| match std::str::from_utf8(b) {
+                Ok(s) => Value::String(s.to_string()),
+                Err(_) => Value::InvalidString(dec.source.bytes(b)),
+            }),
+            ValueType::Bytes => read_bytes(data).map(|b| Value::Bytes(dec.source.bytes(b))),
+            ValueType::Enum(eref) => read_varint(data, |u| {
+                Some(Value::Enum(EnumValue {
                     enum_ref: *eref,
-                    value: v,
-                })
+                    value: u as i64,
+                }))
             }),
             ValueType::Message(mref) => usize::from_unsigned_varint(data).and_then(|length| {
                 if data.len() < length {
@@ -221,33 +652,36 @@ impl Value
                 let (consumed, remainder) = data.split_at(length);
                 *data = remainder;
 
-                Some(Value::Message(Box::new(
-                    ctx.resolve_message(*mref).decode(consumed, ctx),
-                )))
+                Some(Value::Message(Box::new(dec.decode_nested(*mref, consumed))))
             }),
+            ValueType::Group(mref) => match read_group(data, number) {
+                GroupBody::Complete(body) => {
+                    Some(Value::Group(Box::new(dec.decode_nested(*mref, body))))
+                }
+                GroupBody::Incomplete => None,
+                GroupBody::Invalid => Some(Value::Unknown(read_invalid(data, vt_raw, dec.source))),
+            },
         };
 
-        opt.unwrap_or_else(|| {
-            *data = &[];
-            Value::Incomplete(vt_raw, Bytes::copy_from_slice(original))
-        })
+        opt.unwrap_or_else(|| return_incomplete(data, vt_raw, original, dec.source))
     }
 
-    fn decode_packed(data: &mut &[u8], vt_raw: u8, vt: &ValueType) -> Self
+    fn decode_packed(data: &mut &[u8], vt_raw: u8, vt: &ValueType, src: Source) -> Self
     {
         let original = *data;
         let length = match usize::from_unsigned_varint(data) {
             Some(len) => len,
             None => {
-                return return_incomplete(data, vt_raw, original);
+                return return_incomplete(data, vt_raw, original, src);
             }
         };
 
         if data.len() < length {
-            return return_incomplete(data, vt_raw, original);
+            return return_incomplete(data, vt_raw, original, src);
         }
 
-        let mut array = &data[..length];
+        let packed = &data[..length];
+        let mut array = packed;
         *data = &data[length..];
 
         // Reading the packed arrays follows very similar format for each type.  The variances are
@@ -255,6 +689,9 @@ impl Value
         // value.
         //
         // This macro implements the basic structure with holes for the varying bits.
+        //
+        // The length of the array is known so an array with a truncated or out of range element
+        // is kept as an unknown value and decoding continues after it.
         macro_rules! read_packed {
             ($variant:ident @ $val:ident = $try_read:expr => $insert:expr ) => {
                 let mut output = vec![];
@@ -265,7 +702,9 @@ impl Value
 
                     match $try_read {
                         Some($val) => output.push($insert),
-                        None => return return_incomplete(&mut array, vt_raw, original),
+                        None => {
+                            return Value::Unknown(UnknownValue::VariableLength(src.bytes(packed)))
+                        }
                     }
                 }
             };
@@ -285,24 +724,16 @@ impl Value
                 read_packed! { Int64 @ b = i64::from_signed_varint(&mut array) => b }
             }
             ValueType::UInt32 => {
-                read_packed! { UInt32 @ b = u32::from_signed_varint(&mut array) => b }
+                read_packed! { UInt32 @ b = u32::from_unsigned_varint(&mut array) => b }
             }
             ValueType::UInt64 => {
-                read_packed! { UInt64 @ b = u64::from_signed_varint(&mut array) => b }
+                read_packed! { UInt64 @ b = u64::from_unsigned_varint(&mut array) => b }
             }
             ValueType::SInt32 => {
-                read_packed! { SInt32 @ b = u32::from_signed_varint(&mut array) => {
-                    let (sign, sign_bit) = if b % 2 == 0 { (1i32, 0) } else { (-1i32, 1) };
-                    let magnitude = (b / 2) as i32 + sign_bit;
-                    sign * magnitude
-                } }
+                read_packed! { SInt32 @ b = u32::from_unsigned_varint(&mut array) => zigzag_decode_32(b) }
             }
             ValueType::SInt64 => {
-                read_packed! { SInt64 @ b = u64::from_signed_varint(&mut array) => {
-                    let (sign, sign_bit) = if b % 2 == 0 { (1i64, 0) } else { (-1i64, 1) };
-                    let magnitude = (b / 2) as i64 + sign_bit;
-                    sign * magnitude
-                } }
+                read_packed! { SInt64 @ b = u64::from_unsigned_varint(&mut array) => zigzag_decode_64(b) }
             }
             ValueType::Fixed32 => {
                 read_packed! { Fixed32 @ b = try_read_4_bytes(&mut array) => u32::from_le_bytes(b) }
@@ -317,13 +748,23 @@ impl Value
                 read_packed! { SFixed64 @ b = try_read_8_bytes(&mut array) => i64::from_le_bytes(b) }
             }
             ValueType::Bool => {
-                read_packed! { Bool @ b = u8::from_unsigned_varint(&mut array) => b != 0 }
+                read_packed! { Bool @ b = u64::from_unsigned_varint(&mut array) => b != 0 }
+            }
+            ValueType::Enum(eref) => {
+                read_packed! { Enum @ b = u64::from_unsigned_varint(&mut array) => EnumValue { enum_ref: *eref, value: b as i64 } }
             }
-            _ => panic!("Non-scalar type was handled as packed"),
+
+            // Only scalars can be packed, but a field built by hand may claim otherwise.
+            _ => Value::Unknown(UnknownValue::VariableLength(src.bytes(packed))),
         }
     }
 
-    fn decode_unknown(data: &mut &[u8], vt: u8) -> Value
+    pub(crate) fn decode_unknown(data: &mut &[u8], number: u64, vt: u8) -> Value
+    {
+        Value::read_unknown(data, number, vt, Source(None))
+    }
+
+    fn read_unknown(data: &mut &[u8], number: u64, vt: u8, src: Source) -> Value
     {
         let original = *data;
         let value =
@@ -338,22 +779,21 @@ impl Value
                     }
                     let (consumed, remainder) = data.split_at(length);
                     *data = remainder;
-                    Some(UnknownValue::VariableLength(Bytes::copy_from_slice(
-                        consumed,
-                    )))
+                    Some(UnknownValue::VariableLength(src.bytes(consumed)))
                 }),
                 5 => try_read_4_bytes(data)
                     .map(|value| UnknownValue::Fixed32(u32::from_le_bytes(value))),
-                _ => {
-                    let bytes = Bytes::copy_from_slice(data);
-                    *data = &[];
-                    Some(UnknownValue::Invalid(vt, bytes))
-                }
+                3 => match read_group(data, number) {
+                    GroupBody::Complete(body) => Some(UnknownValue::Group(src.bytes(body))),
+                    GroupBody::Incomplete => None,
+                    GroupBody::Invalid => Some(read_invalid(data, vt, src)),
+                },
+                _ => Some(read_invalid(data, vt, src)),
             };
 
         value
             .map(Value::Unknown)
-            .unwrap_or_else(|| Value::Incomplete(vt, Bytes::copy_from_slice(data)))
+            .unwrap_or_else(|| return_incomplete(data, vt, original, src))
     }
 
     fn encode(&self, ctx: &Context) -> Option<(u8, BytesMut)>
@@ -365,14 +805,8 @@ impl Value
             Value::Int64(v) => BytesMut::from(v.into_signed_varint().as_ref()),
             Value::UInt32(v) => BytesMut::from(v.into_unsigned_varint().as_ref()),
             Value::UInt64(v) => BytesMut::from(v.into_unsigned_varint().as_ref()),
-            Value::SInt32(v) => {
-                let (v, sign_bit) = if *v < 0 { (-v, 1) } else { (*v, 0) };
-                (v * 2 - sign_bit).into_unsigned_varint()
-            }
-            Value::SInt64(v) => {
-                let (v, sign_bit) = if *v < 0 { (-v, 1) } else { (*v, 0) };
-                (v * 2 - sign_bit).into_unsigned_varint()
-            }
+            Value::SInt32(v) => zigzag_encode_32(*v).into_unsigned_varint(),
+            Value::SInt64(v) => zigzag_encode_64(*v).into_unsigned_varint(),
             Value::Fixed32(v) => BytesMut::from(v.to_le_bytes().as_ref()),
             Value::Fixed64(v) => BytesMut::from(v.to_le_bytes().as_ref()),
             Value::SFixed32(v) => BytesMut::from(v.to_le_bytes().as_ref()),
@@ -383,7 +817,7 @@ impl Value
                 output.extend_from_slice(v.as_bytes());
                 output
             }
-            Value::Bytes(v) => {
+            Value::Bytes(v) | Value::InvalidString(v) => {
                 let mut output = v.len().into_unsigned_varint();
                 output.extend_from_slice(v);
                 output
@@ -395,14 +829,100 @@ impl Value
                 output.extend_from_slice(&data);
                 output
             }
+            Value::LazyMessage(v) => {
+                let mut output = v.data.len().into_unsigned_varint();
+                output.extend_from_slice(&v.data);
+                output
+            }
+            Value::Group(v) => v.encode(ctx),
+            Value::MapEntry(v) => {
+                let key = v.key.as_ref().map(|key| encode_field(1, key, ctx));
+                let value = v.value.as_ref().map(|value| encode_field(2, value, ctx));
+                let fields = match v.value_first {
+                    true => [value, key],
+                    false => [key, value],
+                };
+                let mut data = BytesMut::new();
+                for field in fields.iter().flatten() {
+                    data.extend_from_slice(field);
+                }
+                let mut output = data.len().into_unsigned_varint();
+                output.extend_from_slice(&data);
+                output
+            }
             Value::Packed(p) => p.encode(),
             Value::Unknown(u) => u.encode(),
             Value::Incomplete(_, bytes) => BytesMut::from(bytes.as_ref()),
+            Value::LimitExceeded(_, _, bytes) => BytesMut::from(bytes.as_ref()),
         };
 
         Some((self.wire_type(), bytes))
     }
 
+    /// Converts a map entry message into a `MapEntry` value.
+    ///
+    /// Only entries consisting of at most one key and one value in any order are converted. Any
+    /// other value is returned as is.
+    pub(crate) fn into_map_entry(self, ctx: &Context) -> Value
+    {
+        let msg = match self {
+            Value::Message(msg) if msg.garbage.is_none() => msg,
+            other => return other,
+        };
+        let info = ctx.resolve_message(msg.msg_ref);
+        if info.get_field(1).is_none() || info.get_field(2).is_none() {
+            return Value::Message(msg);
+        }
+
+        let mut key = None;
+        let mut value = None;
+        for field in &msg.fields {
+            let slot = match field.number {
+                1 => &mut key,
+                2 => &mut value,
+                _ => return Value::Message(msg),
+            };
+            let is_valid = !matches!(
+                field.value,
+                Value::Unknown(..)
+                    | Value::Incomplete(..)
+                    | Value::LimitExceeded(..)
+                    | Value::InvalidString(..)
+            );
+            if slot.is_some() || !is_valid {
+                return Value::Message(msg);
+            }
+            *slot = Some(field.value.clone());
+        }
+
+        let value_first = matches!(msg.fields.as_slice(), [first, _] if first.number == 2);
+        Value::MapEntry(Box::new(MapEntryValue {
+            entry_ref: msg.msg_ref,
+            key,
+            value,
+            value_first,
+        }))
+    }
+
+    /// Converts a value that is not defined in a closed enum into an unknown value.
+    ///
+    /// Closed enums store such values in the unknown fields. Packed arrays are left as is.
+    fn check_closed_enum(self, ctx: &Context) -> Value
+    {
+        match self {
+            Value::Enum(v) => {
+                let info = ctx.resolve_enum(v.enum_ref);
+                match info.features.enum_type == EnumType::Closed
+                    && info.get_field_by_value(v.value).is_none()
+                {
+                    true => Value::Unknown(UnknownValue::Varint(v.value as u64 as u128)),
+                    false => Value::Enum(v),
+                }
+            }
+            other => other,
+        }
+    }
+
     fn wire_type(&self) -> u8
     {
         match self {
@@ -421,23 +941,105 @@ impl Value
             Value::Bool(..) => 0,
             Value::String(..) => 2,
             Value::Bytes(..) => 2,
+            Value::InvalidString(..) => 2,
             Value::Message(..) => 2,
+            Value::LazyMessage(..) => 2,
+            Value::Group(..) => 3,
             Value::Enum(..) => 0,
+            Value::MapEntry(..) => 2,
             Value::Packed(..) => 2,
             Value::Unknown(unk) => match unk {
                 UnknownValue::Varint(..) => 0,
                 UnknownValue::Fixed64(..) => 1,
                 UnknownValue::VariableLength(..) => 2,
                 UnknownValue::Fixed32(..) => 5,
+                UnknownValue::Group(..) => 3,
                 UnknownValue::Invalid(vt, ..) => *vt,
             },
             Value::Incomplete(vt, ..) => *vt,
+            Value::LimitExceeded(_, vt, ..) => *vt,
         }
     }
 }
 
 impl PackedArray
 {
+    /// Collects scalar values into a packed array.
+    ///
+    /// Returns `None` if the type can't be packed or any of the values doesn't match the type.
+    pub(crate) fn from_values(vt: &ValueType, values: &[Value]) -> Option<Self>
+    {
+        if let ValueType::Enum(eref) = vt {
+            return Some(PackedArray::Enum(
+                values
+                    .iter()
+                    .map(|v| match v {
+                        Value::Enum(v) if v.enum_ref == *eref => Some(v.clone()),
+                        _ => None,
+                    })
+                    .collect::<Option<_>>()?,
+            ));
+        }
+
+        macro_rules! pack {
+            ($($variant:ident),*) => {
+                match vt {
+                    $(ValueType::$variant => PackedArray::$variant(
+                        values
+                            .iter()
+                            .map(|v| match v {
+                                Value::$variant(v) => Some(*v),
+                                _ => None,
+                            })
+                            .collect::<Option<_>>()?,
+                    ),)*
+                    _ => return None,
+                }
+            };
+        }
+
+        Some(pack!(
+            Double, Float, Int32, Int64, UInt32, UInt64, SInt32, SInt64, Fixed32, Fixed64,
+            SFixed32, SFixed64, Bool
+        ))
+    }
+
+    /// Number of elements in the array.
+    pub(crate) fn len(&self) -> usize
+    {
+        macro_rules! len {
+            ($($variant:ident),*) => {
+                match self {
+                    $(PackedArray::$variant(v) => v.len(),)*
+                    PackedArray::Enum(v) => v.len(),
+                }
+            };
+        }
+
+        len!(
+            Double, Float, Int32, Int64, UInt32, UInt64, SInt32, SInt64, Fixed32, Fixed64,
+            SFixed32, SFixed64, Bool
+        )
+    }
+
+    /// Splits the packed array into scalar values.
+    pub(crate) fn to_values(&self) -> Vec<Value>
+    {
+        macro_rules! unpack {
+            ($($variant:ident),*) => {
+                match self {
+                    $(PackedArray::$variant(v) => v.iter().map(|v| Value::$variant(*v)).collect(),)*
+                    PackedArray::Enum(v) => v.iter().map(|v| Value::Enum(v.clone())).collect(),
+                }
+            };
+        }
+
+        unpack!(
+            Double, Float, Int32, Int64, UInt32, UInt64, SInt32, SInt64, Fixed32, Fixed64,
+            SFixed32, SFixed64, Bool
+        )
+    }
+
     fn encode(&self) -> BytesMut
     {
         macro_rules! write_packed {
@@ -466,16 +1068,10 @@ impl PackedArray
                 write_packed!(v => |v| BytesMut::from(v.into_unsigned_varint().as_ref()))
             }
             PackedArray::SInt32(v) => {
-                write_packed! { v => |v| {
-                    let (v, sign_bit) = if *v < 0 { (-v, 1) } else { (*v, 0) };
-                    (v * 2 - sign_bit).into_unsigned_varint()
-                } }
+                write_packed!(v => |v| zigzag_encode_32(*v).into_unsigned_varint())
             }
             PackedArray::SInt64(v) => {
-                write_packed! { v => |v| {
-                    let (v, sign_bit) = if *v < 0 { (-v, 1) } else { (*v, 0) };
-                    (v * 2 - sign_bit).into_unsigned_varint()
-                } }
+                write_packed!(v => |v| zigzag_encode_64(*v).into_unsigned_varint())
             }
             PackedArray::Fixed32(v) => {
                 write_packed!( v => |v| BytesMut::from(v.to_le_bytes().as_ref()) )
@@ -492,6 +1088,9 @@ impl PackedArray
             PackedArray::Bool(v) => {
                 write_packed!( v => |v| BytesMut::from(if *v { [1u8].as_ref() } else { [0u8].as_ref() }))
             }
+            PackedArray::Enum(v) => {
+                write_packed!(v => |v| BytesMut::from(v.value.into_signed_varint().as_ref()))
+            }
         };
 
         let mut output = data.len().into_unsigned_varint();
@@ -500,10 +1099,162 @@ impl PackedArray
     }
 }
 
-fn return_incomplete(data: &mut &[u8], vt: u8, original: &[u8]) -> Value
+/// Reads a varint and converts it into a value.
+///
+/// Varints that `convert` rejects as out of range for the field type are kept as unknown varints
+/// so that they can be encoded back as they were.
+fn read_varint(data: &mut &[u8], convert: impl FnOnce(u64) -> Option<Value>) -> Option<Value>
+{
+    u64::from_unsigned_varint(data)
+        .map(|u| convert(u).unwrap_or(Value::Unknown(UnknownValue::Varint(u as u128))))
+}
+
+fn zigzag_decode_32(u: u32) -> i32
+{
+    (u >> 1) as i32 ^ -((u & 1) as i32)
+}
+
+fn zigzag_decode_64(u: u64) -> i64
+{
+    (u >> 1) as i64 ^ -((u & 1) as i64)
+}
+
+fn zigzag_encode_32(v: i32) -> u32
 {
+    ((v << 1) ^ (v >> 31)) as u32
+}
+
+fn zigzag_encode_64(v: i64) -> u64
+{
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+/// Default value of a type, used for the fields missing from the data.
+pub(crate) fn default_value(vt: &ValueType) -> Value
+{
+    match vt {
+        ValueType::Double => Value::Double(0.0),
+        ValueType::Float => Value::Float(0.0),
+        ValueType::Int32 => Value::Int32(0),
+        ValueType::Int64 => Value::Int64(0),
+        ValueType::UInt32 => Value::UInt32(0),
+        ValueType::UInt64 => Value::UInt64(0),
+        ValueType::SInt32 => Value::SInt32(0),
+        ValueType::SInt64 => Value::SInt64(0),
+        ValueType::Fixed32 => Value::Fixed32(0),
+        ValueType::Fixed64 => Value::Fixed64(0),
+        ValueType::SFixed32 => Value::SFixed32(0),
+        ValueType::SFixed64 => Value::SFixed64(0),
+        ValueType::Bool => Value::Bool(false),
+        ValueType::String => Value::String(String::new()),
+        ValueType::Bytes => Value::Bytes(Bytes::new()),
+        ValueType::Enum(e) => Value::Enum(EnumValue {
+            enum_ref: *e,
+            value: 0,
+        }),
+        ValueType::Message(m) => Value::Message(Box::new(MessageValue {
+            msg_ref: *m,
+            fields: vec![],
+            garbage: None,
+        })),
+        ValueType::Group(m) => Value::Group(Box::new(MessageValue {
+            msg_ref: *m,
+            fields: vec![],
+            garbage: None,
+        })),
+    }
+}
+
+fn return_incomplete(data: &mut &[u8], vt: u8, original: &[u8], src: Source) -> Value
+{
+    *data = &[];
+    Value::Incomplete(vt, src.bytes(original))
+}
+
+fn read_invalid(data: &mut &[u8], vt: u8, src: Source) -> UnknownValue
+{
+    let bytes = src.bytes(data);
     *data = &[];
-    Value::Incomplete(vt, Bytes::copy_from_slice(original))
+    UnknownValue::Invalid(vt, bytes)
+}
+
+/// Group contents read by `read_group`.
+enum GroupBody<'a>
+{
+    /// Fields between the start-group and end-group tags.
+    Complete(&'a [u8]),
+
+    /// The data ended before the end-group tag.
+    Incomplete,
+
+    /// The group contained an invalid wire type or an end-group tag of another group.
+    Invalid,
+}
+
+/// Reads a group up to and including the end-group tag matching the field number.
+///
+/// The data is advanced only if the group is complete.
+fn read_group<'a>(data: &mut &'a [u8], number: u64) -> GroupBody<'a>
+{
+    let original = *data;
+    let mut cursor = *data;
+    let mut open_groups = vec![number];
+    loop {
+        let body_len = original.len() - cursor.len();
+        let tag = match u64::from_unsigned_varint(&mut cursor) {
+            Some(tag) => tag,
+            None => return GroupBody::Incomplete,
+        };
+
+        let complete = match tag & 0x07 {
+            vt @ (0 | 1 | 2 | 5) => skip_value(&mut cursor, tag >> 3, vt as u8).is_some(),
+            3 => {
+                open_groups.push(tag >> 3);
+                true
+            }
+            4 if open_groups.last() == Some(&(tag >> 3)) => {
+                open_groups.pop();
+                if open_groups.is_empty() {
+                    *data = cursor;
+                    return GroupBody::Complete(&original[..body_len]);
+                }
+                true
+            }
+            _ => return GroupBody::Invalid,
+        };
+
+        if !complete {
+            return GroupBody::Incomplete;
+        }
+    }
+}
+
+/// Skips a value. Returns the bytes of the value, or `None` if the value is incomplete or has an
+/// invalid wire type.
+///
+/// The data is advanced only if the value is complete.
+fn skip_value<'a>(data: &mut &'a [u8], number: u64, vt: u8) -> Option<&'a [u8]>
+{
+    let original = *data;
+    let complete = match vt {
+        0 => u64::from_unsigned_varint(data).is_some(),
+        1 => try_read_8_bytes(data).is_some(),
+        2 => usize::from_unsigned_varint(data)
+            .filter(|length| *length <= data.len())
+            .map(|length| *data = &data[length..])
+            .is_some(),
+        5 => try_read_4_bytes(data).is_some(),
+        3 => matches!(read_group(data, number), GroupBody::Complete(..)),
+        _ => false,
+    };
+
+    match complete {
+        true => Some(&original[..original.len() - data.len()]),
+        false => {
+            *data = original;
+            None
+        }
+    }
 }
 
 fn try_read_8_bytes(data: &mut &[u8]) -> Option<[u8; 8]>
@@ -536,20 +1287,7 @@ fn try_read_4_bytes(data: &mut &[u8]) -> Option<[u8; 4]>
     }
 }
 
-fn read_string(data: &mut &[u8]) -> Option<String>
-{
-    let original = *data;
-    let len = usize::from_unsigned_varint(data)?;
-    if len > data.len() {
-        *data = original;
-        return None;
-    }
-    let (str_data, remainder) = data.split_at(len);
-    *data = remainder;
-    Some(String::from_utf8_lossy(str_data).to_string())
-}
-
-fn read_bytes(data: &mut &[u8]) -> Option<Bytes>
+fn read_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]>
 {
     let original = *data;
     let len = usize::from_unsigned_varint(data)?;
@@ -557,9 +1295,9 @@ fn read_bytes(data: &mut &[u8]) -> Option<Bytes>
         *data = original;
         return None;
     }
-    let (str_data, remainder) = data.split_at(len);
+    let (bytes, remainder) = data.split_at(len);
     *data = remainder;
-    Some(Bytes::copy_from_slice(str_data))
+    Some(bytes)
 }
 
 impl MessageInfo
@@ -569,13 +1307,58 @@ impl MessageInfo
     /// Will **panic** if the message defined by the `MessageRef` does not exist in this context.
     /// Such panic means the `MessageRef` came from a different context. The panic is not
     /// guaranteed, as a message with an equal `MessageRef` may exist in multiple contexts.
-    pub fn decode(&self, mut data: &[u8], ctx: &Context) -> MessageValue
+    pub fn decode(&self, data: &[u8], ctx: &Context) -> MessageValue
+    {
+        self.decode_with_options(data, ctx, &DecodeOptions::default())
+    }
+
+    /// Decode a message with custom limits.
+    ///
+    /// Will **panic** if the message defined by the `MessageRef` does not exist in this context.
+    pub fn decode_with_options(
+        &self,
+        data: &[u8],
+        ctx: &Context,
+        options: &DecodeOptions,
+    ) -> MessageValue
+    {
+        let mut dec = Decoder {
+            ctx,
+            options,
+            source: Source(None),
+            depth: 0,
+            allocated: Arc::new(AtomicUsize::new(0)),
+        };
+        self.decode_message(data, &mut dec)
+    }
+
+    /// Decode a message from `Bytes` without copying.
+    ///
+    /// The `bytes` values, the unknown and incomplete values and the garbage share the memory of
+    /// the input.
+    ///
+    /// Will **panic** if the message defined by the `MessageRef` does not exist in this context.
+    pub fn decode_bytes(&self, data: &Bytes, ctx: &Context, options: &DecodeOptions)
+        -> MessageValue
+    {
+        let mut dec = Decoder {
+            ctx,
+            options,
+            source: Source(Some(data)),
+            depth: 0,
+            allocated: Arc::new(AtomicUsize::new(0)),
+        };
+        self.decode_message(data, &mut dec)
+    }
+
+    fn decode_message(&self, mut data: &[u8], dec: &mut Decoder) -> MessageValue
     {
         let mut msg = MessageValue {
             msg_ref: self.self_ref,
             fields: vec![],
             garbage: None,
         };
+        let mut counts = HashMap::new();
 
         loop {
             if data.is_empty() {
@@ -585,7 +1368,7 @@ impl MessageInfo
             let tag = match u64::from_unsigned_varint(&mut data) {
                 Some(tag) => tag,
                 None => {
-                    msg.garbage = Some(Bytes::copy_from_slice(data));
+                    msg.garbage = Some(dec.source.bytes(data));
                     break;
                 }
             };
@@ -593,21 +1376,62 @@ impl MessageInfo
             let number = tag >> 3;
             let wire_type = (tag & 0x07) as u8;
 
-            let value = match self.get_field(number) {
+            let field = self.get_field_or_extension(number, dec.ctx);
+
+            // Complete values are checked against the limits before decoding them.
+            let count = counts.entry(number).or_insert(0);
+            let mut cursor = data;
+            let raw = skip_value(&mut cursor, number, wire_type);
+            if let Some(raw) = raw {
+                if !dec.allocate(field, wire_type, raw) {
+                    let rest = dec.source.bytes(data);
+                    let value = Value::LimitExceeded(DecodeLimit::TotalBytes, wire_type, rest);
+                    msg.fields.push(FieldValue { number, value });
+                    break;
+                }
+                if let Some(limit) = dec.check(field, wire_type, raw, *count) {
+                    data = cursor;
+                    let value = Value::LimitExceeded(limit, wire_type, dec.source.bytes(raw));
+                    msg.fields.push(FieldValue { number, value });
+                    continue;
+                }
+            }
+
+            let value = match field {
                 Some(field) => {
-                    if field.multiplicity == Multiplicity::RepeatedPacked {
-                        if wire_type == 2 {
-                            Value::decode_packed(&mut data, wire_type, &field.field_type)
-                        } else {
-                            Value::decode_unknown(&mut data, wire_type)
-                        }
+                    if is_packed(field, wire_type) {
+                        Value::decode_packed(&mut data, wire_type, &field.field_type, dec.source)
+                    } else if let (Some(raw), ValueType::Message(mref), true) =
+                        (raw, &field.field_type, is_lazy(field, wire_type, dec))
+                    {
+                        data = cursor;
+                        Value::LazyMessage(Box::new(dec.lazy_message(*mref, raw)))
                     } else if field.field_type.wire_type() == wire_type {
-                        Value::decode(&mut data, wire_type, &field.field_type, ctx)
+                        let value =
+                            Value::decode(&mut data, number, wire_type, &field.field_type, dec)
+                                .check_closed_enum(dec.ctx);
+                        match field.multiplicity {
+                            Multiplicity::Map => value.into_map_entry(dec.ctx),
+                            _ => value,
+                        }
                     } else {
-                        Value::decode_unknown(&mut data, wire_type)
+                        Value::read_unknown(&mut data, number, wire_type, dec.source)
                     }
                 }
-                _ => Value::decode_unknown(&mut data, wire_type),
+                _ => Value::read_unknown(&mut data, number, wire_type, dec.source),
+            };
+
+            // Each element of a packed array counts towards the repeated limit.
+            *count += match &value {
+                Value::Packed(packed) => packed.len(),
+                _ => 1,
+            };
+            let value = match raw {
+                Some(raw) if *count > dec.options.max_repeated => {
+                    let raw = dec.source.bytes(raw);
+                    Value::LimitExceeded(DecodeLimit::Repeated, wire_type, raw)
+                }
+                _ => value,
             };
 
             msg.fields.push(FieldValue { number, value })
@@ -621,21 +1445,66 @@ impl MessageValue
 {
     /// Encodes a message value into protobuf wire format.
     ///
+    /// The garbage at the end of the message is written back as it was.
+    ///
     /// Will **panic** if the message defined by the `MessageRef` does not exist in this context.
     /// Such panic means the `MessageRef` came from a different context. The panic is not
     /// guaranteed, as a message with an equal `MessageRef` may exist in multiple contexts.
     pub fn encode(&self, ctx: &Context) -> bytes::BytesMut
     {
-        self.fields
+        let mut output: BytesMut = self
+            .fields
             .iter()
-            .filter_map(|f| f.value.encode(ctx).map(|(w, b)| (f, w, b)))
-            .flat_map(|(field, wire_type, bytes)| {
-                let tag = wire_type as u64 + (field.number << 3);
-                let mut field_data = tag.into_unsigned_varint();
-                field_data.extend_from_slice(&bytes);
-                field_data
-            })
-            .collect()
+            .flat_map(|f| encode_field(f.number, &f.value, ctx))
+            .collect();
+        if let Some(garbage) = &self.garbage {
+            output.extend_from_slice(garbage);
+        }
+        output
+    }
+}
+
+pub(crate) fn encode_field(number: u64, value: &Value, ctx: &Context) -> BytesMut
+{
+    match value.encode(ctx) {
+        Some((wire_type, bytes)) => {
+            let tag = wire_type as u64 + (number << 3);
+            let mut field_data = tag.into_unsigned_varint();
+            field_data.extend_from_slice(&bytes);
+            if let Value::Group(..) | Value::Unknown(UnknownValue::Group(..)) = value {
+                let end_tag = 4 + (number << 3);
+                field_data.extend_from_slice(&end_tag.into_unsigned_varint());
+            }
+            field_data
+        }
+        None => BytesMut::new(),
+    }
+}
+
+/// Decodes the data as a message consisting of unknown fields.
+///
+/// Returns `None` unless the data is a non-empty sequence of well formed fields.
+pub(crate) fn decode_unknown_fields(mut data: &[u8]) -> Option<Vec<FieldValue>>
+{
+    let mut fields = vec![];
+    while !data.is_empty() {
+        let tag = u64::from_unsigned_varint(&mut data)?;
+        let number = tag >> 3;
+        match Value::decode_unknown(&mut data, number, (tag & 0x07) as u8) {
+            value @ Value::Unknown(
+                UnknownValue::Varint(..)
+                | UnknownValue::Fixed64(..)
+                | UnknownValue::VariableLength(..)
+                | UnknownValue::Fixed32(..)
+                | UnknownValue::Group(..),
+            ) if number != 0 => fields.push(FieldValue { number, value }),
+            _ => return None,
+        }
+    }
+
+    match fields.is_empty() {
+        true => None,
+        false => Some(fields),
     }
 }
 
@@ -653,68 +1522,77 @@ impl UnknownValue
                 output
             }
             UnknownValue::Fixed32(v) => BytesMut::from(v.to_le_bytes().as_ref()),
+            UnknownValue::Group(v) => BytesMut::from(v.as_ref()),
             UnknownValue::Invalid(_, v) => BytesMut::from(v.as_ref()),
         }
     }
 }
 
-trait FromUnsignedVarint: Sized
+pub(crate) trait FromUnsignedVarint: Sized
 {
     fn from_unsigned_varint(data: &mut &[u8]) -> Option<Self>;
 }
 
-trait ToUnsignedVarint: Sized
+pub(crate) trait ToUnsignedVarint: Sized
 {
     fn into_unsigned_varint(self) -> BytesMut;
 }
 
-impl<T: Default + TryFrom<u64>> FromUnsignedVarint for T
-where
-    T::Error: Debug,
+/// Reads a varint.
+///
+/// Returns `None` without advancing the data if the varint is truncated, doesn't fit in 64 bits
+/// or doesn't fit in `T`.
+impl<T: TryFrom<u64>> FromUnsignedVarint for T
 {
     fn from_unsigned_varint(data: &mut &[u8]) -> Option<Self>
     {
         let mut result = 0u64;
-        let mut idx = 0;
-        loop {
-            if idx >= data.len() {
+        for (idx, b) in data.iter().enumerate().take(MAX_VARINT_LEN) {
+            let value = (b & 0x7f) as u64;
+
+            // The last byte holds only the highest bit of a 64-bit value.
+            if idx == MAX_VARINT_LEN - 1 && value > 1 {
                 return None;
             }
+            result |= value << (idx * 7);
 
-            let b = data[idx];
-            let value = (b & 0x7f) as u64;
-            result += value << (idx * 7);
-
-            idx += 1;
             if b & 0x80 == 0 {
-                break;
+                let result = T::try_from(result).ok()?;
+                *data = &data[idx + 1..];
+                return Some(result);
             }
         }
 
-        let result = T::try_from(result).expect("Out of range");
-        *data = &data[idx..];
-        Some(result)
+        None
     }
 }
 
-impl<T: Default + TryInto<u64>> ToUnsignedVarint for T
-where
-    T::Error: Debug,
-{
-    fn into_unsigned_varint(self) -> BytesMut
-    {
-        let mut value: u64 = self.try_into().unwrap();
-        let mut data: Vec<u8> = Vec::with_capacity(8);
-        loop {
-            let mut byte = (value & 0x7f) as u8;
-            value >>= 7;
-            if value > 0 {
-                byte |= 0x80;
-                data.push(byte);
-            } else {
-                data.push(byte);
-                break BytesMut::from(data.as_slice());
+macro_rules! impl_to_unsigned_varint {
+    ($($t:ty),*) => {
+        $(impl ToUnsignedVarint for $t
+        {
+            fn into_unsigned_varint(self) -> BytesMut
+            {
+                encode_varint(self as u128)
             }
+        })*
+    };
+}
+
+impl_to_unsigned_varint!(u32, u64, u128, usize);
+
+fn encode_varint(mut value: u128) -> BytesMut
+{
+    let mut data: Vec<u8> = Vec::with_capacity(8);
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value > 0 {
+            byte |= 0x80;
+            data.push(byte);
+        } else {
+            data.push(byte);
+            break BytesMut::from(data.as_slice());
         }
     }
 }
@@ -729,30 +1607,35 @@ trait ToSignedVarint: Sized
     fn into_signed_varint(self) -> BytesMut;
 }
 
-impl<T: Default + TryFrom<i64>> FromSignedVarint for T
-where
-    T::Error: Debug,
+/// Reads a varint.
+///
+/// Returns `None` without advancing the data if the varint is invalid or its value doesn't fit
+/// in `T`.
+impl<T: TryFrom<i64>> FromSignedVarint for T
 {
     fn from_signed_varint(data: &mut &[u8]) -> Option<Self>
     {
-        u64::from_unsigned_varint(data).map(|u| {
-            let signed: i64 = unsafe { std::mem::transmute(u) };
-            signed.try_into().unwrap()
-        })
+        let mut cursor = *data;
+        let value = T::try_from(u64::from_unsigned_varint(&mut cursor)? as i64).ok()?;
+        *data = cursor;
+        Some(value)
     }
 }
 
-impl<T: Default + TryInto<i64>> ToSignedVarint for T
-where
-    T::Error: Debug,
-{
-    fn into_signed_varint(self) -> BytesMut
-    {
-        let v: u64 = unsafe { std::mem::transmute(self.try_into().unwrap()) };
-        v.into_unsigned_varint()
-    }
+macro_rules! impl_to_signed_varint {
+    ($($t:ty),*) => {
+        $(impl ToSignedVarint for $t
+        {
+            fn into_signed_varint(self) -> BytesMut
+            {
+                (self as i64 as u64).into_unsigned_varint()
+            }
+        })*
+    };
 }
 
+impl_to_signed_varint!(i32, i64);
+
 #[cfg(test)]
 mod test
 {
@@ -0,0 +1,268 @@
+//! Length-delimited message streams.
+//!
+//! Streams written with Java's `writeDelimitedTo` or C++'s `SerializeDelimitedToOstream` consist
+//! of messages prefixed with their length as a varint. [`DelimitedDecoder`] splits such a stream
+//! held in memory into messages and [`DelimitedReader`] does the same for a [`Read`] source. Both
+//! yield a [`DelimitedItem`] for each message.
+//!
+//! A stream that ends in the middle of a message, such as a file that is still being written,
+//! ends with [`DelimitedItem::Incomplete`] that holds the remaining bytes, including the length
+//! prefix. Writing the items back with [`DelimitedWriter`] reproduces the original stream.
+//!
+//! ```
+//! use protofish::prelude::*;
+//! use protofish::delimited::{DelimitedDecoder, DelimitedItem};
+//!
+//! let context = Context::parse(&[r#"
+//!   syntax = "proto3";
+//!   message Fish { string name = 1; }
+//! "#]).unwrap();
+//! let fish = context.get_message("Fish").unwrap().self_ref;
+//!
+//! let data = b"\x07\x0a\x05Perch\x06\x0a\x04Pike\x07\x0a\x05Sal";
+//! let items = DelimitedDecoder::new(&data[..], fish, &context).collect::<Vec<_>>();
+//! assert_eq!(items.len(), 3);
+//! match &items[1] {
+//!     DelimitedItem::Message(msg) => {
+//!         assert_eq!(msg.fields[0].value, Value::String(String::from("Pike")))
+//!     }
+//!     item => panic!("Unexpected item: {:?}", item),
+//! }
+//! assert_eq!(items[2], DelimitedItem::Incomplete(b"\x07\x0a\x05Sal"[..].into()));
+//! ```
+
+use std::io::{self, Read, Write};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::context::{Context, MessageInfo, MessageRef};
+use crate::decode::{
+    DecodeOptions, FromUnsignedVarint, MessageValue, ToUnsignedVarint, MAX_VARINT_LEN,
+};
+
+/// Item of a length-delimited stream.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DelimitedItem
+{
+    /// Complete message.
+    Message(MessageValue),
+
+    /// Stream ended before the end of the message. Contains the remaining bytes of the stream,
+    /// including the length prefix.
+    Incomplete(Bytes),
+}
+
+/// Splits a length-delimited stream held in memory into messages.
+///
+/// Works with any [`Buf`], such as `&[u8]` or `Bytes`.
+pub struct DelimitedDecoder<'a, B>
+{
+    data: B,
+    info: &'a MessageInfo,
+    ctx: &'a Context,
+}
+
+/// Reads a length-delimited stream from a [`Read`] source message by message.
+pub struct DelimitedReader<'a, R>
+{
+    reader: R,
+    info: &'a MessageInfo,
+    ctx: &'a Context,
+    done: bool,
+}
+
+/// Writes a length-delimited stream.
+pub struct DelimitedWriter<W>
+{
+    writer: W,
+}
+
+/// Length prefix of a message.
+#[derive(Default)]
+struct LengthPrefix
+{
+    bytes: BytesMut,
+}
+
+impl LengthPrefix
+{
+    /// Adds the next byte of the varint. Returns the length once the varint is complete.
+    ///
+    /// Varints that are too long to be valid never complete.
+    fn push(&mut self, b: u8) -> Option<u64>
+    {
+        self.bytes.put_u8(b);
+        match b & 0x80 == 0 && self.bytes.len() <= MAX_VARINT_LEN {
+            true => u64::from_unsigned_varint(&mut &self.bytes[..]),
+            false => None,
+        }
+    }
+
+    /// Prefix followed by the bytes read after it.
+    fn incomplete(mut self, rest: &[u8]) -> DelimitedItem
+    {
+        self.bytes.extend_from_slice(rest);
+        DelimitedItem::Incomplete(self.bytes.freeze())
+    }
+}
+
+impl<'a, B: Buf> DelimitedDecoder<'a, B>
+{
+    /// Creates a decoder that decodes the messages as the `msg` type.
+    pub fn new(data: B, msg: MessageRef, ctx: &'a Context) -> Self
+    {
+        DelimitedDecoder {
+            data,
+            info: ctx.resolve_message(msg),
+            ctx,
+        }
+    }
+}
+
+impl<B: Buf> Iterator for DelimitedDecoder<'_, B>
+{
+    type Item = DelimitedItem;
+
+    fn next(&mut self) -> Option<DelimitedItem>
+    {
+        if !self.data.has_remaining() {
+            return None;
+        }
+
+        let mut prefix = LengthPrefix::default();
+        let len = loop {
+            if !self.data.has_remaining() {
+                return Some(prefix.incomplete(&[]));
+            }
+            if let Some(len) = prefix.push(self.data.get_u8()) {
+                break len;
+            }
+        };
+
+        if (self.data.remaining() as u64) < len {
+            let rest = self.data.copy_to_bytes(self.data.remaining());
+            return Some(prefix.incomplete(&rest));
+        }
+
+        let data = self.data.copy_to_bytes(len as usize);
+        let msg = self
+            .info
+            .decode_bytes(&data, self.ctx, &DecodeOptions::default());
+        Some(DelimitedItem::Message(msg))
+    }
+}
+
+impl<'a, R: Read> DelimitedReader<'a, R>
+{
+    /// Creates a reader that decodes the messages as the `msg` type.
+    pub fn new(reader: R, msg: MessageRef, ctx: &'a Context) -> Self
+    {
+        DelimitedReader {
+            reader,
+            info: ctx.resolve_message(msg),
+            ctx,
+            done: false,
+        }
+    }
+
+    /// Reads a single byte. Returns `None` at the end of the stream.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>
+    {
+        let mut byte = [0];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_item(&mut self) -> io::Result<Option<DelimitedItem>>
+    {
+        let mut prefix = LengthPrefix::default();
+        let len = loop {
+            match (self.read_byte()?, prefix.bytes.is_empty()) {
+                (None, true) => return Ok(None),
+                (None, false) => return Ok(Some(prefix.incomplete(&[]))),
+                (Some(b), _) => {
+                    if let Some(len) = prefix.push(b) {
+                        break len;
+                    }
+                }
+            }
+        };
+
+        // The length is not trusted for allocating the buffer up front.
+        let mut data = vec![];
+        (&mut self.reader).take(len).read_to_end(&mut data)?;
+        if (data.len() as u64) < len {
+            return Ok(Some(prefix.incomplete(&data)));
+        }
+
+        let data = Bytes::from(data);
+        let msg = self
+            .info
+            .decode_bytes(&data, self.ctx, &DecodeOptions::default());
+        Ok(Some(DelimitedItem::Message(msg)))
+    }
+}
+
+impl<R: Read> Iterator for DelimitedReader<'_, R>
+{
+    type Item = io::Result<DelimitedItem>;
+
+    fn next(&mut self) -> Option<io::Result<DelimitedItem>>
+    {
+        if self.done {
+            return None;
+        }
+
+        let item = self.read_item().transpose();
+        self.done = !matches!(item, Some(Ok(DelimitedItem::Message(..))));
+        item
+    }
+}
+
+impl<W: Write> DelimitedWriter<W>
+{
+    /// Creates a writer.
+    pub fn new(writer: W) -> Self
+    {
+        DelimitedWriter { writer }
+    }
+
+    /// Writes a message with its length prefix.
+    pub fn write(&mut self, msg: &MessageValue, ctx: &Context) -> io::Result<()>
+    {
+        self.writer.write_all(&msg.encode_delimited(ctx))
+    }
+
+    /// Writes an item. Incomplete items are written as is.
+    pub fn write_item(&mut self, item: &DelimitedItem, ctx: &Context) -> io::Result<()>
+    {
+        match item {
+            DelimitedItem::Message(msg) => self.write(msg, ctx),
+            DelimitedItem::Incomplete(data) => self.writer.write_all(data),
+        }
+    }
+
+    /// Gets the underlying writer.
+    pub fn into_inner(self) -> W
+    {
+        self.writer
+    }
+}
+
+impl MessageValue
+{
+    /// Encodes the message with a varint length prefix.
+    pub fn encode_delimited(&self, ctx: &Context) -> BytesMut
+    {
+        let data = self.encode(ctx);
+        let mut output = data.len().into_unsigned_varint();
+        output.extend_from_slice(&data);
+        output
+    }
+}
@@ -0,0 +1,150 @@
+use std::io;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "snappy",
+    feature = "zstd"
+))]
+use std::io::Read;
+#[cfg(any(feature = "gzip", feature = "deflate", feature = "snappy"))]
+use std::io::Write;
+
+/// Message compression algorithm negotiated with the `grpc-encoding` header.
+///
+/// The algorithms other than `identity` require the Cargo feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Compression
+{
+    /// No compression.
+    Identity,
+
+    /// Gzip. Requires the `gzip` feature.
+    Gzip,
+
+    /// Zlib format, which HTTP calls `deflate`. Requires the `deflate` feature.
+    Deflate,
+
+    /// Snappy framing format. Requires the `snappy` feature.
+    Snappy,
+
+    /// Zstandard. Requires the `zstd` feature.
+    Zstd,
+}
+
+impl Compression
+{
+    /// Gets the algorithm by its `grpc-encoding` name.
+    pub fn from_name(name: &str) -> Option<Self>
+    {
+        Some(match name.trim().to_ascii_lowercase().as_str() {
+            "identity" => Compression::Identity,
+            "gzip" => Compression::Gzip,
+            "deflate" => Compression::Deflate,
+            "snappy" => Compression::Snappy,
+            "zstd" => Compression::Zstd,
+            _ => return None,
+        })
+    }
+
+    /// Name of the algorithm in the `grpc-encoding` header.
+    pub fn name(self) -> &'static str
+    {
+        match self {
+            Compression::Identity => "identity",
+            Compression::Gzip => "gzip",
+            Compression::Deflate => "deflate",
+            Compression::Snappy => "snappy",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// True, if the algorithm was enabled when building the crate.
+    pub fn is_supported(self) -> bool
+    {
+        match self {
+            Compression::Identity => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Deflate => cfg!(feature = "deflate"),
+            Compression::Snappy => cfg!(feature = "snappy"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    /// Compresses the data. Returns `None` if the algorithm isn't supported.
+    pub(crate) fn compress(self, data: &[u8]) -> Option<io::Result<Vec<u8>>>
+    {
+        Some(match self {
+            Compression::Identity => Ok(data.to_vec()),
+
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.finish())
+            }
+
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.finish())
+            }
+
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => {
+                let mut encoder = snap::write::FrameEncoder::new(vec![]);
+                encoder
+                    .write_all(data)
+                    .and_then(|_| encoder.into_inner().map_err(|e| e.into_error()))
+            }
+
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::encode_all(data, 0),
+
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        })
+    }
+
+    /// Decompresses the data. Returns `None` if the algorithm isn't supported.
+    ///
+    /// At most `limit + 1` bytes are decompressed, which is enough to tell whether the data
+    /// exceeds the limit without decompressing all of it.
+    pub(crate) fn decompress(self, data: &[u8], limit: usize) -> Option<io::Result<Vec<u8>>>
+    {
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "snappy",
+            feature = "zstd"
+        ))]
+        fn read_all(reader: impl Read, limit: usize) -> io::Result<Vec<u8>>
+        {
+            let mut output = vec![];
+            let limit = (limit as u64).saturating_add(1);
+            reader.take(limit).read_to_end(&mut output).map(|_| output)
+        }
+
+        Some(match self {
+            Compression::Identity => Ok(data[..data.len().min(limit.saturating_add(1))].to_vec()),
+
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => read_all(flate2::read::GzDecoder::new(data), limit),
+
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => read_all(flate2::read::ZlibDecoder::new(data), limit),
+
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => read_all(snap::read::FrameDecoder::new(data), limit),
+
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::read::Decoder::new(data).and_then(|d| read_all(d, limit))
+            }
+
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        })
+    }
+}
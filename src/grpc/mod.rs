@@ -0,0 +1,391 @@
+//! gRPC message framing.
+//!
+//! gRPC sends each message prefixed with a 5-byte header: a compressed flag followed by the
+//! length of the message as a big-endian `u32`. The HTTP/2 DATA frames that carry the messages
+//! don't follow the message boundaries, so a single message may be split across multiple chunks
+//! and a single chunk may contain multiple messages.
+//!
+//! [`FrameDecoder`] buffers the chunks of a stream and splits them into [`Frame`]s, which can be
+//! decoded as the input or the output message of an rpc with [`Frame::decode`]. Messages are
+//! framed with [`MessageValue::encode_frame`] or [`Frame::encode`].
+//!
+//! The decoder rejects frames longer than 4 MiB, the default maximum message size of gRPC, before
+//! buffering them. A different limit can be set with [`FrameDecoder::with_limit`].
+//!
+//! Compressed frames are decompressed with [`Frame::decompress`] using the [`Compression`]
+//! negotiated with the `grpc-encoding` header before decoding them. The compression algorithms
+//! are enabled with the `gzip`, `deflate`, `snappy` and `zstd` Cargo features. Untrusted frames
+//! should be decompressed with [`Frame::decompress_with_options`], which limits the size of the
+//! decompressed message.
+//!
+//! ```
+//! use protofish::prelude::*;
+//! use protofish::grpc::{Direction, FrameDecoder};
+//!
+//! let context = Context::parse(&[r#"
+//!   syntax = "proto3";
+//!   message Request { string kind = 1; }
+//!   message Response { int32 distance = 1; }
+//!   service Fish { rpc Swim( Request ) returns ( Response ); }
+//! "#]).unwrap();
+//! let rpc = context.get_service("Fish").unwrap().rpc_by_name("Swim").unwrap();
+//!
+//! let mut decoder = FrameDecoder::new();
+//! decoder.push(&b"\x00\x00\x00\x00\x07\x0a\x05Pe"[..]);
+//! assert!(decoder.next_frame().unwrap().is_none());
+//!
+//! decoder.push(&b"rch"[..]);
+//! let frame = decoder.next_frame().unwrap().unwrap();
+//! let request = frame.decode(rpc, Direction::Request, &context).unwrap();
+//! assert_eq!(request.fields[0].value, Value::String(String::from("Perch")));
+//! ```
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use snafu::Snafu;
+use std::convert::TryFrom;
+
+use crate::context::{Context, Rpc, RpcArg};
+use crate::decode::{DecodeOptions, MessageValue};
+
+mod compression;
+pub use compression::Compression;
+
+/// Length of the frame header.
+const HEADER_LEN: usize = 5;
+
+/// Default limit of the frame length.
+const DEFAULT_MAX_FRAME_LEN: usize = 4 * 1024 * 1024;
+
+/// Error decoding a gRPC frame.
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum GrpcError
+{
+    /// Frame is compressed and must be decompressed before decoding.
+    #[snafu(display("Frame is compressed"))]
+    Compressed
+    {
+        /// Compressed payload of the frame.
+        data: Bytes,
+    },
+
+    /// Compression algorithm is not enabled in this build.
+    #[snafu(display("Unsupported encoding '{}'", encoding))]
+    UnsupportedEncoding
+    {
+        /// Name of the algorithm.
+        encoding: &'static str,
+
+        /// Payload of the frame.
+        data: Bytes,
+    },
+
+    /// Compressing or decompressing the frame failed.
+    #[snafu(display("Invalid '{}' data: {}", encoding, source))]
+    InvalidCompressedData
+    {
+        /// Name of the algorithm.
+        encoding: &'static str,
+
+        /// Payload of the frame.
+        data: Bytes,
+
+        /// Source error.
+        source: std::io::Error,
+    },
+
+    /// Decompressed frame is larger than the `max_total_bytes` limit.
+    #[snafu(display("Decompressed '{}' data exceeds {} bytes", encoding, limit))]
+    DecompressedSizeExceeded
+    {
+        /// Name of the algorithm.
+        encoding: &'static str,
+
+        /// Limit of the decompressed size.
+        limit: usize,
+
+        /// Payload of the frame.
+        data: Bytes,
+    },
+
+    /// Frame is longer than the limit of the frame length.
+    #[snafu(display("Frame of {} bytes exceeds the limit of {} bytes", len, limit))]
+    FrameTooLarge
+    {
+        /// Length of the frame without the header.
+        len: usize,
+
+        /// Limit of the frame length.
+        limit: usize,
+    },
+}
+
+/// Direction of a message within an rpc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction
+{
+    /// Message from the client to the server. Decoded as the rpc input.
+    Request,
+
+    /// Message from the server to the client. Decoded as the rpc output.
+    Response,
+}
+
+/// A length-prefixed gRPC message.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Frame
+{
+    /// True, if the message is compressed.
+    pub compressed: bool,
+
+    /// Message payload without the frame header.
+    pub data: Bytes,
+}
+
+/// Incremental decoder that splits a stream into frames.
+#[derive(Debug, Clone)]
+pub struct FrameDecoder
+{
+    buffer: BytesMut,
+    max_frame_len: usize,
+}
+
+impl Rpc
+{
+    /// Gets the input or the output of the rpc.
+    pub fn arg(&self, direction: Direction) -> &RpcArg
+    {
+        match direction {
+            Direction::Request => &self.input,
+            Direction::Response => &self.output,
+        }
+    }
+}
+
+impl Frame
+{
+    /// Creates an uncompressed frame.
+    pub fn new(data: Bytes) -> Self
+    {
+        Frame {
+            compressed: false,
+            data,
+        }
+    }
+
+    /// Encodes the frame with the header.
+    ///
+    /// Fails if the payload is too long for the 32-bit length of the header.
+    pub fn encode(&self) -> Result<Bytes, GrpcError>
+    {
+        let len = u32::try_from(self.data.len()).map_err(|_| GrpcError::FrameTooLarge {
+            len: self.data.len(),
+            limit: u32::MAX as usize,
+        })?;
+
+        let mut output = BytesMut::with_capacity(HEADER_LEN + self.data.len());
+        output.put_u8(self.compressed as u8);
+        output.put_u32(len);
+        output.put_slice(&self.data);
+        Ok(output.freeze())
+    }
+
+    /// Decodes the frame as the message of the rpc in the given direction.
+    ///
+    /// Compressed frames must be decompressed first.
+    pub fn decode(
+        &self,
+        rpc: &Rpc,
+        direction: Direction,
+        ctx: &Context,
+    ) -> Result<MessageValue, GrpcError>
+    {
+        self.decode_with_options(rpc, direction, ctx, &DecodeOptions::default())
+    }
+
+    /// Decodes the frame with custom limits.
+    pub fn decode_with_options(
+        &self,
+        rpc: &Rpc,
+        direction: Direction,
+        ctx: &Context,
+        options: &DecodeOptions,
+    ) -> Result<MessageValue, GrpcError>
+    {
+        if self.compressed {
+            return Err(GrpcError::Compressed {
+                data: self.data.clone(),
+            });
+        }
+
+        let msg = rpc.arg(direction).message;
+        Ok(ctx.decode_bytes(msg, &self.data, options))
+    }
+
+    /// Decompresses a compressed frame. Uncompressed frames are returned as is.
+    ///
+    /// The errors include the payload of the frame, which allows falling back to the raw data.
+    pub fn decompress(&self, compression: Compression) -> Result<Frame, GrpcError>
+    {
+        self.decompress_with_options(compression, &DecodeOptions::default())
+    }
+
+    /// Decompresses a compressed frame with custom limits.
+    ///
+    /// The decompressed message may not be larger than `max_total_bytes`. Decompression stops
+    /// as soon as the limit is exceeded, so a small frame cannot expand into an arbitrarily large
+    /// buffer.
+    pub fn decompress_with_options(
+        &self,
+        compression: Compression,
+        options: &DecodeOptions,
+    ) -> Result<Frame, GrpcError>
+    {
+        if !self.compressed {
+            return Ok(self.clone());
+        }
+
+        let limit = options.max_total_bytes;
+        let data = self.convert(compression, compression.decompress(&self.data, limit))?;
+        if data.len() > limit {
+            return Err(GrpcError::DecompressedSizeExceeded {
+                encoding: compression.name(),
+                limit,
+                data: self.data.clone(),
+            });
+        }
+        Ok(Frame::new(data))
+    }
+
+    /// Compresses an uncompressed frame. `Identity` compression leaves the frame uncompressed.
+    pub fn compress(&self, compression: Compression) -> Result<Frame, GrpcError>
+    {
+        if self.compressed || compression == Compression::Identity {
+            return Ok(self.clone());
+        }
+
+        let data = self.convert(compression, compression.compress(&self.data))?;
+        Ok(Frame {
+            compressed: true,
+            data,
+        })
+    }
+
+    fn convert(
+        &self,
+        compression: Compression,
+        result: Option<std::io::Result<Vec<u8>>>,
+    ) -> Result<Bytes, GrpcError>
+    {
+        match result {
+            Some(Ok(data)) => Ok(data.into()),
+            Some(Err(source)) => Err(GrpcError::InvalidCompressedData {
+                encoding: compression.name(),
+                data: self.data.clone(),
+                source,
+            }),
+            None => Err(GrpcError::UnsupportedEncoding {
+                encoding: compression.name(),
+                data: self.data.clone(),
+            }),
+        }
+    }
+}
+
+impl Default for FrameDecoder
+{
+    fn default() -> Self
+    {
+        Self::with_limit(DEFAULT_MAX_FRAME_LEN)
+    }
+}
+
+impl FrameDecoder
+{
+    /// Creates a decoder with an empty buffer that accepts frames of up to 4 MiB.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Creates a decoder with an empty buffer that accepts frames of up to `max_frame_len` bytes
+    /// without the header.
+    pub fn with_limit(max_frame_len: usize) -> Self
+    {
+        Self {
+            buffer: BytesMut::new(),
+            max_frame_len,
+        }
+    }
+
+    /// Appends a chunk of the stream to the buffer.
+    pub fn push(&mut self, chunk: impl Buf)
+    {
+        self.buffer.put(chunk);
+    }
+
+    /// Takes the next complete frame from the buffer.
+    ///
+    /// Returns `None` if the buffer doesn't contain a complete frame yet. Fails as soon as the
+    /// header of a frame longer than the limit is buffered. The stream can't be decoded past such
+    /// frame, so the error is returned again on the following calls.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, GrpcError>
+    {
+        if self.buffer.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes([
+            self.buffer[1],
+            self.buffer[2],
+            self.buffer[3],
+            self.buffer[4],
+        ]) as usize;
+        if len > self.max_frame_len {
+            return Err(GrpcError::FrameTooLarge {
+                len,
+                limit: self.max_frame_len,
+            });
+        }
+        if self.buffer.len() - HEADER_LEN < len {
+            return Ok(None);
+        }
+
+        let compressed = self.buffer[0] != 0;
+        self.buffer.advance(HEADER_LEN);
+        Ok(Some(Frame {
+            compressed,
+            data: self.buffer.split_to(len).freeze(),
+        }))
+    }
+
+    /// Gets the buffered data that doesn't form a complete frame yet.
+    ///
+    /// Data left in the buffer at the end of the stream belongs to a truncated frame.
+    pub fn remaining(&self) -> &[u8]
+    {
+        &self.buffer
+    }
+}
+
+impl MessageValue
+{
+    /// Encodes the message as an uncompressed gRPC frame.
+    pub fn encode_frame(&self, ctx: &Context) -> Result<Bytes, GrpcError>
+    {
+        Frame::new(self.encode(ctx).freeze()).encode()
+    }
+
+    /// Encodes the message as a gRPC frame compressed with the given algorithm.
+    pub fn encode_compressed_frame(
+        &self,
+        compression: Compression,
+        ctx: &Context,
+    ) -> Result<Bytes, GrpcError>
+    {
+        Frame::new(self.encode(ctx).freeze())
+            .compress(compression)?
+            .encode()
+    }
+}
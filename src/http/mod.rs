@@ -0,0 +1,206 @@
+//! HTTP/JSON transcoding.
+//!
+//! Rpcs can be exposed as REST endpoints with the [`google.api.http`][http] option. The option
+//! binds the rpc to an HTTP method and a path template, such as
+//! `get: "/v1/{name=shelves/*/books/*}"`, and optionally names the field that holds the request
+//! body. Additional bindings are listed in `additional_bindings`.
+//!
+//! [`Context::decode_http_request`](crate::context::Context::decode_http_request) finds the rpc
+//! that matches an HTTP request and builds its input message:
+//!
+//! - The variables of the path template set the fields they name.
+//! - A `body: "*"` binding reads the whole message from the JSON body. Otherwise the body is
+//!   stored in the field named by `body` and the query parameters set the remaining fields,
+//!   such as `?page_size=10&filter.state=ACTIVE`. Repeated fields can be given multiple times.
+//!   Query parameters that don't match a field are ignored.
+//!
+//! [`MessageValue::to_http_request`](crate::decode::MessageValue::to_http_request) does the
+//! reverse and renders an input message as an HTTP request using the first binding that can
+//! represent it.
+//!
+//! The bindings are read from the options as written in the proto files and don't require the
+//! `google/api/http.proto` definitions to be present in the context. Custom options are not
+//! preserved in the descriptors produced by `protoc`, so contexts built from such descriptors
+//! have no bindings.
+//!
+//! [http]: https://github.com/googleapis/googleapis/blob/master/google/api/http.proto
+
+use bytes::Bytes;
+use serde_json::Value as JsonValue;
+use snafu::Snafu;
+
+use crate::context::{Constant, ProtoOption, Rpc};
+use crate::json::JsonError;
+use template::Template;
+
+mod template;
+mod transcode;
+
+/// Name of the option that holds the HTTP bindings.
+const HTTP_OPTION: &str = "(google.api.http)";
+
+/// An HTTP binding of an rpc.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct HttpRule
+{
+    /// HTTP method in upper case, such as `GET`, or the kind of a custom binding.
+    pub method: String,
+
+    /// Path template.
+    pub path: String,
+
+    /// Field that holds the request body. `*` for the whole message.
+    pub body: Option<String>,
+
+    /// Field that holds the response body.
+    pub response_body: Option<String>,
+
+    template: Template,
+}
+
+/// An HTTP request transcoded from a message.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct HttpRequest
+{
+    /// HTTP method.
+    pub method: String,
+
+    /// Request path, including the query string.
+    pub path: String,
+
+    /// JSON body of the request.
+    pub body: Option<JsonValue>,
+}
+
+/// Error transcoding an HTTP request.
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum HttpError
+{
+    /// No HTTP binding matches the request.
+    #[snafu(display("No HTTP binding for '{} {}'", method, path))]
+    NoMatchingBinding
+    {
+        /// HTTP method of the request.
+        method: String,
+
+        /// Path of the request.
+        path: String,
+    },
+
+    /// Path template refers to a field that the input message doesn't have.
+    #[snafu(display("Unknown field '{}' in the path of '{} {}'", name, method, path))]
+    UnknownField
+    {
+        /// Field path of the variable.
+        name: String,
+
+        /// HTTP method of the binding.
+        method: String,
+
+        /// Path template of the binding.
+        path: String,
+    },
+
+    /// Request could not be represented as the input message.
+    #[snafu(display("Invalid request: {}", source))]
+    InvalidRequest
+    {
+        /// Source error.
+        source: JsonError,
+    },
+
+    /// None of the HTTP bindings of the rpc can represent the message.
+    #[snafu(display("No HTTP binding of '{}' can represent the message", rpc))]
+    NoBinding
+    {
+        /// Name of the rpc.
+        rpc: String,
+    },
+}
+
+impl Rpc
+{
+    /// Gets the HTTP bindings of the rpc from the `google.api.http` option.
+    ///
+    /// The primary binding comes first, followed by the additional bindings. Bindings with
+    /// invalid path templates are left out.
+    pub fn http_rules(&self) -> Vec<HttpRule>
+    {
+        let mut fields = vec![];
+        for ProtoOption { name, value } in &self.options {
+            match (name.strip_prefix(HTTP_OPTION), value) {
+                (Some(""), Constant::Aggregate(f)) => fields.extend(f.iter().cloned()),
+
+                // The fields can also be set one by one: `(google.api.http).get = "/v1/books"`.
+                (Some(field), value) => {
+                    if let Some(field) = field.strip_prefix('.') {
+                        fields.push((field.to_string(), value.clone()));
+                    }
+                }
+                (None, _) => {}
+            }
+        }
+
+        let mut rules = vec![];
+        push_rules(&fields, &mut rules);
+        rules
+    }
+}
+
+/// Collects the binding described by the fields of a `google.api.HttpRule` and its additional
+/// bindings.
+fn push_rules(fields: &[(String, Constant)], rules: &mut Vec<HttpRule>)
+{
+    let string = |v: &Bytes| String::from_utf8_lossy(v).into_owned();
+
+    let mut pattern = None;
+    let mut body = None;
+    let mut response_body = None;
+    let mut additional_bindings = vec![];
+    for (name, value) in fields {
+        match (name.as_str(), value) {
+            ("get", Constant::String(p))
+            | ("put", Constant::String(p))
+            | ("post", Constant::String(p))
+            | ("delete", Constant::String(p))
+            | ("patch", Constant::String(p)) => pattern = Some((name.to_uppercase(), string(p))),
+            ("custom", Constant::Aggregate(custom)) => {
+                let field = |field| {
+                    custom.iter().find_map(|(name, value)| match value {
+                        Constant::String(v) if name == field => Some(string(v)),
+                        _ => None,
+                    })
+                };
+                if let (Some(kind), Some(path)) = (field("kind"), field("path")) {
+                    pattern = Some((kind, path));
+                }
+            }
+            ("body", Constant::String(v)) if !v.is_empty() => body = Some(string(v)),
+            ("response_body", Constant::String(v)) if !v.is_empty() => {
+                response_body = Some(string(v))
+            }
+            ("additional_bindings", Constant::Aggregate(binding)) => {
+                additional_bindings.push(binding)
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((method, path)) = pattern {
+        if let Some(template) = Template::parse(&path) {
+            rules.push(HttpRule {
+                method,
+                path,
+                body,
+                response_body,
+                template,
+            });
+        }
+    }
+    for binding in additional_bindings {
+        push_rules(binding, rules);
+    }
+}
@@ -0,0 +1,263 @@
+use pest::{iterators::Pair, Parser};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "http/template.pest"]
+struct TemplateParser;
+
+/// Path template of an HTTP binding, such as `/v1/{name=shelves/*/books/*}:publish`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Template
+{
+    segments: Vec<Segment>,
+    verb: Option<String>,
+
+    /// Field paths of the variables.
+    variables: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment
+{
+    kind: SegmentKind,
+
+    /// Index of the variable the segment belongs to.
+    variable: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SegmentKind
+{
+    Literal(String),
+    Wildcard,
+    DeepWildcard,
+}
+
+impl Template
+{
+    /// Parses a path template. Returns `None` for invalid templates.
+    pub(crate) fn parse(text: &str) -> Option<Self>
+    {
+        let template = TemplateParser::parse(Rule::template, text).ok()?.next()?;
+
+        let mut output = Template {
+            segments: vec![],
+            verb: None,
+            variables: vec![],
+        };
+        for p in template.into_inner() {
+            match p.as_rule() {
+                Rule::variable => {
+                    let variable = Some(output.variables.len());
+                    let mut inner = p.into_inner();
+                    let path = inner.next().unwrap().as_str();
+                    output
+                        .variables
+                        .push(path.split('.').map(String::from).collect());
+
+                    // `{name}` is a shorthand for `{name=*}`.
+                    let start = output.segments.len();
+                    output.segments.extend(inner.map(|s| Segment {
+                        kind: SegmentKind::parse(s),
+                        variable,
+                    }));
+                    if output.segments.len() == start {
+                        output.segments.push(Segment {
+                            kind: SegmentKind::Wildcard,
+                            variable,
+                        });
+                    }
+                }
+                Rule::verb => {
+                    output.verb = Some(p.into_inner().next().unwrap().as_str().to_string())
+                }
+                Rule::EOI => {}
+                _ => output.segments.push(Segment {
+                    kind: SegmentKind::parse(p),
+                    variable: None,
+                }),
+            }
+        }
+
+        // The matching doesn't support backtracking over multiple deep wildcards.
+        let deep_wildcards = output
+            .segments
+            .iter()
+            .filter(|s| s.kind == SegmentKind::DeepWildcard)
+            .count();
+        match deep_wildcards {
+            0 | 1 => Some(output),
+            _ => None,
+        }
+    }
+
+    /// Matches a request path, without the query, against the template.
+    ///
+    /// Returns the field paths of the variables with their percent-decoded values.
+    pub(crate) fn match_path(&self, path: &str) -> Option<Vec<(&[String], String)>>
+    {
+        let mut path = path.strip_prefix('/')?;
+        if let Some(verb) = &self.verb {
+            path = path.strip_suffix(verb.as_str())?.strip_suffix(':')?;
+        }
+        let parts = match path.is_empty() {
+            true => vec![],
+            false => path.split('/').collect::<Vec<_>>(),
+        };
+
+        // Segments before and after the deep wildcard are matched one to one. The deep wildcard
+        // covers whatever remains between them.
+        let deep = self
+            .segments
+            .iter()
+            .position(|s| s.kind == SegmentKind::DeepWildcard);
+        let before = deep.unwrap_or(self.segments.len());
+        let after = self.segments.len() - deep.map(|d| d + 1).unwrap_or(self.segments.len());
+        match deep {
+            Some(_) if parts.len() < before + after => return None,
+            None if parts.len() != before => return None,
+            _ => {}
+        }
+
+        let mut ranges = Vec::with_capacity(self.segments.len());
+        for (idx, segment) in self.segments.iter().enumerate() {
+            let range = if Some(idx) == deep {
+                before..parts.len() - after
+            } else if idx < before {
+                idx..idx + 1
+            } else {
+                let part = parts.len() - (self.segments.len() - idx);
+                part..part + 1
+            };
+
+            if let SegmentKind::Literal(literal) = &segment.kind {
+                if percent_decode(parts[range.start], false) != *literal {
+                    return None;
+                }
+            }
+            ranges.push(range);
+        }
+
+        let values = self
+            .variables
+            .iter()
+            .enumerate()
+            .map(|(idx, path)| {
+                // The segments of a variable are consecutive.
+                let (start, end) = self
+                    .segments
+                    .iter()
+                    .zip(&ranges)
+                    .filter(|(s, _)| s.variable == Some(idx))
+                    .fold((usize::MAX, 0), |(start, end), (_, r)| {
+                        (start.min(r.start), end.max(r.end))
+                    });
+                let value = parts[start..end]
+                    .iter()
+                    .map(|p| percent_decode(p, false))
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (path.as_slice(), value)
+            })
+            .collect();
+        Some(values)
+    }
+
+    /// Renders a request path with the variable values given by `value`.
+    ///
+    /// Returns `None` if a variable has no value or the template has wildcards outside the
+    /// variables.
+    pub(crate) fn render(
+        &self,
+        mut value: impl FnMut(&[String]) -> Option<String>,
+    ) -> Option<String>
+    {
+        let mut output = String::new();
+        let mut segments = self.segments.iter().peekable();
+        while let Some(segment) = segments.next() {
+            output.push('/');
+            match (segment.variable, &segment.kind) {
+                (None, SegmentKind::Literal(literal)) => output.push_str(literal),
+                (None, _) => return None,
+                (Some(variable), kind) => {
+                    let value = value(&self.variables[variable])?;
+
+                    // Variables that span multiple segments keep the slashes of the value.
+                    let mut multiple = *kind == SegmentKind::DeepWildcard;
+                    while segments.peek().map(|s| s.variable) == Some(Some(variable)) {
+                        segments.next();
+                        multiple = true;
+                    }
+                    match multiple {
+                        true => {
+                            let parts = value.split('/').map(percent_encode);
+                            output.push_str(&parts.collect::<Vec<_>>().join("/"));
+                        }
+                        false => output.push_str(&percent_encode(&value)),
+                    }
+                }
+            }
+        }
+
+        if output.is_empty() {
+            output.push('/');
+        }
+        if let Some(verb) = &self.verb {
+            output.push(':');
+            output.push_str(verb);
+        }
+        Some(output)
+    }
+}
+
+impl SegmentKind
+{
+    fn parse(p: Pair<Rule>) -> Self
+    {
+        match p.as_rule() {
+            Rule::literal => SegmentKind::Literal(p.as_str().to_string()),
+            Rule::wildcard => SegmentKind::Wildcard,
+            Rule::deepWildcard => SegmentKind::DeepWildcard,
+            r => unreachable!("{:?}: {:?}", r, p),
+        }
+    }
+}
+
+/// Percent-encodes everything except the unreserved characters of RFC 3986.
+pub(crate) fn percent_encode(value: &str) -> String
+{
+    let mut output = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                output.push(b as char)
+            }
+            _ => output.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    output
+}
+
+/// Decodes percent-encoded characters. Query strings also encode spaces as `+`.
+pub(crate) fn percent_decode(value: &str, query: bool) -> String
+{
+    let hex = |b: Option<&u8>| (*b? as char).to_digit(16);
+
+    let bytes = value.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' => match (hex(bytes.get(idx + 1)), hex(bytes.get(idx + 2))) {
+                (Some(high), Some(low)) => {
+                    output.push((high * 16 + low) as u8);
+                    idx += 2;
+                }
+                _ => output.push(b'%'),
+            },
+            b'+' if query => output.push(b' '),
+            b => output.push(b),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
@@ -0,0 +1,308 @@
+use serde_json::{Map, Value as JsonValue};
+
+use super::template::{percent_decode, percent_encode};
+use super::{HttpError, HttpRequest, HttpRule};
+use crate::context::*;
+use crate::decode::MessageValue;
+use crate::json::JsonError;
+
+impl Context
+{
+    /// Finds the rpc that matches an HTTP request and converts the request into its input
+    /// message.
+    ///
+    /// The `path` may include the query string. See the [`http`](crate::http) module for the
+    /// mapping rules.
+    pub fn decode_http_request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&JsonValue>,
+    ) -> Result<(&Service, &Rpc, MessageValue), HttpError>
+    {
+        for service in self.iter_services() {
+            match service.decode_http_request(method, path, body, self) {
+                Err(HttpError::NoMatchingBinding { .. }) => continue,
+                result => return result.map(|(rpc, msg)| (service, rpc, msg)),
+            }
+        }
+
+        Err(HttpError::NoMatchingBinding {
+            method: method.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl Service
+{
+    /// Finds the rpc of the service that matches an HTTP request and converts the request into
+    /// its input message.
+    ///
+    /// The `path` may include the query string. See the [`http`](crate::http) module for the
+    /// mapping rules.
+    pub fn decode_http_request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&JsonValue>,
+        ctx: &Context,
+    ) -> Result<(&Rpc, MessageValue), HttpError>
+    {
+        let (request_path, query) = match path.find('?') {
+            Some(idx) => (&path[..idx], Some(&path[idx + 1..])),
+            None => (path, None),
+        };
+
+        for rpc in &self.rpcs {
+            for rule in rpc.http_rules() {
+                if !rule.method.eq_ignore_ascii_case(method) {
+                    continue;
+                }
+                if let Some(variables) = rule.template.match_path(request_path) {
+                    let info = ctx.resolve_message(rpc.input.message);
+                    let msg = decode_request(&rule, info, variables, query, body, ctx)?;
+                    return Ok((rpc, msg));
+                }
+            }
+        }
+
+        Err(HttpError::NoMatchingBinding {
+            method: method.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl MessageValue
+{
+    /// Renders the message as an HTTP request to the `rpc` it is the input of.
+    ///
+    /// The request uses the first HTTP binding whose path template matches the values of the
+    /// path variables.
+    /// See the [`http`](crate::http) module for the mapping rules.
+    pub fn to_http_request(&self, rpc: &Rpc, ctx: &Context) -> Result<HttpRequest, HttpError>
+    {
+        let info = ctx.resolve_message(self.msg_ref);
+        let json = match self.to_json(ctx) {
+            JsonValue::Object(object) => object,
+            _ => Map::new(),
+        };
+
+        for rule in rpc.http_rules() {
+            let mut object = json.clone();
+            let path = match rule
+                .template
+                .render(|path| take_param(&mut object, info, path, ctx))
+            {
+                Some(path) if rule.template.match_path(&path).is_some() => path,
+                _ => continue,
+            };
+
+            let body = match rule.body.as_deref() {
+                Some("*") => return Ok(request(&rule, path, Some(JsonValue::Object(object)))),
+                Some(field) => info
+                    .get_field_by_name(field)
+                    .and_then(|f| object.remove(&f.json_name)),
+                None => None,
+            };
+
+            let mut query = vec![];
+            push_query(&mut query, "", &JsonValue::Object(object));
+            let path = match query.is_empty() {
+                true => path,
+                false => format!("{}?{}", path, query.join("&")),
+            };
+            return Ok(request(&rule, path, body));
+        }
+
+        Err(HttpError::NoBinding {
+            rpc: rpc.name.clone(),
+        })
+    }
+}
+
+fn request(rule: &HttpRule, path: String, body: Option<JsonValue>) -> HttpRequest
+{
+    HttpRequest {
+        method: rule.method.clone(),
+        path,
+        body,
+    }
+}
+
+fn decode_request(
+    rule: &HttpRule,
+    info: &MessageInfo,
+    variables: Vec<(&[String], String)>,
+    query: Option<&str>,
+    body: Option<&JsonValue>,
+    ctx: &Context,
+) -> Result<MessageValue, HttpError>
+{
+    let mut object = Map::new();
+    match (rule.body.as_deref(), body) {
+        (Some("*"), Some(body)) => match body {
+            JsonValue::Object(body) => object = body.clone(),
+            _ => {
+                return Err(HttpError::InvalidRequest {
+                    source: JsonError::UnexpectedValue {
+                        expected: "object",
+                        path: "$".to_string(),
+                    },
+                })
+            }
+        },
+        (Some(field), Some(body)) => {
+            if let Some(field) = info.get_field_by_name(field) {
+                object.insert(field.json_name.clone(), body.clone());
+            }
+        }
+        _ => {}
+    }
+
+    for (path, value) in variables {
+        set_param(&mut object, info, path, &value, ctx).ok_or_else(|| HttpError::UnknownField {
+            name: path.join("."),
+            method: rule.method.clone(),
+            path: rule.path.clone(),
+        })?;
+    }
+
+    // The whole message comes from the body so there is nothing left for the query parameters.
+    if rule.body.as_deref() != Some("*") {
+        let params = query.into_iter().flat_map(|q| q.split('&'));
+        for param in params.filter(|p| !p.is_empty()) {
+            let (name, value) = match param.find('=') {
+                Some(idx) => (&param[..idx], &param[idx + 1..]),
+                None => (param, ""),
+            };
+            let name = percent_decode(name, true);
+            let path = name.split('.').collect::<Vec<_>>();
+            set_param(&mut object, info, &path, &percent_decode(value, true), ctx);
+        }
+    }
+
+    info.decode_json(&JsonValue::Object(object), ctx)
+        .map_err(|source| HttpError::InvalidRequest { source })
+}
+
+/// Sets the field at `path` in the JSON object of a message.
+///
+/// Returns `None` if the path doesn't lead to a field that can be set from a string.
+fn set_param<S: AsRef<str>>(
+    object: &mut Map<String, JsonValue>,
+    info: &MessageInfo,
+    path: &[S],
+    value: &str,
+    ctx: &Context,
+) -> Option<()>
+{
+    let (first, rest) = path.split_first()?;
+    let field = param_field(info, first.as_ref())?;
+    if field.multiplicity == Multiplicity::Map {
+        return None;
+    }
+
+    if !rest.is_empty() {
+        let inner = match field.field_type {
+            ValueType::Message(m) => ctx.resolve_message(m),
+            _ => return None,
+        };
+        let entry = object
+            .entry(field.json_name.clone())
+            .or_insert_with(|| JsonValue::Object(Map::new()));
+        return set_param(entry.as_object_mut()?, inner, rest, value, ctx);
+    }
+
+    let value = param_json(&field.field_type, value, ctx);
+    match field.multiplicity {
+        Multiplicity::Repeated | Multiplicity::RepeatedPacked => object
+            .entry(field.json_name.clone())
+            .or_insert_with(|| JsonValue::Array(vec![]))
+            .as_array_mut()?
+            .push(value),
+        _ => {
+            object.remove(&field.name);
+            object.insert(field.json_name.clone(), value);
+        }
+    }
+    Some(())
+}
+
+/// Takes the value of a path variable out of the JSON object of a message.
+fn take_param<S: AsRef<str>>(
+    object: &mut Map<String, JsonValue>,
+    info: &MessageInfo,
+    path: &[S],
+    ctx: &Context,
+) -> Option<String>
+{
+    let (first, rest) = path.split_first()?;
+    let field = param_field(info, first.as_ref())?;
+    if !rest.is_empty() {
+        let inner = match field.field_type {
+            ValueType::Message(m) => ctx.resolve_message(m),
+            _ => return None,
+        };
+        let value = object.get_mut(&field.json_name)?.as_object_mut()?;
+        return take_param(value, inner, rest, ctx);
+    }
+
+    match object.remove(&field.json_name)? {
+        JsonValue::String(s) => Some(s),
+        value @ JsonValue::Number(..) | value @ JsonValue::Bool(..) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Finds a field by its name or its JSON name.
+fn param_field<'a>(info: &'a MessageInfo, name: &str) -> Option<&'a MessageField>
+{
+    info.get_field_by_name(name)
+        .or_else(|| info.iter_fields().find(|f| f.json_name == name))
+}
+
+/// Converts a path or query parameter into the JSON value of the field type.
+///
+/// The JSON mapping accepts strings for most types so only booleans need converting.
+fn param_json(value_type: &ValueType, value: &str, ctx: &Context) -> JsonValue
+{
+    let is_bool = match value_type {
+        ValueType::Bool => true,
+        ValueType::Message(m) => ctx.resolve_message(*m).full_name == "google.protobuf.BoolValue",
+        _ => false,
+    };
+
+    match (is_bool, value) {
+        (true, "true") => JsonValue::Bool(true),
+        (true, "false") => JsonValue::Bool(false),
+        _ => JsonValue::String(value.to_string()),
+    }
+}
+
+/// Renders the fields of a JSON value as query parameters.
+fn push_query(query: &mut Vec<String>, name: &str, value: &JsonValue)
+{
+    match value {
+        JsonValue::Object(object) => {
+            for (key, value) in object {
+                let name = match name.is_empty() {
+                    true => key.clone(),
+                    false => format!("{}.{}", name, key),
+                };
+                push_query(query, &name, value);
+            }
+        }
+        JsonValue::Array(items) => {
+            for item in items.iter().filter(|i| !i.is_object() && !i.is_array()) {
+                push_query(query, name, item);
+            }
+        }
+        JsonValue::Null => {}
+        JsonValue::String(s) => {
+            query.push(format!("{}={}", percent_encode(name), percent_encode(s)))
+        }
+        value => query.push(format!("{}={}", percent_encode(name), value)),
+    }
+}
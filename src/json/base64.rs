@@ -0,0 +1,92 @@
+//! Base64 encoding used by the JSON mapping for `bytes` values.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn encode(data: &[u8]) -> String
+{
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+
+        output.push(ALPHABET[n >> 18 & 0x3f] as char);
+        output.push(ALPHABET[n >> 12 & 0x3f] as char);
+        match chunk.len() {
+            1 => output.push_str("=="),
+            2 => {
+                output.push(ALPHABET[n >> 6 & 0x3f] as char);
+                output.push('=');
+            }
+            _ => {
+                output.push(ALPHABET[n >> 6 & 0x3f] as char);
+                output.push(ALPHABET[n & 0x3f] as char);
+            }
+        }
+    }
+    output
+}
+
+/// Decode standard or URL-safe base64 with or without padding.
+pub(crate) fn decode(data: &str) -> Option<Vec<u8>>
+{
+    let data = data.trim_end_matches('=');
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in data.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn encode_padding()
+    {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn decode_variants()
+    {
+        assert_eq!(decode("").unwrap(), b"");
+        assert_eq!(decode("Zg==").unwrap(), b"f");
+        assert_eq!(decode("Zg").unwrap(), b"f");
+        assert_eq!(decode("Zm9vYg==").unwrap(), b"foob");
+        assert_eq!(decode("+/8=").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode("Zm9vY"), None);
+        assert_eq!(decode("Zm9v!"), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Proto3 JSON mapping.
+//!
+//! Decoded messages can be rendered in the [proto3 JSON format][json] with
+//! [`MessageValue::to_json`](crate::decode::MessageValue::to_json). The fields are keyed by their
+//! JSON names and the extensions by their full names in brackets. Enums are rendered by name,
+//! 64-bit integers as strings and bytes as base64.
+//!
+//! The well-known types such as `google.protobuf.Timestamp` and `google.protobuf.Duration` use
+//! their special representations as long as their definitions are present in the context.
+//!
+//! The JSON format has no place for the data that protofish wasn't able to decode. To avoid
+//! losing the recovery information, the format is extended with the following:
+//!
+//! - Values that don't match the field definition, such as the values of unknown fields, are
+//!   stored under the field number as an array of the values in the order they appeared:
+//!   `"5": [{"varint": "150"}]`. Each value is an object with one of the following keys:
+//!   - `varint`: Varint (wire type 0) as a decimal string.
+//!   - `fixed64`: 64-bit value (wire type 1) as a decimal string.
+//!   - `bytes`: Variable length value (wire type 2) as base64.
+//!   - `fixed32`: 32-bit value (wire type 5) as a number.
+//!   - `group`: The fields of a group (wire type 3) as base64.
+//!   - `invalid`: The bytes following an invalid wire type as base64. The wire type is stored
+//!     in `wireType`.
+//!   - `incomplete`: The bytes of a value cut short by the end of the data as base64. The wire
+//!     type is stored in `wireType`.
+//!   - `limitExceeded`: The bytes of a value that exceeded a decode limit as base64. The wire
+//!     type is stored in `wireType` and the name of the limit, such as `max_depth`, in `limit`.
+//!   - `invalidString`: The bytes of a `string` value that isn't valid UTF-8 as base64.
+//!   - `message`: A map entry that couldn't be represented as a key and a value, rendered as a
+//!     message.
+//! - Garbage at the end of a message is stored as base64 under the `@garbage` key.
+//! - `google.protobuf.Any` values of types missing from the context store the payload as base64
+//!   under the `@value` key. So do the ones nested past the `max_depth` of the
+//!   [`DecodeOptions`](crate::decode::DecodeOptions) given to
+//!   [`MessageValue::to_json_with_options`](crate::decode::MessageValue::to_json_with_options).
+//!
+//! Well-known types that contain any such data are rendered as regular messages.
+//!
+//! JSON can be converted back into a message value with
+//! [`Context::decode_json`](crate::context::Context::decode_json). The conversion accepts both
+//! the JSON names and the original names of the fields, enum names and numbers, and integers
+//! encoded as strings. The recovery data described above is accepted as well.
+//!
+//! [json]: https://protobuf.dev/programming-guides/json/
+
+use snafu::Snafu;
+
+mod base64;
+mod parse;
+mod render;
+
+/// Well-known types with a special JSON representation.
+const WELL_KNOWN_TYPES: &[&str] = &[
+    "google.protobuf.Any",
+    "google.protobuf.Timestamp",
+    "google.protobuf.Duration",
+    "google.protobuf.Struct",
+    "google.protobuf.Value",
+    "google.protobuf.ListValue",
+    "google.protobuf.FieldMask",
+    "google.protobuf.Empty",
+    "google.protobuf.DoubleValue",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int64Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Int32Value",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.BoolValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.BytesValue",
+];
+
+/// Error converting JSON into a message value.
+///
+/// The `path` of the errors points at the offending JSON value, such as `$.items[2].name`.
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum JsonError
+{
+    /// JSON value was of the wrong kind.
+    #[snafu(display("Expected {} at '{}'", expected, path))]
+    UnexpectedValue
+    {
+        /// Description of the expected value.
+        expected: &'static str,
+
+        /// Path of the value.
+        path: String,
+    },
+
+    /// Message has no field with the given name.
+    #[snafu(display("Unknown field '{}' at '{}'", name, path))]
+    UnknownField
+    {
+        /// Name of the field.
+        name: String,
+
+        /// Path of the field.
+        path: String,
+    },
+
+    /// JSON value could not be represented as the field type.
+    #[snafu(display("Invalid value at '{}': {}", path, reason))]
+    InvalidValue
+    {
+        /// Description of the problem.
+        reason: String,
+
+        /// Path of the value.
+        path: String,
+    },
+}
@@ -0,0 +1,740 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use bytes::Bytes;
+use serde_json::{Map, Value as JsonValue};
+
+use super::{base64, JsonError, WELL_KNOWN_TYPES};
+use crate::context::*;
+use crate::decode::*;
+
+impl Context
+{
+    /// Convert a message from the proto3 JSON format.
+    ///
+    /// See the [`json`](crate::json) module for the accepted input.
+    pub fn decode_json(&self, msg: MessageRef, json: &JsonValue)
+        -> Result<MessageValue, JsonError>
+    {
+        self.resolve_message(msg).decode_json(json, self)
+    }
+}
+
+impl MessageInfo
+{
+    /// Convert a message from the proto3 JSON format.
+    ///
+    /// See the [`json`](crate::json) module for the accepted input.
+    pub fn decode_json(&self, json: &JsonValue, ctx: &Context) -> Result<MessageValue, JsonError>
+    {
+        message(self, json, Path::Root, ctx)
+    }
+}
+
+/// Location of a value in the JSON input.
+#[derive(Clone, Copy)]
+enum Path<'a>
+{
+    Root,
+    Field(&'a Path<'a>, &'a str),
+    Index(&'a Path<'a>, usize),
+}
+
+impl fmt::Display for Path<'_>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            Path::Root => write!(f, "$"),
+            Path::Field(parent, name) if is_identifier(name) => write!(f, "{}.{}", parent, name),
+            Path::Field(parent, name) => write!(f, "{}[{}]", parent, JsonValue::from(*name)),
+            Path::Index(parent, idx) => write!(f, "{}[{}]", parent, idx),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool
+{
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn unexpected(expected: &'static str, path: Path) -> JsonError
+{
+    JsonError::UnexpectedValue {
+        expected,
+        path: path.to_string(),
+    }
+}
+
+fn invalid(reason: impl Into<String>, path: Path) -> JsonError
+{
+    JsonError::InvalidValue {
+        reason: reason.into(),
+        path: path.to_string(),
+    }
+}
+
+fn message(
+    info: &MessageInfo,
+    json: &JsonValue,
+    path: Path,
+    ctx: &Context,
+) -> Result<MessageValue, JsonError>
+{
+    match well_known(info, json, path, ctx)? {
+        Some(fields) => Ok(MessageValue {
+            msg_ref: info.self_ref,
+            fields,
+            garbage: None,
+        }),
+        None => generic_message(info, json, path, ctx),
+    }
+}
+
+fn generic_message(
+    info: &MessageInfo,
+    json: &JsonValue,
+    path: Path,
+    ctx: &Context,
+) -> Result<MessageValue, JsonError>
+{
+    let object = json.as_object().ok_or_else(|| unexpected("object", path))?;
+
+    let mut msg = MessageValue {
+        msg_ref: info.self_ref,
+        fields: vec![],
+        garbage: None,
+    };
+    for (key, item) in object {
+        let path = Path::Field(&path, key);
+
+        if key == "@garbage" {
+            msg.garbage = Some(bytes(item, path)?);
+            continue;
+        }
+
+        // Values that the field definition did not account for are stored under the field number.
+        if let Ok(number) = key.parse::<u64>() {
+            let items = item.as_array().ok_or_else(|| unexpected("array", path))?;
+            for (idx, item) in items.iter().enumerate() {
+                let field = info.get_field_or_extension(number, ctx);
+                let value = raw_value(field, item, Path::Index(&path, idx), ctx)?;
+                msg.fields.push(FieldValue { number, value });
+            }
+            continue;
+        }
+
+        let field = info
+            .iter_fields()
+            .find(|f| f.json_name == *key)
+            .or_else(|| info.get_field_by_name(key))
+            .or_else(|| extension_field(info, key, ctx))
+            .ok_or_else(|| JsonError::UnknownField {
+                name: key.clone(),
+                path: path.to_string(),
+            })?;
+
+        // Null stands for the default value except for the types that represent null itself.
+        if item.is_null() && !accepts_null(field, ctx) {
+            continue;
+        }
+
+        msg.fields.extend(field_values(field, item, path, ctx)?);
+    }
+
+    msg.fields.sort_by_key(|f| f.number);
+    Ok(msg)
+}
+
+/// Finds an extension of the message by a `[full.name]` key.
+fn extension_field<'a>(info: &MessageInfo, key: &str, ctx: &'a Context)
+    -> Option<&'a MessageField>
+{
+    let full_name = key.strip_prefix('[')?.strip_suffix(']')?;
+    ctx.get_extension(full_name)
+        .filter(|ext| ext.extendee == info.self_ref)
+        .map(|ext| &ext.field)
+}
+
+fn accepts_null(field: &MessageField, ctx: &Context) -> bool
+{
+    let name = match field.field_type {
+        ValueType::Message(m) => &ctx.resolve_message(m).full_name,
+        ValueType::Enum(e) => &ctx.resolve_enum(e).full_name,
+        _ => return false,
+    };
+
+    match field.multiplicity {
+        Multiplicity::Single | Multiplicity::Optional | Multiplicity::Required => {
+            name == "google.protobuf.Value" || name == "google.protobuf.NullValue"
+        }
+        _ => false,
+    }
+}
+
+fn field_values(
+    field: &MessageField,
+    json: &JsonValue,
+    path: Path,
+    ctx: &Context,
+) -> Result<Vec<FieldValue>, JsonError>
+{
+    let values = match field.multiplicity {
+        Multiplicity::Single | Multiplicity::Optional | Multiplicity::Required => {
+            vec![value(&field.field_type, json, path, ctx)?]
+        }
+        Multiplicity::Repeated | Multiplicity::RepeatedPacked => {
+            let items = json.as_array().ok_or_else(|| unexpected("array", path))?;
+            let values = items
+                .iter()
+                .enumerate()
+                .map(|(idx, item)| value(&field.field_type, item, Path::Index(&path, idx), ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match field.multiplicity {
+                Multiplicity::RepeatedPacked if values.is_empty() => vec![],
+                Multiplicity::RepeatedPacked => {
+                    match PackedArray::from_values(&field.field_type, &values) {
+                        Some(packed) => vec![Value::Packed(packed)],
+                        None => values,
+                    }
+                }
+                _ => values,
+            }
+        }
+        Multiplicity::Map => {
+            let object = json.as_object().ok_or_else(|| unexpected("object", path))?;
+            let entry = match field.field_type {
+                ValueType::Message(m) => ctx.resolve_message(m),
+                _ => return Err(invalid("Map field is not a message", path)),
+            };
+            let (key_field, value_field) = match (entry.get_field(1), entry.get_field(2)) {
+                (Some(k), Some(v)) => (k, v),
+                _ => return Err(invalid("Map entry is missing the key or the value", path)),
+            };
+
+            object
+                .iter()
+                .map(|(key, item)| {
+                    let path = Path::Field(&path, key);
+                    Ok(Value::MapEntry(Box::new(MapEntryValue {
+                        entry_ref: entry.self_ref,
+                        key: Some(map_key(&key_field.field_type, key, path, ctx)?),
+                        value: Some(value(&value_field.field_type, item, path, ctx)?),
+                        value_first: false,
+                    })))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    Ok(values
+        .into_iter()
+        .map(|value| FieldValue {
+            number: field.number,
+            value,
+        })
+        .collect())
+}
+
+fn map_key(vt: &ValueType, key: &str, path: Path, ctx: &Context) -> Result<Value, JsonError>
+{
+    match vt {
+        ValueType::Bool => match key {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(invalid("Invalid boolean map key", path)),
+        },
+        vt => value(vt, &JsonValue::String(key.to_string()), path, ctx),
+    }
+}
+
+fn value(vt: &ValueType, json: &JsonValue, path: Path, ctx: &Context) -> Result<Value, JsonError>
+{
+    Ok(match vt {
+        ValueType::Double => Value::Double(float(json, path)?),
+        ValueType::Float => {
+            let v = float(json, path)?;
+            if v.is_finite() && v.abs() > f32::MAX as f64 {
+                return Err(invalid("Number out of range", path));
+            }
+            Value::Float(v as f32)
+        }
+        ValueType::Int32 => Value::Int32(integer(json, path)?),
+        ValueType::Int64 => Value::Int64(integer(json, path)?),
+        ValueType::UInt32 => Value::UInt32(integer(json, path)?),
+        ValueType::UInt64 => Value::UInt64(integer(json, path)?),
+        ValueType::SInt32 => Value::SInt32(integer(json, path)?),
+        ValueType::SInt64 => Value::SInt64(integer(json, path)?),
+        ValueType::Fixed32 => Value::Fixed32(integer(json, path)?),
+        ValueType::Fixed64 => Value::Fixed64(integer(json, path)?),
+        ValueType::SFixed32 => Value::SFixed32(integer(json, path)?),
+        ValueType::SFixed64 => Value::SFixed64(integer(json, path)?),
+        ValueType::Bool => Value::Bool(json.as_bool().ok_or_else(|| unexpected("boolean", path))?),
+        ValueType::String => Value::String(
+            json.as_str()
+                .ok_or_else(|| unexpected("string", path))?
+                .to_string(),
+        ),
+        ValueType::Bytes => Value::Bytes(bytes(json, path)?),
+        ValueType::Enum(e) => Value::Enum(enum_value(ctx.resolve_enum(*e), json, path)?),
+        ValueType::Message(m) => {
+            Value::Message(Box::new(message(ctx.resolve_message(*m), json, path, ctx)?))
+        }
+        ValueType::Group(m) => {
+            Value::Group(Box::new(message(ctx.resolve_message(*m), json, path, ctx)?))
+        }
+    })
+}
+
+fn float(json: &JsonValue, path: Path) -> Result<f64, JsonError>
+{
+    match json {
+        JsonValue::Number(n) => n.as_f64().ok_or_else(|| invalid("Invalid number", path)),
+        JsonValue::String(s) => match s.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            s => s
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| invalid("Invalid number", path)),
+        },
+        _ => Err(unexpected("number", path)),
+    }
+}
+
+fn integer<T: TryFrom<i128>>(json: &JsonValue, path: Path) -> Result<T, JsonError>
+{
+    // Integers may be written as exponents or with zero fractions, such as `1e3` or `5.0`.
+    let integral = |v: f64| match v.fract() == 0.0 && v.abs() < 2f64.powi(127) {
+        true => Some(v as i128),
+        false => None,
+    };
+
+    let value = match json {
+        JsonValue::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from))
+            .or_else(|| n.as_f64().and_then(integral)),
+        JsonValue::String(s) => s
+            .parse::<i128>()
+            .ok()
+            .or_else(|| s.parse::<f64>().ok().and_then(integral)),
+        _ => return Err(unexpected("integer", path)),
+    };
+
+    let value = value.ok_or_else(|| invalid("Invalid integer", path))?;
+    T::try_from(value).map_err(|_| invalid("Integer out of range", path))
+}
+
+fn bytes(json: &JsonValue, path: Path) -> Result<Bytes, JsonError>
+{
+    let s = json
+        .as_str()
+        .ok_or_else(|| unexpected("base64 string", path))?;
+    base64::decode(s)
+        .map(Bytes::from)
+        .ok_or_else(|| invalid("Invalid base64", path))
+}
+
+fn enum_value(info: &EnumInfo, json: &JsonValue, path: Path) -> Result<EnumValue, JsonError>
+{
+    let value = match json {
+        JsonValue::Null if info.full_name == "google.protobuf.NullValue" => 0,
+        JsonValue::String(name) => match info.get_field_by_name(name) {
+            Some(field) => field.value,
+            None => {
+                return Err(invalid(
+                    format!("Unknown value '{}' for enum '{}'", name, info.full_name),
+                    path,
+                ))
+            }
+        },
+        JsonValue::Number(..) => integer::<i32>(json, path)? as i64,
+        _ => return Err(unexpected("enum name or number", path)),
+    };
+
+    Ok(EnumValue {
+        enum_ref: info.self_ref,
+        value,
+    })
+}
+
+fn raw_value(
+    field: Option<&MessageField>,
+    json: &JsonValue,
+    path: Path,
+    ctx: &Context,
+) -> Result<Value, JsonError>
+{
+    let object = json.as_object().ok_or_else(|| unexpected("object", path))?;
+    let wire_type = || {
+        let json = object.get("wireType").unwrap_or(&JsonValue::Null);
+        integer::<u8>(json, Path::Field(&path, "wireType"))
+    };
+    let limit = || {
+        object
+            .get("limit")
+            .and_then(JsonValue::as_str)
+            .and_then(DecodeLimit::from_name)
+            .ok_or_else(|| unexpected("decode limit", Path::Field(&path, "limit")))
+    };
+
+    let (key, item) = object
+        .iter()
+        .find(|(key, _)| *key != "wireType" && *key != "limit")
+        .ok_or_else(|| unexpected("unknown value", path))?;
+    let path = Path::Field(&path, key);
+
+    Ok(match key.as_str() {
+        "varint" => Value::Unknown(UnknownValue::Varint(integer(item, path)?)),
+        "fixed64" => Value::Unknown(UnknownValue::Fixed64(integer(item, path)?)),
+        "fixed32" => Value::Unknown(UnknownValue::Fixed32(integer(item, path)?)),
+        "bytes" => Value::Unknown(UnknownValue::VariableLength(bytes(item, path)?)),
+        "group" => Value::Unknown(UnknownValue::Group(bytes(item, path)?)),
+        "invalid" => Value::Unknown(UnknownValue::Invalid(wire_type()?, bytes(item, path)?)),
+        "incomplete" => Value::Incomplete(wire_type()?, bytes(item, path)?),
+        "limitExceeded" => Value::LimitExceeded(limit()?, wire_type()?, bytes(item, path)?),
+        "invalidString" => Value::InvalidString(bytes(item, path)?),
+        "message" | "value" => match field {
+            Some(field) => value(&field.field_type, item, path, ctx)?,
+            None => return Err(invalid("Unknown field number", path)),
+        },
+        _ => {
+            return Err(JsonError::UnknownField {
+                name: key.clone(),
+                path: path.to_string(),
+            })
+        }
+    })
+}
+
+fn well_known(
+    info: &MessageInfo,
+    json: &JsonValue,
+    path: Path,
+    ctx: &Context,
+) -> Result<Option<Vec<FieldValue>>, JsonError>
+{
+    let field = |name: &str| {
+        info.get_field_by_name(name).ok_or_else(|| {
+            invalid(
+                format!("Definition of '{}' has no field '{}'", info.full_name, name),
+                path,
+            )
+        })
+    };
+    let single = |name: &str, json: &JsonValue| {
+        let field = field(name)?;
+        Ok(vec![FieldValue {
+            number: field.number,
+            value: value(&field.field_type, json, path, ctx)?,
+        }])
+    };
+
+    Ok(Some(match (info.full_name.as_str(), json) {
+        ("google.protobuf.Any", JsonValue::Object(object)) if object.contains_key("@type") => {
+            any(info, object, path, ctx)?
+        }
+        ("google.protobuf.Timestamp", JsonValue::String(s)) => {
+            let (seconds, nanos) =
+                parse_timestamp(s).ok_or_else(|| invalid("Invalid timestamp", path))?;
+            seconds_and_nanos(field("seconds")?, field("nanos")?, seconds, nanos)
+        }
+        ("google.protobuf.Duration", JsonValue::String(s)) => {
+            let (seconds, nanos) =
+                parse_duration(s).ok_or_else(|| invalid("Invalid duration", path))?;
+            seconds_and_nanos(field("seconds")?, field("nanos")?, seconds, nanos)
+        }
+        ("google.protobuf.FieldMask", JsonValue::String(s)) => {
+            let paths = field("paths")?;
+            s.split(',')
+                .filter(|p| !p.is_empty())
+                .map(|p| FieldValue {
+                    number: paths.number,
+                    value: Value::String(snake_case(p)),
+                })
+                .collect()
+        }
+        ("google.protobuf.Struct", _) => field_values(field("fields")?, json, path, ctx)?,
+        ("google.protobuf.ListValue", _) => field_values(field("values")?, json, path, ctx)?,
+        ("google.protobuf.Value", _) => {
+            let name = match json {
+                JsonValue::Null => "null_value",
+                JsonValue::Number(..) => "number_value",
+                JsonValue::String(..) => "string_value",
+                JsonValue::Bool(..) => "bool_value",
+                JsonValue::Object(..) => "struct_value",
+                JsonValue::Array(..) => "list_value",
+            };
+            single(name, json)?
+        }
+        ("google.protobuf.DoubleValue", _)
+        | ("google.protobuf.FloatValue", _)
+        | ("google.protobuf.Int64Value", _)
+        | ("google.protobuf.UInt64Value", _)
+        | ("google.protobuf.Int32Value", _)
+        | ("google.protobuf.UInt32Value", _)
+        | ("google.protobuf.BoolValue", _)
+        | ("google.protobuf.StringValue", _)
+        | ("google.protobuf.BytesValue", _)
+            if !json.is_object() =>
+        {
+            single("value", json)?
+        }
+        _ => return Ok(None),
+    }))
+}
+
+fn any(
+    info: &MessageInfo,
+    object: &Map<String, JsonValue>,
+    path: Path,
+    ctx: &Context,
+) -> Result<Vec<FieldValue>, JsonError>
+{
+    let field = |name: &str| {
+        info.get_field_by_name(name).ok_or_else(|| {
+            invalid(
+                format!("Definition of '{}' has no field '{}'", info.full_name, name),
+                path,
+            )
+        })
+    };
+
+    let type_path = Path::Field(&path, "@type");
+    let type_url = object["@type"]
+        .as_str()
+        .ok_or_else(|| unexpected("string", type_path))?;
+    let type_name = type_url.rsplit('/').next().unwrap_or_default();
+
+    let data = match ctx.get_message(type_name) {
+        Some(inner) if WELL_KNOWN_TYPES.contains(&type_name) => {
+            let value = object.get("value").unwrap_or(&JsonValue::Null);
+            message(inner, value, Path::Field(&path, "value"), ctx)?
+                .encode(ctx)
+                .freeze()
+        }
+        Some(inner) => {
+            let mut fields = object.clone();
+            fields.remove("@type");
+            message(inner, &JsonValue::Object(fields), path, ctx)?
+                .encode(ctx)
+                .freeze()
+        }
+        None => match object.get("@value") {
+            Some(value) => bytes(value, Path::Field(&path, "@value"))?,
+            None => return Err(invalid(format!("Unknown type '{}'", type_url), type_path)),
+        },
+    };
+
+    Ok(vec![
+        FieldValue {
+            number: field("type_url")?.number,
+            value: Value::String(type_url.to_string()),
+        },
+        FieldValue {
+            number: field("value")?.number,
+            value: Value::Bytes(data),
+        },
+    ])
+}
+
+fn seconds_and_nanos(
+    seconds_field: &MessageField,
+    nanos_field: &MessageField,
+    seconds: i64,
+    nanos: i32,
+) -> Vec<FieldValue>
+{
+    let mut fields = vec![];
+    if seconds != 0 {
+        fields.push(FieldValue {
+            number: seconds_field.number,
+            value: Value::Int64(seconds),
+        });
+    }
+    if nanos != 0 {
+        fields.push(FieldValue {
+            number: nanos_field.number,
+            value: Value::Int32(nanos),
+        });
+    }
+    fields
+}
+
+/// Parse a fixed number of decimal digits.
+fn digits(s: &str, count: usize) -> Option<i64>
+{
+    match s.len() == count && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+/// Parse up to nine fractional digits into nanoseconds.
+fn fraction(s: &str) -> Option<i32>
+{
+    if s.is_empty() || s.len() > 9 {
+        return None;
+    }
+    Some(digits(s, s.len())? as i32 * 10i32.pow(9 - s.len() as u32))
+}
+
+/// Parse an RFC 3339 timestamp, such as `1972-01-01T10:00:20.021-05:00`.
+fn parse_timestamp(s: &str) -> Option<(i64, i32)>
+{
+    let (date, time) = s.split_once(['T', 't'])?;
+
+    let mut date = date.split('-');
+    let year = digits(date.next()?, 4)?;
+    let month = digits(date.next()?, 2)?;
+    let day = digits(date.next()?, 2)?;
+    if date.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, 0),
+        None => {
+            let idx = time.rfind(['+', '-'])?;
+            let (hours, minutes) = time[idx + 1..].split_once(':')?;
+            let offset = digits(hours, 2)? * 3600 + digits(minutes, 2)? * 60;
+            match &time[idx..idx + 1] {
+                "-" => (&time[..idx], -offset),
+                _ => (&time[..idx], offset),
+            }
+        }
+    };
+
+    let (time, nanos) = match time.split_once('.') {
+        Some((time, fract)) => (time, fraction(fract)?),
+        None => (time, 0),
+    };
+    let mut time = time.split(':');
+    let hour = digits(time.next()?, 2)?;
+    let minute = digits(time.next()?, 2)?;
+    let second = digits(time.next()?, 2)?;
+    if time.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Reject days past the end of the month, such as February 30th.
+    let days = days_from_civil(year, month, day);
+    if month < 12 && days_from_civil(year, month + 1, 1) <= days {
+        return None;
+    }
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    match (-62_135_596_800..=253_402_300_799).contains(&seconds) {
+        true => Some((seconds, nanos)),
+        false => None,
+    }
+}
+
+/// Parse a duration, such as `-1.5s`.
+fn parse_duration(s: &str) -> Option<(i64, i32)>
+{
+    let s = s.strip_suffix('s')?;
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+
+    let (seconds, nanos) = match s.split_once('.') {
+        Some((seconds, fract)) => (seconds, fraction(fract)?),
+        None => (s, 0),
+    };
+    let seconds = digits(seconds, seconds.len())?;
+    if seconds > 315_576_000_000 {
+        return None;
+    }
+
+    match negative {
+        true => Some((-seconds, -nanos)),
+        false => Some((seconds, nanos)),
+    }
+}
+
+/// Convert a proleptic Gregorian date into days since the Unix epoch.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64
+{
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Convert a FieldMask path from `fooBar` to `foo_bar`.
+fn snake_case(path: &str) -> String
+{
+    let mut output = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_uppercase() {
+            output.push('_');
+            output.push(c.to_ascii_lowercase());
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn timestamps()
+    {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some((0, 0)));
+        assert_eq!(
+            parse_timestamp("2009-02-13T23:31:30.12Z"),
+            Some((1_234_567_890, 120_000_000))
+        );
+        assert_eq!(
+            parse_timestamp("1972-01-01T10:00:20.021-05:00"),
+            Some((63_126_020, 21_000_000))
+        );
+        assert_eq!(
+            parse_timestamp("0001-01-01T00:00:00Z"),
+            Some((-62_135_596_800, 0))
+        );
+        assert_eq!(
+            parse_timestamp("2000-02-29T00:00:00Z"),
+            Some((951_782_400, 0))
+        );
+        assert_eq!(parse_timestamp("2001-02-29T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("2001-02-03T04:05:06"), None);
+        assert_eq!(parse_timestamp("2001-02-03T04:05:06.Z"), None);
+    }
+
+    #[test]
+    fn durations()
+    {
+        assert_eq!(parse_duration("0s"), Some((0, 0)));
+        assert_eq!(parse_duration("1.5s"), Some((1, 500_000_000)));
+        assert_eq!(parse_duration("-0.000000001s"), Some((0, -1)));
+        assert_eq!(parse_duration("1.5"), None);
+        assert_eq!(parse_duration("+1s"), None);
+        assert_eq!(parse_duration("1.0000000001s"), None);
+    }
+
+    #[test]
+    fn paths()
+    {
+        let root = Path::Root;
+        let field = Path::Field(&root, "items");
+        let index = Path::Index(&field, 2);
+        let key = Path::Field(&index, "a b");
+        assert_eq!(key.to_string(), r#"$.items[2]["a b"]"#);
+    }
+}
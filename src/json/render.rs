@@ -0,0 +1,533 @@
+use serde_json::{Map, Value as JsonValue};
+
+use super::{base64, WELL_KNOWN_TYPES};
+use crate::context::*;
+use crate::decode::*;
+
+impl MessageValue
+{
+    /// Render the message in the proto3 JSON format.
+    ///
+    /// See the [`json`](crate::json) module for the representation of values that could not be
+    /// decoded.
+    pub fn to_json(&self, ctx: &Context) -> JsonValue
+    {
+        self.to_json_with_options(ctx, &DecodeOptions::default())
+    }
+
+    /// Render the message in the proto3 JSON format, decoding the `google.protobuf.Any` payloads
+    /// with the `options`.
+    ///
+    /// The payloads count towards the `max_depth` of the `options` as nested messages. Payloads
+    /// past the limit are kept as base64 under the `@value` key.
+    pub fn to_json_with_options(&self, ctx: &Context, options: &DecodeOptions) -> JsonValue
+    {
+        message_json(self, ctx, options, 0)
+    }
+}
+
+fn message_json(
+    msg: &MessageValue,
+    ctx: &Context,
+    options: &DecodeOptions,
+    depth: usize,
+) -> JsonValue
+{
+    let info = ctx.resolve_message(msg.msg_ref);
+    let object = fields_json(msg, info, ctx, options, depth);
+
+    // Use the generic representation for well-known types with recovery data to avoid losing it.
+    if object.keys().any(|key| is_recovery_key(key)) {
+        return JsonValue::Object(object);
+    }
+
+    well_known_json(msg, info, object, ctx, options, depth)
+}
+
+fn fields_json(
+    msg: &MessageValue,
+    info: &MessageInfo,
+    ctx: &Context,
+    options: &DecodeOptions,
+    depth: usize,
+) -> Map<String, JsonValue>
+{
+    let mut object = Map::new();
+    for field in &msg.fields {
+        let (def, key) = match (&field.value, info.get_field(field.number)) {
+            (Value::Unknown(..), _)
+            | (Value::Incomplete(..), _)
+            | (Value::LimitExceeded(..), _)
+            | (Value::InvalidString(..), _) => {
+                push_raw(&mut object, field.number, &field.value, ctx, options, depth);
+                continue;
+            }
+            (_, Some(def)) => (def, def.json_name.clone()),
+
+            // Extensions use their full name in brackets as the key.
+            (_, None) => match ctx.get_extension_by_number(info.self_ref, field.number) {
+                Some(ext) => (&ext.field, format!("[{}]", ext.full_name)),
+                None => {
+                    push_raw(&mut object, field.number, &field.value, ctx, options, depth);
+                    continue;
+                }
+            },
+        };
+
+        match def.multiplicity {
+            Multiplicity::Single | Multiplicity::Optional | Multiplicity::Required => {
+                object.insert(key, value_json(&field.value, ctx, options, depth));
+            }
+            Multiplicity::Repeated | Multiplicity::RepeatedPacked => {
+                let array = object
+                    .entry(key)
+                    .or_insert_with(|| JsonValue::Array(vec![]));
+                if let JsonValue::Array(array) = array {
+                    match &field.value {
+                        Value::Packed(packed) => array.extend(packed_json(packed, ctx)),
+                        value => array.push(value_json(value, ctx, options, depth)),
+                    }
+                }
+            }
+            Multiplicity::Map => match &field.value {
+                Value::MapEntry(entry) => {
+                    let map = object
+                        .entry(key)
+                        .or_insert_with(|| JsonValue::Object(Map::new()));
+                    if let JsonValue::Object(map) = map {
+                        map.insert(
+                            map_key(&entry.key_or_default(ctx), ctx),
+                            value_json(&entry.value_or_default(ctx), ctx, options, depth),
+                        );
+                    }
+                }
+                value => push_raw(&mut object, field.number, value, ctx, options, depth),
+            },
+        }
+    }
+
+    if let Some(garbage) = &msg.garbage {
+        object.insert(
+            "@garbage".to_string(),
+            JsonValue::String(base64::encode(garbage)),
+        );
+    }
+
+    object
+}
+
+fn is_recovery_key(key: &str) -> bool
+{
+    key.starts_with(|c: char| c == '@' || c.is_ascii_digit())
+}
+
+fn push_raw(
+    object: &mut Map<String, JsonValue>,
+    number: u64,
+    value: &Value,
+    ctx: &Context,
+    options: &DecodeOptions,
+    depth: usize,
+)
+{
+    let array = object
+        .entry(number.to_string())
+        .or_insert_with(|| JsonValue::Array(vec![]));
+    if let JsonValue::Array(array) = array {
+        array.push(raw_json(value, ctx, options, depth));
+    }
+}
+
+fn raw_json(value: &Value, ctx: &Context, options: &DecodeOptions, depth: usize) -> JsonValue
+{
+    let mut object = Map::new();
+    match value {
+        Value::Unknown(UnknownValue::Varint(v)) => {
+            object.insert("varint".to_string(), JsonValue::String(v.to_string()));
+        }
+        Value::Unknown(UnknownValue::Fixed64(v)) => {
+            object.insert("fixed64".to_string(), JsonValue::String(v.to_string()));
+        }
+        Value::Unknown(UnknownValue::VariableLength(data)) => {
+            object.insert("bytes".to_string(), JsonValue::String(base64::encode(data)));
+        }
+        Value::Unknown(UnknownValue::Fixed32(v)) => {
+            object.insert("fixed32".to_string(), JsonValue::from(*v));
+        }
+        Value::Unknown(UnknownValue::Group(data)) => {
+            object.insert("group".to_string(), JsonValue::String(base64::encode(data)));
+        }
+        Value::Unknown(UnknownValue::Invalid(wire_type, data)) => {
+            object.insert(
+                "invalid".to_string(),
+                JsonValue::String(base64::encode(data)),
+            );
+            object.insert("wireType".to_string(), JsonValue::from(*wire_type));
+        }
+        Value::Incomplete(wire_type, data) => {
+            object.insert(
+                "incomplete".to_string(),
+                JsonValue::String(base64::encode(data)),
+            );
+            object.insert("wireType".to_string(), JsonValue::from(*wire_type));
+        }
+        Value::LimitExceeded(limit, wire_type, data) => {
+            object.insert(
+                "limitExceeded".to_string(),
+                JsonValue::String(base64::encode(data)),
+            );
+            object.insert("limit".to_string(), JsonValue::from(limit.name()));
+            object.insert("wireType".to_string(), JsonValue::from(*wire_type));
+        }
+        Value::InvalidString(data) => {
+            object.insert(
+                "invalidString".to_string(),
+                JsonValue::String(base64::encode(data)),
+            );
+        }
+        Value::Message(msg) => {
+            object.insert(
+                "message".to_string(),
+                message_json(msg, ctx, options, depth + 1),
+            );
+        }
+        other => {
+            object.insert("value".to_string(), value_json(other, ctx, options, depth));
+        }
+    }
+    JsonValue::Object(object)
+}
+
+fn value_json(value: &Value, ctx: &Context, options: &DecodeOptions, depth: usize) -> JsonValue
+{
+    match value {
+        Value::Double(v) => double_json(*v),
+        Value::Float(v) => float_json(*v),
+        Value::Int32(v) | Value::SInt32(v) | Value::SFixed32(v) => JsonValue::from(*v),
+        Value::UInt32(v) | Value::Fixed32(v) => JsonValue::from(*v),
+        Value::Int64(v) | Value::SInt64(v) | Value::SFixed64(v) => JsonValue::String(v.to_string()),
+        Value::UInt64(v) | Value::Fixed64(v) => JsonValue::String(v.to_string()),
+        Value::Bool(v) => JsonValue::Bool(*v),
+        Value::String(v) => JsonValue::String(v.clone()),
+        Value::Bytes(v) => JsonValue::String(base64::encode(v)),
+        Value::Packed(packed) => JsonValue::Array(packed_json(packed, ctx)),
+        Value::Message(msg) | Value::Group(msg) => message_json(msg, ctx, options, depth + 1),
+        Value::LazyMessage(msg) => message_json(msg.get(ctx), ctx, options, depth + 1),
+        Value::Enum(v) => enum_json(v, ctx),
+        Value::MapEntry(entry) => {
+            let mut object = Map::new();
+            object.insert(
+                map_key(&entry.key_or_default(ctx), ctx),
+                value_json(&entry.value_or_default(ctx), ctx, options, depth),
+            );
+            JsonValue::Object(object)
+        }
+        Value::Incomplete(..)
+        | Value::Unknown(..)
+        | Value::LimitExceeded(..)
+        | Value::InvalidString(..) => raw_json(value, ctx, options, depth),
+    }
+}
+
+fn packed_json(packed: &PackedArray, ctx: &Context) -> Vec<JsonValue>
+{
+    match packed {
+        PackedArray::Double(v) => v.iter().map(|v| double_json(*v)).collect(),
+        PackedArray::Float(v) => v.iter().map(|v| float_json(*v)).collect(),
+        PackedArray::Int32(v) | PackedArray::SInt32(v) | PackedArray::SFixed32(v) => {
+            v.iter().map(|v| JsonValue::from(*v)).collect()
+        }
+        PackedArray::UInt32(v) | PackedArray::Fixed32(v) => {
+            v.iter().map(|v| JsonValue::from(*v)).collect()
+        }
+        PackedArray::Int64(v) | PackedArray::SInt64(v) | PackedArray::SFixed64(v) => {
+            v.iter().map(|v| JsonValue::String(v.to_string())).collect()
+        }
+        PackedArray::UInt64(v) | PackedArray::Fixed64(v) => {
+            v.iter().map(|v| JsonValue::String(v.to_string())).collect()
+        }
+        PackedArray::Bool(v) => v.iter().map(|v| JsonValue::Bool(*v)).collect(),
+        PackedArray::Enum(v) => v.iter().map(|v| enum_json(v, ctx)).collect(),
+    }
+}
+
+fn double_json(v: f64) -> JsonValue
+{
+    if v.is_nan() {
+        JsonValue::String("NaN".to_string())
+    } else if v == f64::INFINITY {
+        JsonValue::String("Infinity".to_string())
+    } else if v == f64::NEG_INFINITY {
+        JsonValue::String("-Infinity".to_string())
+    } else {
+        JsonValue::from(v)
+    }
+}
+
+fn float_json(v: f32) -> JsonValue
+{
+    // Go through the shortest decimal representation of the f32 to avoid rendering 0.1f32 as
+    // 0.10000000149011612.
+    double_json(v.to_string().parse().unwrap_or(v as f64))
+}
+
+fn enum_json(value: &EnumValue, ctx: &Context) -> JsonValue
+{
+    let info = ctx.resolve_enum(value.enum_ref);
+    if info.full_name == "google.protobuf.NullValue" {
+        return JsonValue::Null;
+    }
+
+    match info.get_field_by_value(value.value) {
+        Some(field) => JsonValue::String(field.name.clone()),
+        None => JsonValue::from(value.value),
+    }
+}
+
+fn map_key(key: &Value, ctx: &Context) -> String
+{
+    // Keys are scalars, so the options don't matter.
+    match value_json(key, ctx, &DecodeOptions::default(), 0) {
+        JsonValue::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+fn well_known_json(
+    msg: &MessageValue,
+    info: &MessageInfo,
+    mut object: Map<String, JsonValue>,
+    ctx: &Context,
+    options: &DecodeOptions,
+    depth: usize,
+) -> JsonValue
+{
+    let special = match info.full_name.as_str() {
+        "google.protobuf.Any" => any_json(msg, ctx, options, depth),
+        "google.protobuf.Timestamp" => timestamp_json(msg),
+        "google.protobuf.Duration" => duration_json(msg),
+        "google.protobuf.Struct" => Some(
+            object
+                .remove("fields")
+                .unwrap_or_else(|| JsonValue::Object(Map::new())),
+        ),
+        "google.protobuf.ListValue" => Some(
+            object
+                .remove("values")
+                .unwrap_or_else(|| JsonValue::Array(vec![])),
+        ),
+        "google.protobuf.Value" => Some(object.values().next().cloned().unwrap_or(JsonValue::Null)),
+        "google.protobuf.FieldMask" => field_mask_json(&object),
+        "google.protobuf.DoubleValue"
+        | "google.protobuf.FloatValue"
+        | "google.protobuf.Int64Value"
+        | "google.protobuf.UInt64Value"
+        | "google.protobuf.Int32Value"
+        | "google.protobuf.UInt32Value"
+        | "google.protobuf.BoolValue"
+        | "google.protobuf.StringValue"
+        | "google.protobuf.BytesValue" => {
+            let default = info.get_field(1).map(|f| default_json(&f.field_type));
+            object.remove("value").or(default)
+        }
+        _ => None,
+    };
+
+    special.unwrap_or(JsonValue::Object(object))
+}
+
+fn default_json(value_type: &ValueType) -> JsonValue
+{
+    match value_type {
+        ValueType::Int64
+        | ValueType::UInt64
+        | ValueType::SInt64
+        | ValueType::Fixed64
+        | ValueType::SFixed64 => JsonValue::String("0".to_string()),
+        ValueType::Bool => JsonValue::Bool(false),
+        ValueType::String | ValueType::Bytes => JsonValue::String(String::new()),
+        ValueType::Message(..) | ValueType::Group(..) => JsonValue::Null,
+        _ => JsonValue::from(0),
+    }
+}
+
+fn any_json(
+    msg: &MessageValue,
+    ctx: &Context,
+    options: &DecodeOptions,
+    depth: usize,
+) -> Option<JsonValue>
+{
+    let mut type_url = None;
+    let mut data = None;
+    for field in &msg.fields {
+        match (field.number, &field.value) {
+            (1, Value::String(s)) => type_url = Some(s),
+            (2, Value::Bytes(b)) => data = Some(b.clone()),
+            _ => {}
+        }
+    }
+
+    // Empty Any is rendered as an empty object.
+    let type_url = type_url?;
+
+    let mut object = Map::new();
+    object.insert("@type".to_string(), JsonValue::String(type_url.clone()));
+
+    let type_name = type_url.rsplit('/').next().unwrap_or_default();
+    let data = data.unwrap_or_default();
+    // Payloads past the depth limit are kept as they are, like the ones of unknown types.
+    let info = ctx
+        .get_message(type_name)
+        .filter(|_| depth < options.max_depth);
+    match info {
+        Some(info) => {
+            // The payload is nested in the Any, so it has the depth that remains below it.
+            let mut options = options.clone();
+            options.max_depth -= depth + 1;
+            let payload = info.decode_bytes(&data, ctx, &options);
+            match message_json(&payload, ctx, &options, 0) {
+                JsonValue::Object(fields) if !WELL_KNOWN_TYPES.contains(&type_name) => {
+                    object.extend(fields)
+                }
+                value => {
+                    object.insert("value".to_string(), value);
+                }
+            }
+        }
+        None => {
+            object.insert(
+                "@value".to_string(),
+                JsonValue::String(base64::encode(&data)),
+            );
+        }
+    }
+
+    Some(JsonValue::Object(object))
+}
+
+fn seconds_and_nanos(msg: &MessageValue) -> (i64, i32)
+{
+    let mut seconds = 0;
+    let mut nanos = 0;
+    for field in &msg.fields {
+        match (field.number, &field.value) {
+            (1, Value::Int64(v)) => seconds = *v,
+            (2, Value::Int32(v)) => nanos = *v,
+            _ => {}
+        }
+    }
+    (seconds, nanos)
+}
+
+fn timestamp_json(msg: &MessageValue) -> Option<JsonValue>
+{
+    // 0001-01-01T00:00:00Z to 9999-12-31T23:59:59Z.
+    let (seconds, nanos) = seconds_and_nanos(msg);
+    if !(-62_135_596_800..=253_402_300_799).contains(&seconds)
+        || !(0..1_000_000_000).contains(&nanos)
+    {
+        return None;
+    }
+
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    Some(JsonValue::String(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        fraction(nanos as u32)
+    )))
+}
+
+fn duration_json(msg: &MessageValue) -> Option<JsonValue>
+{
+    // Roughly +-10000 years.
+    let (seconds, nanos) = seconds_and_nanos(msg);
+    if !(-315_576_000_000..=315_576_000_000).contains(&seconds)
+        || !(-999_999_999..=999_999_999).contains(&nanos)
+        || (seconds > 0 && nanos < 0)
+        || (seconds < 0 && nanos > 0)
+    {
+        return None;
+    }
+
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    Some(JsonValue::String(format!(
+        "{}{}{}s",
+        sign,
+        seconds.unsigned_abs(),
+        fraction(nanos.unsigned_abs())
+    )))
+}
+
+/// Fractional seconds with 0, 3, 6 or 9 digits.
+fn fraction(nanos: u32) -> String
+{
+    if nanos == 0 {
+        String::new()
+    } else if nanos.is_multiple_of(1_000_000) {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos.is_multiple_of(1_000) {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    }
+}
+
+/// Convert days since the Unix epoch into a proleptic Gregorian (year, month, day).
+fn civil_from_days(days: i64) -> (i64, i64, i64)
+{
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn field_mask_json(object: &Map<String, JsonValue>) -> Option<JsonValue>
+{
+    let paths = match object.get("paths") {
+        Some(JsonValue::Array(paths)) => paths,
+        Some(_) => return None,
+        None => return Some(JsonValue::String(String::new())),
+    };
+
+    let paths = paths
+        .iter()
+        .map(|path| {
+            path.as_str().map(|path| {
+                path.split('.')
+                    .map(default_json_name)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(JsonValue::String(paths.join(",")))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn civil_dates()
+    {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-719_162), (1, 1, 1));
+        assert_eq!(civil_from_days(2_932_896), (9999, 12, 31));
+    }
+}
@@ -51,4 +51,12 @@
 
 pub mod context;
 pub mod decode;
+pub mod delimited;
+pub mod grpc;
+#[cfg(feature = "json")]
+pub mod http;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod prelude;
+pub mod raw;
+pub mod text_format;
@@ -0,0 +1,268 @@
+//! Schema-less decoding.
+//!
+//! [`decode_raw`] decodes a payload without a message definition, similar to
+//! `protoc --decode_raw`. The wire format does not tell whether a length-delimited value is a
+//! nested message, a packed array, a string or plain bytes, so the decoder guesses:
+//!
+//! - Valid UTF-8 without control characters other than tabs and line breaks is a
+//!   [`String`](RawValue::String) if the first character is printable.
+//! - Data that decodes cleanly into fields is a [`Message`](RawValue::Message).
+//! - Remaining valid UTF-8 without control characters is a [`String`](RawValue::String).
+//! - Data that decodes cleanly into varints is a [`Packed`](RawValue::Packed) array.
+//! - Anything else is [`Bytes`](RawValue::Bytes).
+//!
+//! Data decodes cleanly if every field has a non-zero field number, there are no incomplete or
+//! invalid values or garbage and encoding the result gives back the original bytes. Packed
+//! 32-bit and 64-bit values cannot be told apart from bytes and are decoded as such.
+//!
+//! Incomplete and invalid values and garbage at the end of the top level message are recovered
+//! the same way as in [`MessageInfo::decode`](crate::context::MessageInfo::decode), which allows
+//! [`RawMessage::encode`] to reproduce the original payload.
+
+use bytes::{Bytes, BytesMut};
+
+use crate::decode::{FromUnsignedVarint, ToUnsignedVarint, UnknownValue, Value};
+
+/// Maximum depth of nested messages that are decoded.
+///
+/// Length-delimited values nested deeper are decoded as strings or bytes and groups are kept as
+/// [`GroupBytes`](RawValue::GroupBytes).
+const MAX_DEPTH: usize = 64;
+
+/// Message decoded without a message definition.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RawMessage
+{
+    /// Fields in the order they appeared in the payload.
+    pub fields: Vec<RawField>,
+
+    /// Garbage data at the end of the message.
+    pub garbage: Option<Bytes>,
+}
+
+/// Field decoded without a message definition.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RawField
+{
+    /// Field number.
+    pub number: u64,
+
+    /// Wire type of the field.
+    pub wire_type: u8,
+
+    /// Field value.
+    pub value: RawValue,
+}
+
+/// Value decoded without a message definition.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RawValue
+{
+    /// Varint (wire type = 0).
+    Varint(u128),
+
+    /// 64-bit value (wire type = 1).
+    Fixed64(u64),
+
+    /// 32-bit value (wire type = 5).
+    Fixed32(u32),
+
+    /// Group (wire type = 3).
+    Group(Box<RawMessage>),
+
+    /// Group that was nested too deeply to be decoded.
+    ///
+    /// Contains the bytes between the start-group and the end-group tags.
+    GroupBytes(Bytes),
+
+    /// Length-delimited value that was decoded as a nested message.
+    Message(Box<RawMessage>),
+
+    /// Length-delimited value that was decoded as packed varints.
+    Packed(Vec<u128>),
+
+    /// Length-delimited value that was decoded as a UTF-8 string.
+    String(String),
+
+    /// Length-delimited value that was not recognized as anything else.
+    Bytes(Bytes),
+
+    /// Value which was incomplete due to missing bytes in the payload.
+    Incomplete(Bytes),
+
+    /// Value with an invalid wire type.
+    ///
+    /// The value consumes the remaining bytes of the payload, as it is impossible to tell how
+    /// large it is.
+    Invalid(Bytes),
+}
+
+/// Decode a payload without a message definition.
+///
+/// See the [module documentation](self) for how length-delimited values are interpreted.
+pub fn decode_raw(data: &[u8]) -> RawMessage
+{
+    decode_message(data, 0)
+}
+
+fn decode_message(mut data: &[u8], depth: usize) -> RawMessage
+{
+    let mut msg = RawMessage {
+        fields: vec![],
+        garbage: None,
+    };
+
+    while !data.is_empty() {
+        let tag = match u64::from_unsigned_varint(&mut data) {
+            Some(tag) => tag,
+            None => {
+                msg.garbage = Some(Bytes::copy_from_slice(data));
+                break;
+            }
+        };
+
+        let number = tag >> 3;
+        let wire_type = (tag & 0x07) as u8;
+        let value = match Value::decode_unknown(&mut data, number, wire_type) {
+            Value::Unknown(UnknownValue::Varint(v)) => RawValue::Varint(v),
+            Value::Unknown(UnknownValue::Fixed64(v)) => RawValue::Fixed64(v),
+            Value::Unknown(UnknownValue::Fixed32(v)) => RawValue::Fixed32(v),
+            Value::Unknown(UnknownValue::VariableLength(v)) => decode_length_delimited(v, depth),
+            Value::Unknown(UnknownValue::Group(v)) if depth < MAX_DEPTH => {
+                RawValue::Group(Box::new(decode_message(&v, depth + 1)))
+            }
+            Value::Unknown(UnknownValue::Group(v)) => RawValue::GroupBytes(v),
+            Value::Unknown(UnknownValue::Invalid(_, v)) => RawValue::Invalid(v),
+            Value::Incomplete(_, v) => RawValue::Incomplete(v),
+            _ => unreachable!("Unknown value decoded as a known type"),
+        };
+
+        msg.fields.push(RawField {
+            number,
+            wire_type,
+            value,
+        });
+    }
+
+    msg
+}
+
+fn decode_length_delimited(data: Bytes, depth: usize) -> RawValue
+{
+    let text = std::str::from_utf8(&data).ok().filter(|s| {
+        !s.chars()
+            .any(|c| c.is_control() && !['\t', '\n', '\r'].contains(&c))
+    });
+
+    if let Some(text) = text {
+        if !text.starts_with(char::is_control) {
+            return RawValue::String(text.to_string());
+        }
+    }
+
+    if depth < MAX_DEPTH {
+        let msg = decode_message(&data, depth + 1);
+        if is_clean(&msg) && msg.encode() == data {
+            return RawValue::Message(Box::new(msg));
+        }
+    }
+
+    if let Some(text) = text {
+        return RawValue::String(text.to_string());
+    }
+
+    if let Some(values) = decode_varints(&data) {
+        return RawValue::Packed(values);
+    }
+
+    RawValue::Bytes(data)
+}
+
+fn is_clean(msg: &RawMessage) -> bool
+{
+    msg.garbage.is_none()
+        && msg.fields.iter().all(|f| {
+            f.number != 0 && !matches!(f.value, RawValue::Incomplete(..) | RawValue::Invalid(..))
+        })
+}
+
+fn decode_varints(mut data: &[u8]) -> Option<Vec<u128>>
+{
+    let original = data;
+    let mut values = vec![];
+    while !data.is_empty() {
+        values.push(u128::from_unsigned_varint(&mut data)?);
+    }
+
+    let encoded: BytesMut = values
+        .iter()
+        .flat_map(|v| v.into_unsigned_varint())
+        .collect();
+    match encoded == original {
+        true => Some(values),
+        false => None,
+    }
+}
+
+impl RawMessage
+{
+    /// Encodes the message into protobuf wire format.
+    ///
+    /// Unlike [`MessageValue::encode`](crate::decode::MessageValue::encode), the garbage is
+    /// included in the output so a decoded payload is encoded back into the original bytes.
+    pub fn encode(&self) -> BytesMut
+    {
+        let mut output: BytesMut = self.fields.iter().flat_map(RawField::encode).collect();
+        if let Some(garbage) = &self.garbage {
+            output.extend_from_slice(garbage);
+        }
+        output
+    }
+}
+
+impl RawField
+{
+    fn encode(&self) -> BytesMut
+    {
+        let tag = self.wire_type as u64 + (self.number << 3);
+        let mut output = tag.into_unsigned_varint();
+        output.extend_from_slice(&self.value.encode());
+        if let RawValue::Group(..) | RawValue::GroupBytes(..) = self.value {
+            let end_tag = 4 + (self.number << 3);
+            output.extend_from_slice(&end_tag.into_unsigned_varint());
+        }
+        output
+    }
+}
+
+impl RawValue
+{
+    fn encode(&self) -> BytesMut
+    {
+        let length_delimited = |data: &[u8]| {
+            let mut output = data.len().into_unsigned_varint();
+            output.extend_from_slice(data);
+            output
+        };
+
+        match self {
+            RawValue::Varint(v) => v.into_unsigned_varint(),
+            RawValue::Fixed64(v) => BytesMut::from(v.to_le_bytes().as_ref()),
+            RawValue::Fixed32(v) => BytesMut::from(v.to_le_bytes().as_ref()),
+            RawValue::Message(msg) => length_delimited(&msg.encode()),
+            RawValue::Group(msg) => msg.encode(),
+            RawValue::Packed(values) => {
+                let data: BytesMut = values
+                    .iter()
+                    .flat_map(|v| v.into_unsigned_varint())
+                    .collect();
+                length_delimited(&data)
+            }
+            RawValue::String(s) => length_delimited(s.as_bytes()),
+            RawValue::Bytes(b) => length_delimited(b),
+            RawValue::GroupBytes(b) | RawValue::Incomplete(b) | RawValue::Invalid(b) => {
+                BytesMut::from(b.as_ref())
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Protobuf text format.
+//!
+//! Decoded messages can be printed in the [text format][spec] with
+//! [`MessageValue::to_text_format`](crate::decode::MessageValue::to_text_format). The output
+//! follows `protoc --decode`. Fields that are not defined in the message are printed by their
+//! field numbers the same way `protoc --decode_raw` prints them:
+//!
+//! - Varints as unsigned decimals: `5: 150`.
+//! - 32-bit and 64-bit values as hexadecimals: `5: 0x00000001`.
+//! - Variable length values as nested messages if they can be decoded as such and as strings
+//!   otherwise: `5 { 1: 2 }` or `5: "abc"`.
+//! - Groups as nested messages: `5 { 1: 2 }`.
+//!
+//! Unknown fields nested more than 64 levels deep are printed as strings, or as comments in case
+//! of groups.
+//!
+//! The text format cannot represent incomplete or invalid values, strings that aren't valid
+//! UTF-8, values that exceeded a decode limit or garbage at the end of a message. These are
+//! printed as comments, which are ignored when parsing the text back.
+//!
+//! Text can be converted into a message value with
+//! [`Context::decode_text_format`](crate::context::Context::decode_text_format). The parser
+//! accepts the unknown fields in the format above, as well as `google.protobuf.Any` messages in
+//! the expanded `[type.googleapis.com/package.Message] { ... }` form. Group fields are printed by
+//! the name of the group type and parsed by either the type or the field name. Extensions are
+//! printed and parsed by their full names in brackets: `[package.extension]: 5`.
+//!
+//! [spec]: https://protobuf.dev/reference/protobuf/textformat-spec/
+
+use crate::context::Position;
+use snafu::Snafu;
+
+mod parse;
+mod print;
+
+pub(crate) use print::escape;
+
+/// Error parsing the text format.
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum TextFormatError
+{
+    /// Syntax error in the input.
+    #[snafu(display("Parsing error: {}", source))]
+    SyntaxError
+    {
+        /// Source error.
+        source: Box<dyn std::error::Error + Send + Sync>,
+
+        /// Location of the error.
+        position: Position,
+    },
+
+    /// Message has no field with the given name.
+    #[snafu(display(
+        "Unknown field '{}' (line {}, column {})",
+        name,
+        position.line,
+        position.column
+    ))]
+    UnknownField
+    {
+        /// Name of the field.
+        name: String,
+
+        /// Location of the field.
+        position: Position,
+    },
+
+    /// Value could not be represented as the field type.
+    #[snafu(display(
+        "Invalid value for '{}': {} (line {}, column {})",
+        field,
+        reason,
+        position.line,
+        position.column
+    ))]
+    InvalidValue
+    {
+        /// Name of the field.
+        field: String,
+
+        /// Description of the problem.
+        reason: String,
+
+        /// Location of the value.
+        position: Position,
+    },
+}
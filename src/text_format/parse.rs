@@ -0,0 +1,496 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use pest::{error::LineColLocation, iterators::Pair, Parser};
+
+use super::TextFormatError;
+use crate::context::*;
+use crate::decode::*;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "text_format/text_format.pest"]
+struct TextFormatParser;
+
+impl Context
+{
+    /// Parse a message from the protobuf text format.
+    ///
+    /// See the [`text_format`](crate::text_format) module for the accepted input.
+    pub fn decode_text_format(
+        &self,
+        msg: MessageRef,
+        text: &str,
+    ) -> Result<MessageValue, TextFormatError>
+    {
+        self.resolve_message(msg).decode_text_format(text, self)
+    }
+}
+
+impl MessageInfo
+{
+    /// Parse a message from the protobuf text format.
+    ///
+    /// See the [`text_format`](crate::text_format) module for the accepted input.
+    pub fn decode_text_format(
+        &self,
+        text: &str,
+        ctx: &Context,
+    ) -> Result<MessageValue, TextFormatError>
+    {
+        let document = TextFormatParser::parse(Rule::document, text)
+            .map_err(|e| {
+                let (line, column) = match e.line_col {
+                    LineColLocation::Pos(pos) => pos,
+                    LineColLocation::Span(start, _) => start,
+                };
+                TextFormatError::SyntaxError {
+                    position: Position { line, column },
+                    source: Box::new(e),
+                }
+            })?
+            .next()
+            .unwrap();
+
+        message(self, document.into_inner().next().unwrap(), ctx)
+    }
+}
+
+fn position(pair: &Pair<Rule>) -> Position
+{
+    let (line, column) = pair.as_span().start_pos().line_col();
+    Position { line, column }
+}
+
+fn invalid(field: &str, reason: impl Into<String>, pair: &Pair<Rule>) -> TextFormatError
+{
+    TextFormatError::InvalidValue {
+        field: field.to_string(),
+        reason: reason.into(),
+        position: position(pair),
+    }
+}
+
+fn unknown_field(name: &Pair<Rule>) -> TextFormatError
+{
+    TextFormatError::UnknownField {
+        name: name.as_str().to_string(),
+        position: position(name),
+    }
+}
+
+/// Parsed field in a message.
+///
+/// The values of packed fields may be spread over several fields in the text. These are collected
+/// into a single packed array in the place of the first value.
+enum Entry
+{
+    Field(FieldValue),
+    Packed(u64),
+}
+
+fn message(
+    info: &MessageInfo,
+    fields: Pair<Rule>,
+    ctx: &Context,
+) -> Result<MessageValue, TextFormatError>
+{
+    let mut entries = vec![];
+    let mut packed: BTreeMap<u64, Vec<Value>> = BTreeMap::new();
+    for field in fields.into_inner() {
+        let mut inner = field.into_inner();
+        let name = inner.next().unwrap();
+        let value = inner.next().unwrap();
+
+        let def = match name.as_rule() {
+            Rule::ident => info
+                .get_field_by_name(name.as_str())
+                .or_else(|| group_field(info, name.as_str(), ctx))
+                .ok_or_else(|| unknown_field(&name))?,
+            Rule::fieldNumber => {
+                let number = name
+                    .as_str()
+                    .parse()
+                    .map_err(|_| invalid(name.as_str(), "Invalid field number", &name))?;
+                entries.extend(
+                    unknown_values(name.as_str(), value, ctx)?
+                        .into_iter()
+                        .map(|value| Entry::Field(FieldValue { number, value })),
+                );
+                continue;
+            }
+            Rule::extensionName if info.full_name == "google.protobuf.Any" => {
+                entries.extend(any(info, name, value, ctx)?.into_iter().map(Entry::Field));
+                continue;
+            }
+            Rule::extensionName => {
+                let full_name = name.clone().into_inner().next().unwrap().as_str();
+                ctx.get_extension(full_name)
+                    .filter(|ext| ext.extendee == info.self_ref)
+                    .map(|ext| &ext.field)
+                    .ok_or_else(|| unknown_field(&name))?
+            }
+            _ => return Err(unknown_field(&name)),
+        };
+
+        let values = field_values(def, value, ctx)?;
+        if def.multiplicity == Multiplicity::RepeatedPacked {
+            packed
+                .entry(def.number)
+                .or_insert_with(|| {
+                    entries.push(Entry::Packed(def.number));
+                    vec![]
+                })
+                .extend(values);
+        } else {
+            entries.extend(values.into_iter().map(|value| {
+                Entry::Field(FieldValue {
+                    number: def.number,
+                    value,
+                })
+            }));
+        }
+    }
+
+    let mut msg = MessageValue {
+        msg_ref: info.self_ref,
+        fields: vec![],
+        garbage: None,
+    };
+    for entry in entries {
+        match entry {
+            Entry::Field(field) => msg.fields.push(field),
+            Entry::Packed(number) => {
+                let values = packed.remove(&number).unwrap_or_default();
+                let vt = &info.get_field_or_extension(number, ctx).unwrap().field_type;
+                match PackedArray::from_values(vt, &values) {
+                    Some(array) => msg.fields.push(FieldValue {
+                        number,
+                        value: Value::Packed(array),
+                    }),
+                    None => msg
+                        .fields
+                        .extend(values.into_iter().map(|value| FieldValue { number, value })),
+                }
+            }
+        }
+    }
+
+    Ok(msg)
+}
+
+/// Finds a group field by the name of the group type, which the text format uses for groups.
+fn group_field<'a>(info: &'a MessageInfo, name: &str, ctx: &Context) -> Option<&'a MessageField>
+{
+    info.iter_fields().find(|f| match f.field_type {
+        ValueType::Group(m) => ctx.resolve_message(m).name == name,
+        _ => false,
+    })
+}
+
+fn field_values(
+    field: &MessageField,
+    value: Pair<Rule>,
+    ctx: &Context,
+) -> Result<Vec<Value>, TextFormatError>
+{
+    match value.as_rule() {
+        Rule::list => {
+            match field.multiplicity {
+                Multiplicity::Single | Multiplicity::Optional | Multiplicity::Required => Err(
+                    invalid(&field.name, "List for a non-repeated field", &value),
+                ),
+                _ => value
+                    .into_inner()
+                    .map(|item| field_value(field, item, ctx))
+                    .collect(),
+            }
+        }
+        _ => Ok(vec![field_value(field, value, ctx)?]),
+    }
+}
+
+fn field_value(
+    field: &MessageField,
+    value: Pair<Rule>,
+    ctx: &Context,
+) -> Result<Value, TextFormatError>
+{
+    match (&field.field_type, value.as_rule()) {
+        (ValueType::Message(m), Rule::messageValue) => {
+            let msg = message(
+                ctx.resolve_message(*m),
+                value.into_inner().next().unwrap(),
+                ctx,
+            )?;
+            match field.multiplicity {
+                Multiplicity::Map => Ok(Value::Message(Box::new(msg)).into_map_entry(ctx)),
+                _ => Ok(Value::Message(Box::new(msg))),
+            }
+        }
+        (ValueType::Group(m), Rule::messageValue) => {
+            let msg = message(
+                ctx.resolve_message(*m),
+                value.into_inner().next().unwrap(),
+                ctx,
+            )?;
+            Ok(Value::Group(Box::new(msg)))
+        }
+        (ValueType::Message(..), _) | (ValueType::Group(..), _) => {
+            Err(invalid(&field.name, "Expected a message", &value))
+        }
+        (_, Rule::messageValue) => Err(invalid(&field.name, "Expected a scalar", &value)),
+        (vt, _) => scalar(vt, &value, ctx).map_err(|reason| invalid(&field.name, reason, &value)),
+    }
+}
+
+fn scalar(vt: &ValueType, value: &Pair<Rule>, ctx: &Context) -> Result<Value, String>
+{
+    Ok(match vt {
+        ValueType::Double => Value::Double(float(value)?),
+        ValueType::Float => Value::Float(float(value)? as f32),
+        ValueType::Int32 => Value::Int32(integer(value)?),
+        ValueType::Int64 => Value::Int64(integer(value)?),
+        ValueType::UInt32 => Value::UInt32(integer(value)?),
+        ValueType::UInt64 => Value::UInt64(integer(value)?),
+        ValueType::SInt32 => Value::SInt32(integer(value)?),
+        ValueType::SInt64 => Value::SInt64(integer(value)?),
+        ValueType::Fixed32 => Value::Fixed32(integer(value)?),
+        ValueType::Fixed64 => Value::Fixed64(integer(value)?),
+        ValueType::SFixed32 => Value::SFixed32(integer(value)?),
+        ValueType::SFixed64 => Value::SFixed64(integer(value)?),
+        ValueType::Bool => match value.as_str() {
+            "true" | "True" | "t" | "1" => Value::Bool(true),
+            "false" | "False" | "f" | "0" => Value::Bool(false),
+            _ => return Err("Expected a boolean".to_string()),
+        },
+        ValueType::String => Value::String(
+            String::from_utf8(string(value)?.to_vec())
+                .map_err(|_| "String is not valid UTF-8".to_string())?,
+        ),
+        ValueType::Bytes => Value::Bytes(string(value)?),
+        ValueType::Enum(e) => {
+            let info = ctx.resolve_enum(*e);
+            let value = match value.as_rule() {
+                Rule::identifier => match info.get_field_by_name(value.as_str()) {
+                    Some(field) => field.value,
+                    None => {
+                        return Err(format!(
+                            "Unknown value '{}' for enum '{}'",
+                            value.as_str(),
+                            info.full_name
+                        ))
+                    }
+                },
+                _ => integer::<i32>(value)? as i64,
+            };
+            Value::Enum(EnumValue {
+                enum_ref: info.self_ref,
+                value,
+            })
+        }
+        ValueType::Message(..) | ValueType::Group(..) => {
+            return Err("Expected a message".to_string())
+        }
+    })
+}
+
+fn integer<T: TryFrom<i128>>(value: &Pair<Rule>) -> Result<T, String>
+{
+    if value.as_rule() != Rule::number {
+        return Err("Expected an integer".to_string());
+    }
+
+    let text = value.as_str();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let parsed = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i128::from_str_radix(hex, 16)
+    } else if digits.contains(['.', 'e', 'E', 'f', 'F']) {
+        return Err("Expected an integer".to_string());
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+
+    let parsed = parsed.map_err(|_| "Integer out of range".to_string())?;
+    let parsed = if negative { -parsed } else { parsed };
+    T::try_from(parsed).map_err(|_| "Integer out of range".to_string())
+}
+
+fn float(value: &Pair<Rule>) -> Result<f64, String>
+{
+    let text = value.as_str();
+    match value.as_rule() {
+        Rule::identifier => {
+            let (sign, name) = match text.strip_prefix('-') {
+                Some(name) => (-1.0, name),
+                None => (1.0, text),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "inf" | "infinity" => Ok(sign * f64::INFINITY),
+                "nan" => Ok(f64::NAN),
+                _ => Err("Expected a number".to_string()),
+            }
+        }
+        Rule::number => match integer::<i128>(value) {
+            Ok(v) => Ok(v as f64),
+            Err(_) => text
+                .trim_end_matches(['f', 'F'])
+                .parse()
+                .map_err(|_| "Invalid number".to_string()),
+        },
+        _ => Err("Expected a number".to_string()),
+    }
+}
+
+fn string(value: &Pair<Rule>) -> Result<Bytes, String>
+{
+    if value.as_rule() != Rule::string {
+        return Err("Expected a string".to_string());
+    }
+
+    let mut output = BytesMut::new();
+    for c in value.clone().into_inner().flat_map(|lit| lit.into_inner()) {
+        let text = c.as_str();
+        match c.as_rule() {
+            Rule::octEscape => match u8::from_str_radix(&text[1..], 8) {
+                Ok(b) => output.put_u8(b),
+                Err(_) => return Err(format!("Invalid escape sequence '{}'", text)),
+            },
+            Rule::hexEscape => output.put_u8(u8::from_str_radix(&text[2..], 16).unwrap()),
+            Rule::unicodeEscape => {
+                let c = u32::from_str_radix(&text[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid escape sequence '{}'", text))?;
+                output.put(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            Rule::charEscape => output.put_u8(match &text[1..] {
+                "a" => 0x07,
+                "b" => 0x08,
+                "f" => 0x0C,
+                "n" => 0x0A,
+                "r" => 0x0D,
+                "t" => 0x09,
+                "v" => 0x0B,
+                "?" => b'?',
+                "\\" => b'\\',
+                "'" => b'\'',
+                "\"" => b'"',
+                o => unreachable!("Invalid escape sequence \\{}", o),
+            }),
+            Rule::literal => output.put(text.as_bytes()),
+            r => unreachable!("{:?}: {:?}", r, c),
+        }
+    }
+    Ok(output.freeze())
+}
+
+/// Parse the value of a field referred to by its number, such as `5: 150`.
+fn unknown_values(
+    name: &str,
+    value: Pair<Rule>,
+    ctx: &Context,
+) -> Result<Vec<Value>, TextFormatError>
+{
+    match value.as_rule() {
+        Rule::list => value
+            .into_inner()
+            .map(|item| unknown_value(name, item, ctx))
+            .collect(),
+        _ => Ok(vec![unknown_value(name, value, ctx)?]),
+    }
+}
+
+fn unknown_value(name: &str, value: Pair<Rule>, ctx: &Context) -> Result<Value, TextFormatError>
+{
+    let unknown = match value.as_rule() {
+        Rule::number => {
+            let text = value.as_str();
+            let hex_digits = text
+                .strip_prefix("0x")
+                .or_else(|| text.strip_prefix("0X"))
+                .map(|hex| hex.len());
+            let error = |reason| invalid(name, reason, &value);
+            match hex_digits {
+                Some(8) => UnknownValue::Fixed32(integer(&value).map_err(error)?),
+                Some(16) => UnknownValue::Fixed64(integer(&value).map_err(error)?),
+                _ if text.starts_with('-') => {
+                    UnknownValue::Varint(integer::<i64>(&value).map_err(error)? as u64 as u128)
+                }
+                _ => UnknownValue::Varint(integer(&value).map_err(error)?),
+            }
+        }
+        Rule::string => {
+            UnknownValue::VariableLength(string(&value).map_err(|e| invalid(name, e, &value))?)
+        }
+        Rule::messageValue => {
+            let mut data = BytesMut::new();
+            for field in value.into_inner().next().unwrap().into_inner() {
+                let mut inner = field.into_inner();
+                let name = inner.next().unwrap();
+                let number = match name.as_rule() {
+                    Rule::fieldNumber => name
+                        .as_str()
+                        .parse()
+                        .map_err(|_| invalid(name.as_str(), "Invalid field number", &name))?,
+                    _ => return Err(unknown_field(&name)),
+                };
+                for value in unknown_values(name.as_str(), inner.next().unwrap(), ctx)? {
+                    data.extend_from_slice(&encode_field(number, &value, ctx));
+                }
+            }
+            UnknownValue::VariableLength(data.freeze())
+        }
+        _ => {
+            return Err(invalid(
+                name,
+                "Expected a number, a string or a message",
+                &value,
+            ))
+        }
+    };
+    Ok(Value::Unknown(unknown))
+}
+
+/// Parse an `Any` message in the expanded form: `[type.googleapis.com/pkg.Message] { ... }`.
+fn any(
+    info: &MessageInfo,
+    name: Pair<Rule>,
+    value: Pair<Rule>,
+    ctx: &Context,
+) -> Result<Vec<FieldValue>, TextFormatError>
+{
+    let type_url = name.clone().into_inner().next().unwrap().as_str();
+    let type_name = type_url.rsplit('/').next().unwrap_or_default();
+    let inner = ctx
+        .get_message(type_name)
+        .ok_or_else(|| invalid(type_url, "Unknown type", &name))?;
+    if value.as_rule() != Rule::messageValue {
+        return Err(invalid(type_url, "Expected a message", &value));
+    }
+    let data = message(inner, value.into_inner().next().unwrap(), ctx)?.encode(ctx);
+
+    let field = |field_name| {
+        info.get_field_by_name(field_name)
+            .map(|f| f.number)
+            .ok_or_else(|| invalid(type_url, "Incomplete definition of Any", &name))
+    };
+    Ok(vec![
+        FieldValue {
+            number: field("type_url")?,
+            value: Value::String(type_url.to_string()),
+        },
+        FieldValue {
+            number: field("value")?,
+            value: Value::Bytes(data.freeze()),
+        },
+    ])
+}
@@ -0,0 +1,275 @@
+use std::fmt::{self, Write};
+
+use crate::context::*;
+use crate::decode::*;
+
+/// Maximum depth of unknown fields that are printed as nested messages.
+///
+/// Unknown fields nested deeper are printed as strings or, in case of groups, as comments.
+const MAX_UNKNOWN_DEPTH: usize = 64;
+
+impl MessageValue
+{
+    /// Print the message in the protobuf text format.
+    ///
+    /// See the [`text_format`](crate::text_format) module for the representation of unknown
+    /// fields.
+    pub fn to_text_format(&self, ctx: &Context) -> String
+    {
+        let mut output = String::new();
+        write_message(&mut output, self, ctx, 0);
+        output
+    }
+}
+
+fn write_message(output: &mut String, msg: &MessageValue, ctx: &Context, indent: usize)
+{
+    let info = ctx.resolve_message(msg.msg_ref);
+    for field in &msg.fields {
+        match (&field.value, info.get_field(field.number)) {
+            (Value::Unknown(..), _)
+            | (Value::Incomplete(..), _)
+            | (Value::LimitExceeded(..), _)
+            | (Value::InvalidString(..), _) => write_unknown(
+                output,
+                &field.number.to_string(),
+                &field.value,
+                ctx,
+                indent,
+                0,
+            ),
+            // Extensions are printed by their full name in brackets.
+            (value, None) => match ctx.get_extension_by_number(info.self_ref, field.number) {
+                Some(ext) => {
+                    write_field(output, &format!("[{}]", ext.full_name), value, ctx, indent)
+                }
+                None => write_unknown(output, &field.number.to_string(), value, ctx, indent, 0),
+            },
+            // Groups are printed by the name of the group type.
+            (value, Some(def)) => match def.field_type {
+                ValueType::Group(m) => {
+                    write_field(output, &ctx.resolve_message(m).name, value, ctx, indent)
+                }
+                _ => write_field(output, &def.name, value, ctx, indent),
+            },
+        }
+    }
+
+    if let Some(garbage) = &msg.garbage {
+        write_indent(output, indent);
+        let _ = writeln!(output, "# garbage: \"{}\"", escape(garbage));
+    }
+}
+
+fn write_indent(output: &mut String, indent: usize)
+{
+    for _ in 0..indent {
+        output.push_str("  ");
+    }
+}
+
+fn write_field(output: &mut String, name: &str, value: &Value, ctx: &Context, indent: usize)
+{
+    match value {
+        Value::Packed(packed) => {
+            for item in packed.to_values() {
+                write_field(output, name, &item, ctx, indent);
+            }
+        }
+        Value::Message(msg) | Value::Group(msg) => write_nested(output, name, msg, ctx, indent),
+        Value::LazyMessage(msg) => write_nested(output, name, msg.get(ctx), ctx, indent),
+        Value::MapEntry(entry) => {
+            write_indent(output, indent);
+            let _ = writeln!(output, "{} {{", name);
+            let key = entry.key.as_ref().map(|key| ("key", key));
+            let value = entry.value.as_ref().map(|value| ("value", value));
+            let fields = match entry.value_first {
+                true => [value, key],
+                false => [key, value],
+            };
+            for (name, value) in fields.iter().flatten() {
+                write_field(output, name, value, ctx, indent + 1);
+            }
+            write_indent(output, indent);
+            output.push_str("}\n");
+        }
+        Value::Unknown(..)
+        | Value::Incomplete(..)
+        | Value::LimitExceeded(..)
+        | Value::InvalidString(..) => write_unknown(output, name, value, ctx, indent, 0),
+        value => {
+            write_indent(output, indent);
+            let _ = writeln!(output, "{}: {}", name, scalar(value, ctx));
+        }
+    }
+}
+
+fn write_nested(output: &mut String, name: &str, msg: &MessageValue, ctx: &Context, indent: usize)
+{
+    write_indent(output, indent);
+    let _ = writeln!(output, "{} {{", name);
+    write_message(output, msg, ctx, indent + 1);
+    write_indent(output, indent);
+    output.push_str("}\n");
+}
+
+/// Writes an unknown value. `depth` is the number of unknown values it is nested in.
+fn write_unknown(
+    output: &mut String,
+    name: &str,
+    value: &Value,
+    ctx: &Context,
+    indent: usize,
+    depth: usize,
+)
+{
+    let unknown = match value {
+        Value::Unknown(unknown) => unknown,
+        Value::Incomplete(wire_type, data) => {
+            write_indent(output, indent);
+            let _ = writeln!(
+                output,
+                "# {}: incomplete wire type {}: \"{}\"",
+                name,
+                wire_type,
+                escape(data)
+            );
+            return;
+        }
+        Value::LimitExceeded(limit, wire_type, data) => {
+            write_indent(output, indent);
+            let _ = writeln!(
+                output,
+                "# {}: {} exceeded, wire type {}: \"{}\"",
+                name,
+                limit.name(),
+                wire_type,
+                escape(data)
+            );
+            return;
+        }
+        Value::InvalidString(data) => {
+            write_indent(output, indent);
+            let _ = writeln!(output, "# {}: invalid UTF-8: \"{}\"", name, escape(data));
+            return;
+        }
+
+        // Values of fields that are not in the message definition are normally unknown, but
+        // anything else is printed as well.
+        value => return write_field(output, name, value, ctx, indent),
+    };
+
+    let fields = |data: &[u8]| match depth < MAX_UNKNOWN_DEPTH {
+        true => decode_unknown_fields(data),
+        false => None,
+    };
+
+    write_indent(output, indent);
+    let _ = match unknown {
+        UnknownValue::Varint(v) => writeln!(output, "{}: {}", name, v),
+        UnknownValue::Fixed64(v) => writeln!(output, "{}: 0x{:016x}", name, v),
+        UnknownValue::Fixed32(v) => writeln!(output, "{}: 0x{:08x}", name, v),
+        UnknownValue::VariableLength(data) => match fields(data) {
+            Some(fields) => write_unknown_fields(output, name, &fields, ctx, indent, depth),
+            None => writeln!(output, "{}: \"{}\"", name, escape(data)),
+        },
+        UnknownValue::Group(data) => match fields(data) {
+            Some(fields) => write_unknown_fields(output, name, &fields, ctx, indent, depth),
+            None if data.is_empty() => write_unknown_fields(output, name, &[], ctx, indent, depth),
+            None => writeln!(output, "# {}: group: \"{}\"", name, escape(data)),
+        },
+        UnknownValue::Invalid(wire_type, data) => writeln!(
+            output,
+            "# {}: invalid wire type {}: \"{}\"",
+            name,
+            wire_type,
+            escape(data)
+        ),
+    };
+}
+
+fn write_unknown_fields(
+    output: &mut String,
+    name: &str,
+    fields: &[FieldValue],
+    ctx: &Context,
+    indent: usize,
+    depth: usize,
+) -> fmt::Result
+{
+    writeln!(output, "{} {{", name)?;
+    for field in fields {
+        let name = field.number.to_string();
+        write_unknown(output, &name, &field.value, ctx, indent + 1, depth + 1);
+    }
+    write_indent(output, indent);
+    writeln!(output, "}}")
+}
+
+fn scalar(value: &Value, ctx: &Context) -> String
+{
+    match value {
+        Value::Double(v) => float(*v),
+        Value::Float(v) => float(*v),
+        Value::Int32(v) | Value::SInt32(v) | Value::SFixed32(v) => v.to_string(),
+        Value::Int64(v) | Value::SInt64(v) | Value::SFixed64(v) => v.to_string(),
+        Value::UInt32(v) | Value::Fixed32(v) => v.to_string(),
+        Value::UInt64(v) | Value::Fixed64(v) => v.to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::String(v) => format!("\"{}\"", escape(v.as_bytes())),
+        Value::Bytes(v) => format!("\"{}\"", escape(v)),
+        Value::Enum(v) => match ctx.resolve_enum(v.enum_ref).get_field_by_value(v.value) {
+            Some(field) => field.name.clone(),
+            None => v.value.to_string(),
+        },
+        Value::Message(..)
+        | Value::LazyMessage(..)
+        | Value::Group(..)
+        | Value::MapEntry(..)
+        | Value::Packed(..)
+        | Value::Unknown(..)
+        | Value::Incomplete(..)
+        | Value::LimitExceeded(..)
+        | Value::InvalidString(..) => unreachable!("Non-scalar value printed as scalar"),
+    }
+}
+
+fn float<T>(v: T) -> String
+where
+    T: Into<f64> + Copy + fmt::Display + fmt::LowerExp,
+{
+    let f: f64 = v.into();
+    if f.is_nan() {
+        "nan".to_string()
+    } else if f.is_infinite() {
+        match f > 0.0 {
+            true => "inf".to_string(),
+            false => "-inf".to_string(),
+        }
+    } else if f != 0.0 && (f.abs() >= 1e16 || f.abs() < 1e-4) {
+        format!("{:e}", v)
+    } else {
+        v.to_string()
+    }
+}
+
+/// Escape the data the same way as C-style string literals in `protoc` output.
+pub(crate) fn escape(data: &[u8]) -> String
+{
+    let mut output = String::with_capacity(data.len());
+    for &b in data {
+        match b {
+            b'\n' => output.push_str("\\n"),
+            b'\r' => output.push_str("\\r"),
+            b'\t' => output.push_str("\\t"),
+            b'"' => output.push_str("\\\""),
+            b'\'' => output.push_str("\\'"),
+            b'\\' => output.push_str("\\\\"),
+            0x20..=0x7e => output.push(b as char),
+            _ => {
+                let _ = write!(output, "\\{:03o}", b);
+            }
+        }
+    }
+    output
+}
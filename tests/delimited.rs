@@ -0,0 +1,104 @@
+use std::io::Read;
+
+use bytes::Bytes;
+use protofish::context::Context;
+use protofish::decode::Value;
+use protofish::delimited::{DelimitedDecoder, DelimitedItem, DelimitedReader, DelimitedWriter};
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+    message Fish { string name = 1; int32 weight = 2; }
+"#;
+
+const STREAM: &[u8] = b"\x07\x0a\x05Perch\x00\x09\x0a\x04Pike\x10\x96\x01";
+
+fn names(items: &[DelimitedItem]) -> Vec<Option<Value>>
+{
+    items
+        .iter()
+        .map(|item| match item {
+            DelimitedItem::Message(msg) => msg.fields.first().map(|f| f.value.clone()),
+            item => panic!("Unexpected item: {:?}", item),
+        })
+        .collect()
+}
+
+#[test]
+fn decode_delimited()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let fish = context.get_message("Fish").unwrap().self_ref;
+
+    let items = DelimitedDecoder::new(STREAM, fish, &context).collect::<Vec<_>>();
+    assert_eq!(
+        names(&items),
+        vec![
+            Some(Value::String("Perch".to_string())),
+            None,
+            Some(Value::String("Pike".to_string())),
+        ]
+    );
+
+    let items = DelimitedDecoder::new(Bytes::from_static(STREAM), fish, &context);
+    assert_eq!(items.count(), 3);
+
+    // Truncated in the middle of the message and in the middle of the length prefix.
+    let items = DelimitedDecoder::new(&STREAM[..12], fish, &context).collect::<Vec<_>>();
+    assert_eq!(items.len(), 3);
+    assert_eq!(
+        items[2],
+        DelimitedItem::Incomplete(Bytes::from_static(b"\x09\x0a\x04"))
+    );
+
+    let items = DelimitedDecoder::new(&b"\x00\x96"[..], fish, &context).collect::<Vec<_>>();
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        items[1],
+        DelimitedItem::Incomplete(Bytes::from_static(b"\x96"))
+    );
+}
+
+#[test]
+fn read_delimited()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let fish = context.get_message("Fish").unwrap().self_ref;
+
+    // Reader that ends in the middle of a message.
+    let reader = STREAM.chain(&b"\x05\x0a"[..]);
+    let items = DelimitedReader::new(reader, fish, &context)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(items.len(), 4);
+    assert_eq!(names(&items[..3]).len(), 3);
+    assert_eq!(
+        items[3],
+        DelimitedItem::Incomplete(Bytes::from_static(b"\x05\x0a"))
+    );
+
+    let mut writer = DelimitedWriter::new(vec![]);
+    for item in &items {
+        writer.write_item(item, &context).unwrap();
+    }
+    let mut expected = STREAM.to_vec();
+    expected.extend_from_slice(b"\x05\x0a");
+    assert_eq!(writer.into_inner(), expected);
+}
+
+#[test]
+fn write_delimited()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let fish = context.get_message("Fish").unwrap();
+
+    let mut writer = DelimitedWriter::new(vec![]);
+    for data in [&b"\x0a\x05Perch"[..], b"", b"\x0a\x04Pike\x10\x96\x01"] {
+        writer
+            .write(&fish.decode(data, &context), &context)
+            .unwrap();
+    }
+    assert_eq!(writer.into_inner(), STREAM);
+
+    let large = fish.decode(&[b"\x0a\x80\x01".as_ref(), &[b'a'; 128]].concat(), &context);
+    assert_eq!(&large.encode_delimited(&context)[..4], b"\x83\x01\x0a\x80");
+}
@@ -0,0 +1,323 @@
+use protobuf::descriptor::{FileDescriptorProto, FileDescriptorSet};
+use protobuf::{Message, UnknownValueRef};
+use protofish::context::{
+    Constant, Context, FileSystemLoader, ImportKind, Multiplicity, ParseError, TypeInfo,
+    TypeParent, ValueType, DESCRIPTOR_PROTO,
+};
+use protofish::decode::{FieldValue, MessageValue, PackedArray, Value};
+
+// `FileDescriptorSet` of `data/descriptor_set.proto` and its imports. Equivalent to the output of
+// `protoc --include_imports -I data --descriptor_set_out=data/descriptor_set.bin`.
+const DESCRIPTOR_SET: &[u8] = include_bytes!("data/descriptor_set.bin");
+
+#[test]
+fn from_file_descriptor_set()
+{
+    let context = Context::from_file_descriptor_set(DESCRIPTOR_SET).unwrap();
+
+    let request = context.get_message("fixture.Request").unwrap();
+    let package = match request.parent {
+        TypeParent::Package(p) => context.resolve_package(p),
+        _ => panic!("Request was not a top level message"),
+    };
+    assert_eq!(package.name(), Some("fixture"));
+    assert_eq!(package.path(), Some("descriptor_set.proto"));
+    assert_eq!(package.imports().len(), 1);
+    assert_eq!(package.imports()[0].path, "fixture/common.proto");
+    assert_eq!(package.imports()[0].kind, ImportKind::Default);
+
+    let field = |name| request.get_field_by_name(name).unwrap();
+    assert_eq!(field("name").multiplicity, Multiplicity::Single);
+    assert_eq!(field("limit").multiplicity, Multiplicity::Optional);
+    assert_eq!(field("ids").multiplicity, Multiplicity::RepeatedPacked);
+    assert_eq!(field("unpacked").multiplicity, Multiplicity::Repeated);
+    assert_eq!(field("kinds").multiplicity, Multiplicity::Map);
+
+    // The synthetic oneof of the proto3 optional field is not included.
+    assert_eq!(request.oneofs.len(), 1);
+    assert_eq!(request.oneofs[0].name, "choice");
+    assert_eq!(request.oneofs[0].fields, vec![10, 11]);
+    assert_eq!(field("limit").oneof, None);
+
+    let shared = context.get_message("common.Shared").unwrap();
+    assert_eq!(
+        field("shared").field_type,
+        ValueType::Message(shared.self_ref)
+    );
+
+    let inner = context.get_message("fixture.Request.Inner").unwrap();
+    assert_eq!(
+        field("inner").field_type,
+        ValueType::Message(inner.self_ref)
+    );
+    let data = inner.get_field_by_name("data").unwrap();
+    assert_eq!(data.field_type, ValueType::Bytes);
+    assert_eq!(data.options[0].name, "deprecated");
+    assert_eq!(data.options[0].value, Constant::Bool(true));
+
+    let entry = match field("kinds").field_type {
+        ValueType::Message(m) => context.resolve_message(m),
+        ref t => panic!("Map field type was not a message: {:?}", t),
+    };
+    assert_eq!(entry.full_name, "fixture.Request.KindsEntry");
+    let kind = match context.get_type("common.Kind").unwrap() {
+        TypeInfo::Enum(e) => e,
+        _ => panic!("Kind was not an enum"),
+    };
+    assert_eq!(
+        entry.get_field_by_name("value").unwrap().field_type,
+        ValueType::Enum(kind.self_ref)
+    );
+    assert_eq!(kind.options[0].name, "allow_alias");
+    assert_eq!(kind.get_field_by_value(0).unwrap().name, "UNKNOWN");
+
+    // Proto2 repeated scalars are not packed by default.
+    assert_eq!(
+        shared.get_field_by_name("tags").unwrap().multiplicity,
+        Multiplicity::Repeated
+    );
+    assert_eq!(
+        shared.get_field_by_name("revision").unwrap().multiplicity,
+        Multiplicity::Optional
+    );
+
+    let service = context.get_service("fixture.Fixture").unwrap();
+    let call = service.rpc_by_name("Call").unwrap();
+    assert_eq!(call.input.message, request.self_ref);
+    assert!(!call.input.stream);
+    let stream = service.rpc_by_name("Stream").unwrap();
+    assert!(stream.input.stream);
+    assert!(stream.output.stream);
+    assert_eq!(stream.options[0].name, "deprecated");
+
+    let value = request.decode(b"\x0a\x03foo\x1a\x02\x01\x02", &context);
+    assert_eq!(
+        value.fields,
+        vec![
+            FieldValue {
+                number: 1,
+                value: Value::String("foo".to_string()),
+            },
+            FieldValue {
+                number: 3,
+                value: Value::Packed(PackedArray::Int32(vec![1, 2])),
+            },
+        ]
+    );
+}
+
+#[test]
+fn invalid_descriptor_set()
+{
+    match Context::from_file_descriptor_set(b"\x0a\x10\x0a").unwrap_err() {
+        ParseError::InvalidDescriptor { .. } => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    // File without a name for its message.
+    match Context::from_file_descriptor_set(b"\x0a\x04\x22\x02\x10\x01").unwrap_err() {
+        ParseError::InvalidDescriptor { .. } => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn descriptor_set_roundtrip()
+{
+    let context = Context::from_file_descriptor_set(DESCRIPTOR_SET).unwrap();
+    let exported = context.to_file_descriptor_set();
+    let roundtrip = Context::from_file_descriptor_set(&exported).unwrap();
+    assert_eq!(context, roundtrip);
+}
+
+#[test]
+fn to_file_descriptor_set()
+{
+    let context = Context::parse([
+        r#"
+        syntax = "proto3";
+        package common;
+        enum Kind {
+            option allow_alias = true;
+            UNKNOWN = 0;
+            FIRST = 1 [deprecated = true];
+            ALIAS = 1;
+        }
+        "#,
+        r#"
+        syntax = "proto3";
+        package api;
+        option java_package = "com.example.api";
+
+        message Request {
+            option deprecated = true;
+            optional string name = 1;
+            repeated int32 ids = 2;
+            repeated int32 unpacked = 3 [packed = false];
+            map<string, common.Kind> kinds = 4;
+            oneof choice {
+                option (custom.oneof) = "x";
+                string text = 5;
+                Inner inner = 6;
+            }
+            common.Kind kind = 7 [(custom.field).value = -5, ctype = CORD];
+
+            message Inner {}
+        }
+
+        service Api {
+            option (custom.service) = 15;
+            rpc Call(stream Request) returns (stream Request.Inner) {
+                option idempotency_level = NO_SIDE_EFFECTS;
+            }
+        }
+        "#,
+    ])
+    .unwrap();
+
+    let exported = context.to_file_descriptor_set();
+    let roundtrip = Context::from_file_descriptor_set(&exported).unwrap();
+
+    for name in [
+        "common.Kind",
+        "api.Request",
+        "api.Request.KindsEntry",
+        "api.Request.Inner",
+    ] {
+        assert_eq!(context.get_type(name), roundtrip.get_type(name), "{}", name);
+    }
+    assert_eq!(
+        context.get_service("api.Api"),
+        roundtrip.get_service("api.Api")
+    );
+
+    let request = roundtrip.get_message("api.Request").unwrap();
+    let package = match request.parent {
+        TypeParent::Package(p) => roundtrip.resolve_package(p),
+        _ => panic!("Request was not a top level message"),
+    };
+    assert_eq!(package.path(), Some("file1.proto"));
+    assert_eq!(package.imports().len(), 1);
+    assert_eq!(package.imports()[0].path, "file0.proto");
+}
+
+#[test]
+fn declaration_order()
+{
+    let context = Context::parse([r#"
+        syntax = "proto3";
+        enum Level {
+            LOW = 0;
+            HIGH = 1;
+            NEGATIVE = -1;
+        }
+        message Message {
+            string b = 2;
+            oneof choice {
+                int32 c = 3;
+                int32 a = 1;
+            }
+            int32 d = 4;
+        }
+        "#])
+    .unwrap();
+    let exported = context.to_file_descriptor_set();
+
+    let descriptor = Context::parse([DESCRIPTOR_PROTO]).unwrap();
+    let decode = |data: &[u8]| {
+        descriptor
+            .get_message("google.protobuf.FileDescriptorSet")
+            .unwrap()
+            .decode(data, &descriptor)
+    };
+
+    // Names of the items in the `number` field of the message.
+    let messages = |msg: &MessageValue, number| -> Vec<MessageValue> {
+        msg.fields
+            .iter()
+            .filter(|f| f.number == number)
+            .map(|f| match &f.value {
+                Value::Message(m) => (**m).clone(),
+                v => panic!("Unexpected value: {:?}", v),
+            })
+            .collect()
+    };
+    let names = |msg: &MessageValue, number| -> Vec<String> {
+        messages(msg, number)
+            .iter()
+            .map(|m| match &m.fields[0].value {
+                Value::String(s) => s.clone(),
+                v => panic!("Unexpected value: {:?}", v),
+            })
+            .collect()
+    };
+
+    let set = decode(&exported);
+    let file = &messages(&set, 1)[0];
+    let level = &messages(file, 5)[0];
+    assert_eq!(names(level, 2), vec!["LOW", "HIGH", "NEGATIVE"]);
+    let message = &messages(file, 4)[0];
+    assert_eq!(names(message, 2), vec!["b", "c", "a", "d"]);
+
+    // Descriptors produced by protoc keep their order as well.
+    let context = Context::from_file_descriptor_set(DESCRIPTOR_SET).unwrap();
+    let exported = decode(&context.to_file_descriptor_set());
+    let original = decode(DESCRIPTOR_SET);
+    for (exported, original) in messages(&exported, 1).iter().zip(&messages(&original, 1)) {
+        for (exported, original) in messages(exported, 4).iter().zip(&messages(original, 4)) {
+            assert_eq!(names(exported, 2), names(original, 2));
+        }
+        for (exported, original) in messages(exported, 5).iter().zip(&messages(original, 5)) {
+            assert_eq!(names(exported, 2), names(original, 2));
+        }
+    }
+}
+
+#[test]
+fn custom_options()
+{
+    let mut loader = FileSystemLoader::new(["tests/data"]);
+    let context = Context::parse_with_loader(["custom_options.proto"], &mut loader).unwrap();
+    let exported = FileDescriptorSet::parse_from_bytes(&context.to_file_descriptor_set()).unwrap();
+    let exported = exported
+        .file
+        .iter()
+        .find(|f| f.name() == "custom_options.proto")
+        .unwrap();
+
+    // The descriptor produced by the parser of the `protobuf` crate, which stores the custom
+    // options as extension fields the same way `protoc` does.
+    let expected: FileDescriptorProto = protobuf_parse::Parser::new()
+        .pure()
+        .include("tests/data")
+        .input("tests/data/custom_options.proto")
+        .parse_and_typecheck()
+        .unwrap()
+        .file_descriptors
+        .into_iter()
+        .find(|f| f.name() == "custom_options.proto")
+        .unwrap();
+
+    for (msg, expected_msg) in exported.message_type.iter().zip(&expected.message_type) {
+        assert_eq!(msg.options, expected_msg.options, "{}", msg.name());
+        for (field, expected_field) in msg.field.iter().zip(&expected_msg.field) {
+            assert_eq!(field.options, expected_field.options, "{}", field.name());
+        }
+    }
+    for (value, expected_value) in exported.enum_type[0]
+        .value
+        .iter()
+        .zip(&expected.enum_type[0].value)
+    {
+        assert_eq!(value.options, expected_value.options, "{}", value.name());
+    }
+
+    let request = &exported.message_type[1];
+    assert!(request.field[0].options.uninterpreted_option.is_empty());
+    let weight = request.field[0]
+        .options
+        .special_fields
+        .unknown_fields()
+        .get(50001);
+    assert_eq!(weight, Some(UnknownValueRef::Varint(3)));
+}
@@ -0,0 +1,154 @@
+use protofish::context::{
+    Context, EnumType, FieldPresence, Multiplicity, Syntax, TypeInfo, TypeParent, ValueType,
+};
+use protofish::decode::{UnknownValue, Value};
+
+const PROTO: &str = r#"
+    edition = "2023";
+    package edition;
+
+    option features.enum_type = CLOSED;
+
+    message Message {
+        option features.field_presence = IMPLICIT;
+
+        int32 implicit = 1;
+        int32 explicit = 2 [features.field_presence = EXPLICIT];
+        int32 required = 3 [features.field_presence = LEGACY_REQUIRED];
+        repeated int32 packed = 4;
+        repeated int32 expanded = 5 [features.repeated_field_encoding = EXPANDED];
+        Inner inner = 6 [features.message_encoding = DELIMITED];
+        Kind kind = 7;
+        map<string, Inner> inners = 8;
+
+        message Nested {
+            int32 value = 1;
+        }
+    }
+
+    message Inner {
+        int32 value = 1;
+    }
+
+    enum Kind {
+        option features.enum_type = OPEN;
+        NONE = 0;
+        SOME = 1;
+    }
+
+    enum Closed { FIRST = 1; }
+"#;
+
+#[test]
+fn resolve_features()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let msg = context.get_message("edition.Message").unwrap();
+    let inner = context.get_message("edition.Inner").unwrap();
+
+    let package = match msg.parent {
+        TypeParent::Package(p) => context.resolve_package(p),
+        p => panic!("Unexpected parent: {:?}", p),
+    };
+    assert_eq!(package.syntax(), Syntax::Edition2023);
+    assert_eq!(package.features().enum_type, EnumType::Closed);
+    assert_eq!(package.features().field_presence, FieldPresence::Explicit);
+
+    let field = |name| msg.get_field_by_name(name).unwrap();
+    assert_eq!(field("implicit").multiplicity, Multiplicity::Single);
+    assert_eq!(field("explicit").multiplicity, Multiplicity::Optional);
+    assert_eq!(field("required").multiplicity, Multiplicity::Required);
+    assert_eq!(field("packed").multiplicity, Multiplicity::RepeatedPacked);
+    assert_eq!(field("expanded").multiplicity, Multiplicity::Repeated);
+    assert_eq!(field("inner").field_type, ValueType::Group(inner.self_ref));
+    assert_eq!(field("inners").multiplicity, Multiplicity::Map);
+    assert_eq!(
+        field("required").features.field_presence,
+        FieldPresence::LegacyRequired
+    );
+
+    // Features cascade from the file and the parent messages.
+    let nested = context.get_message("edition.Message.Nested").unwrap();
+    assert_eq!(nested.features.field_presence, FieldPresence::Implicit);
+    assert_eq!(
+        nested.get_field(1).unwrap().multiplicity,
+        Multiplicity::Single
+    );
+    assert_eq!(
+        inner.get_field(1).unwrap().multiplicity,
+        Multiplicity::Optional
+    );
+
+    let enum_type = |name| match context.get_type(name) {
+        Some(TypeInfo::Enum(e)) => e.features.enum_type,
+        t => panic!("Unexpected type: {:?}", t),
+    };
+    assert_eq!(enum_type("edition.Kind"), EnumType::Open);
+    assert_eq!(enum_type("edition.Closed"), EnumType::Closed);
+}
+
+#[test]
+fn decode_with_features()
+{
+    let context = Context::parse([
+        PROTO,
+        r#"
+        edition = "2023";
+        package closed;
+        option features.enum_type = CLOSED;
+        enum Kind { NONE = 0; SOME = 1; }
+        message Message { Kind kind = 1; }
+        "#,
+    ])
+    .unwrap();
+    let msg = context.get_message("edition.Message").unwrap();
+
+    let data = b"\x28\x01\x28\x02\x33\x08\x05\x34\x38\x05";
+    let value = msg.decode(data, &context);
+    assert_eq!(value.fields[0].value, Value::Int32(1));
+    assert_eq!(value.fields[1].value, Value::Int32(2));
+    match &value.fields[2].value {
+        Value::Group(inner) => assert_eq!(inner.fields[0].value, Value::Int32(5)),
+        v => panic!("Unexpected value: {:?}", v),
+    }
+    assert!(matches!(&value.fields[3].value, Value::Enum(e) if e.value == 5));
+    assert_eq!(value.encode(&context), data.as_ref());
+
+    // Unknown values of closed enums are unknown fields.
+    let closed = context.get_message("closed.Message").unwrap();
+    let value = closed.decode(b"\x08\x01\x08\x05", &context);
+    assert!(matches!(&value.fields[0].value, Value::Enum(e) if e.value == 1));
+    assert_eq!(
+        value.fields[1].value,
+        Value::Unknown(UnknownValue::Varint(5))
+    );
+    assert_eq!(value.encode(&context), b"\x08\x01\x08\x05".as_ref());
+}
+
+#[test]
+fn editions_descriptor_roundtrip()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let roundtrip = Context::from_file_descriptor_set(&context.to_file_descriptor_set()).unwrap();
+
+    let original = context.get_message("edition.Message").unwrap();
+    let msg = roundtrip.get_message("edition.Message").unwrap();
+    match msg.parent {
+        TypeParent::Package(p) => {
+            let package = roundtrip.resolve_package(p);
+            assert_eq!(package.syntax(), Syntax::Edition2023);
+            assert_eq!(package.features().enum_type, EnumType::Closed);
+        }
+        p => panic!("Unexpected parent: {:?}", p),
+    }
+    assert_eq!(msg.features, original.features);
+    for (field, original) in msg.iter_fields().zip(original.iter_fields()) {
+        assert_eq!(field.name, original.name);
+        assert_eq!(field.multiplicity, original.multiplicity);
+        assert_eq!(field.features, original.features);
+        assert_eq!(
+            std::mem::discriminant(&field.field_type),
+            std::mem::discriminant(&original.field_type)
+        );
+    }
+}
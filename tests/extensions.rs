@@ -0,0 +1,199 @@
+use protofish::context::{
+    Context, Extension, InsertError, MessageField, Multiplicity, Package, ParseError, TypeParent,
+    ValueType,
+};
+use protofish::decode::{PackedArray, Value};
+
+const PROTO: &str = r#"
+    syntax = "proto2";
+    package ext;
+
+    message Base {
+        optional int32 id = 1;
+        extensions 100 to max;
+    }
+
+    extend Base {
+        optional string note = 100;
+        repeated int32 tags = 101 [packed = true];
+        optional group Extra = 102 {
+            optional int32 value = 1;
+        }
+    }
+
+    message Scope {
+        extend Base {
+            optional Scope scope = 103;
+        }
+    }
+"#;
+
+#[test]
+fn parse_extensions()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let base = context.get_message("ext.Base").unwrap();
+    let scope = context.get_message("ext.Scope").unwrap();
+
+    let note = context.get_extension("ext.note").unwrap();
+    assert_eq!(note.extendee, base.self_ref);
+    assert_eq!(note.field.number, 100);
+    assert_eq!(note.field.field_type, ValueType::String);
+    assert!(matches!(note.parent, TypeParent::Package(..)));
+    assert_eq!(context.resolve_extension(note.self_ref), note);
+
+    let tags = context.get_extension_by_number(base.self_ref, 101).unwrap();
+    assert_eq!(tags.full_name, "ext.tags");
+    assert_eq!(tags.field.multiplicity, Multiplicity::RepeatedPacked);
+
+    let extra = context.get_message("ext.Extra").unwrap();
+    let extra_ext = context.get_extension("ext.extra").unwrap();
+    assert_eq!(extra_ext.field.field_type, ValueType::Group(extra.self_ref));
+
+    let scoped = context.get_extension_by_number(base.self_ref, 103).unwrap();
+    assert_eq!(scoped.full_name, "ext.Scope.scope");
+    assert_eq!(scoped.parent, TypeParent::Message(scope.self_ref));
+    assert_eq!(scoped.field.field_type, ValueType::Message(scope.self_ref));
+
+    assert_eq!(base.iter_extensions().count(), 4);
+    assert_eq!(base.get_field(100), None);
+    assert!(context
+        .get_extension_by_number(scope.self_ref, 100)
+        .is_none());
+}
+
+#[test]
+fn decode_extensions()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let base = context.get_message("ext.Base").unwrap();
+
+    let data = b"\x08\x01\xa2\x06\x02hi\xaa\x06\x02\x01\x02\xb3\x06\x08\x05\xb4\x06";
+    let value = base.decode(data, &context);
+    assert_eq!(value.fields[0].value, Value::Int32(1));
+    assert_eq!(value.fields[1].value, Value::String("hi".to_string()));
+    assert_eq!(
+        value.fields[2].value,
+        Value::Packed(PackedArray::Int32(vec![1, 2]))
+    );
+    match &value.fields[3].value {
+        Value::Group(extra) => assert_eq!(extra.fields[0].value, Value::Int32(5)),
+        v => panic!("Unexpected value: {:?}", v),
+    }
+    assert_eq!(value.encode(&context), data.as_ref());
+
+    let text = value.to_text_format(&context);
+    assert_eq!(
+        text,
+        r#"id: 1
+[ext.note]: "hi"
+[ext.tags]: 1
+[ext.tags]: 2
+[ext.extra] {
+  value: 5
+}
+"#
+    );
+    let parsed = base.decode_text_format(&text, &context).unwrap();
+    assert_eq!(parsed.encode(&context), data.as_ref());
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn extensions_json()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let base = context.get_message("ext.Base").unwrap();
+
+    let data = b"\x08\x01\xa2\x06\x02hi\xaa\x06\x02\x01\x02\xb3\x06\x08\x05\xb4\x06";
+    let json = base.decode(data, &context).to_json(&context);
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "id": 1,
+            "[ext.note]": "hi",
+            "[ext.tags]": [1, 2],
+            "[ext.extra]": { "value": 5 },
+        })
+    );
+    let parsed = base.decode_json(&json, &context).unwrap();
+    assert_eq!(parsed.encode(&context), data.as_ref());
+}
+
+#[test]
+fn duplicate_extensions()
+{
+    let err = Context::parse([r#"
+        syntax = "proto2";
+        message Base { extensions 100 to 200; }
+        extend Base { optional int32 a = 100; }
+        message Other { extend Base { optional int32 b = 100; } }
+    "#])
+    .unwrap_err();
+    assert!(matches!(err, ParseError::DuplicateExtension { name, .. } if name == "Other.b"));
+
+    let err = Context::parse([r#"
+        syntax = "proto2";
+        enum Kind { NONE = 0; }
+        extend Kind { optional int32 a = 100; }
+    "#])
+    .unwrap_err();
+    assert!(matches!(err, ParseError::InvalidTypeKind { .. }));
+}
+
+#[test]
+fn insert_extension()
+{
+    let mut context = Context::parse([PROTO]).unwrap();
+    let base = context.get_message("ext.Base").unwrap().self_ref;
+    let package = context
+        .insert_package(Package::new(Some("other".to_string())))
+        .unwrap();
+
+    let field = MessageField::new("count".to_string(), 110, ValueType::UInt32);
+    let ext_ref = context
+        .insert_extension(Extension::new(field, base, TypeParent::Package(package)))
+        .unwrap();
+    let ext = context.get_extension("other.count").unwrap();
+    assert_eq!(ext.self_ref, ext_ref);
+    assert_eq!(
+        context.get_extension_by_number(base, 110).unwrap().self_ref,
+        ext_ref
+    );
+    assert_eq!(
+        context
+            .get_message("ext.Base")
+            .unwrap()
+            .decode(b"\xf0\x06\x07", &context)
+            .fields[0]
+            .value,
+        Value::UInt32(7)
+    );
+
+    let field = MessageField::new("conflict".to_string(), 100, ValueType::Int32);
+    let note = context.get_extension("ext.note").unwrap().self_ref;
+    assert!(matches!(
+        context.insert_extension(Extension::new(field, base, TypeParent::Package(package))),
+        Err(InsertError::ExtensionExists { original }) if original == note
+    ));
+}
+
+#[test]
+fn extensions_descriptor_roundtrip()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let roundtrip = Context::from_file_descriptor_set(&context.to_file_descriptor_set()).unwrap();
+    let base = roundtrip.get_message("ext.Base").unwrap();
+
+    for name in ["ext.note", "ext.tags", "ext.extra", "ext.Scope.scope"] {
+        let original = context.get_extension(name).unwrap();
+        let ext = roundtrip.get_extension(name).unwrap();
+        assert_eq!(ext.extendee, base.self_ref);
+        assert_eq!(ext.field.number, original.field.number);
+        assert_eq!(ext.field.multiplicity, original.field.multiplicity);
+        assert_eq!(
+            std::mem::discriminant(&ext.field.field_type),
+            std::mem::discriminant(&original.field.field_type)
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use protofish::context::{Context, MessageField, MessageInfo, Package, TypeParent, ValueType};
+use protofish::decode::{FieldValue, MessageValue, UnknownValue, Value};
+use protofish::raw::{decode_raw, RawValue};
+
+// message Outer {
+//     optional int32 value = 1;
+//     optional group Inner = 2 { optional int32 a = 1; }
+// }
+fn context() -> Context
+{
+    let mut context = Context::new();
+    let package = context
+        .insert_package(Package::new(Some("test".to_string())))
+        .unwrap();
+
+    let mut inner = MessageInfo::new("Inner".to_string(), TypeParent::Package(package));
+    inner
+        .add_field(MessageField::new("a".to_string(), 1, ValueType::Int32))
+        .unwrap();
+    let inner = context.insert_message(inner).unwrap();
+
+    let mut outer = MessageInfo::new("Outer".to_string(), TypeParent::Package(package));
+    outer
+        .add_field(MessageField::new("value".to_string(), 1, ValueType::Int32))
+        .unwrap();
+    outer
+        .add_field(MessageField::new(
+            "inner".to_string(),
+            2,
+            ValueType::Group(inner),
+        ))
+        .unwrap();
+    context.insert_message(outer).unwrap();
+
+    context
+}
+
+#[test]
+fn decode_group()
+{
+    let context = context();
+    let outer = context.get_message("test.Outer").unwrap();
+    let inner = context.get_message("test.Inner").unwrap();
+
+    let data = b"\x08\x01\x13\x08\x05\x14\x08\x02";
+    let value = outer.decode(data, &context);
+    assert_eq!(
+        value.fields,
+        vec![
+            FieldValue {
+                number: 1,
+                value: Value::Int32(1),
+            },
+            FieldValue {
+                number: 2,
+                value: Value::Group(Box::new(MessageValue {
+                    msg_ref: inner.self_ref,
+                    fields: vec![FieldValue {
+                        number: 1,
+                        value: Value::Int32(5),
+                    }],
+                    garbage: None,
+                })),
+            },
+            FieldValue {
+                number: 1,
+                value: Value::Int32(2),
+            },
+        ]
+    );
+    assert_eq!(value.encode(&context), data.as_ref());
+}
+
+#[test]
+fn decode_unknown_group()
+{
+    let context = context();
+    let outer = context.get_message("test.Outer").unwrap();
+
+    // Group 5 containing a varint and an empty nested group 4.
+    let data = b"\x2b\x08\x01\x23\x24\x2c\x08\x07";
+    let value = outer.decode(data, &context);
+    assert_eq!(
+        value.fields,
+        vec![
+            FieldValue {
+                number: 5,
+                value: Value::Unknown(UnknownValue::Group(b"\x08\x01\x23\x24".as_ref().into())),
+            },
+            FieldValue {
+                number: 1,
+                value: Value::Int32(7),
+            },
+        ]
+    );
+    assert_eq!(value.encode(&context), data.as_ref());
+
+    // Missing end-group tag.
+    let value = outer.decode(b"\x2b\x08\x01", &context);
+    assert_eq!(
+        value.fields[0].value,
+        Value::Incomplete(3, b"\x08\x01".as_ref().into())
+    );
+
+    // End-group tag of another field.
+    let value = outer.decode(b"\x2b\x08\x01\x34\x08\x01", &context);
+    assert_eq!(
+        value.fields[0].value,
+        Value::Unknown(UnknownValue::Invalid(
+            3,
+            b"\x08\x01\x34\x08\x01".as_ref().into()
+        ))
+    );
+}
+
+#[test]
+fn group_text_format()
+{
+    let context = context();
+    let outer = context.get_message("test.Outer").unwrap();
+
+    let value = outer.decode(b"\x13\x08\x05\x14\x2b\x08\x01\x23\x24\x2c", &context);
+    let text = value.to_text_format(&context);
+    assert_eq!(
+        text,
+        r#"Inner {
+  a: 5
+}
+5 {
+  1: 1
+  4 {
+  }
+}
+"#
+    );
+
+    let parsed = outer.decode_text_format("Inner { a: 5 } inner { a: 6 }", &context);
+    assert_eq!(
+        parsed.unwrap().encode(&context),
+        b"\x13\x08\x05\x14\x13\x08\x06\x14".as_ref()
+    );
+}
+
+#[test]
+fn raw_group()
+{
+    let data = b"\x08\x01\x13\x08\x05\x23\x24\x14";
+    let msg = decode_raw(data);
+    let group = match &msg.fields[1].value {
+        RawValue::Group(group) => group,
+        v => panic!("Unexpected value: {:?}", v),
+    };
+    assert_eq!(msg.fields[1].number, 2);
+    assert_eq!(group.fields[0].value, RawValue::Varint(5));
+    assert!(matches!(&group.fields[1].value, RawValue::Group(g) if g.fields.is_empty()));
+    assert_eq!(msg.encode(), data.as_ref());
+}
+
+#[test]
+fn group_descriptor()
+{
+    let context = context();
+    let roundtrip = Context::from_file_descriptor_set(&context.to_file_descriptor_set()).unwrap();
+    let outer = roundtrip.get_message("test.Outer").unwrap();
+    let inner = roundtrip.get_message("test.Inner").unwrap();
+    assert_eq!(
+        outer.get_field(2).unwrap().field_type,
+        ValueType::Group(inner.self_ref)
+    );
+}
@@ -0,0 +1,255 @@
+use bytes::Bytes;
+use protofish::context::Context;
+use protofish::decode::{DecodeLimit, DecodeOptions, Value};
+use protofish::grpc::{Compression, Direction, Frame, FrameDecoder, GrpcError};
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+    package fish;
+
+    message Request { string kind = 1; }
+    message Response { int32 distance = 1; }
+    service Fish {
+        rpc Swim(Request) returns (stream Response);
+    }
+"#;
+
+#[test]
+fn split_frames()
+{
+    let mut decoder = FrameDecoder::new();
+    let stream = b"\x00\x00\x00\x00\x03\x08\xa9\x46\x00\x00\x00\x00\x00\x01\x00\x00\x00\x02ab";
+
+    // Feed the stream one byte at a time.
+    let mut frames = vec![];
+    for chunk in stream.chunks(1) {
+        decoder.push(chunk);
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            frames.push(frame);
+        }
+    }
+    let mut compressed = Frame::new(Bytes::from_static(b"ab"));
+    compressed.compressed = true;
+    assert_eq!(
+        frames,
+        vec![
+            Frame::new(Bytes::from_static(b"\x08\xa9\x46")),
+            Frame::new(Bytes::new()),
+            compressed,
+        ]
+    );
+    assert!(decoder.remaining().is_empty());
+
+    // Multiple frames in a single chunk with a truncated frame at the end.
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&stream[..12]);
+    assert_eq!(
+        decoder.next_frame().unwrap().unwrap().data.as_ref(),
+        b"\x08\xa9\x46"
+    );
+    assert!(decoder.next_frame().unwrap().is_none());
+    assert_eq!(decoder.remaining(), b"\x00\x00\x00\x00");
+}
+
+#[test]
+fn frame_length_limit()
+{
+    // The length is checked before the frame is buffered.
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&b"\x00\xff\xff\xff\xff"[..]);
+    match decoder.next_frame() {
+        Err(GrpcError::FrameTooLarge { len, limit }) => {
+            assert_eq!(len, u32::MAX as usize);
+            assert_eq!(limit, 4 * 1024 * 1024);
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(decoder.next_frame().is_err());
+
+    let mut decoder = FrameDecoder::with_limit(3);
+    decoder.push(&b"\x00\x00\x00\x00\x03abc\x00\x00\x00\x00\x04"[..]);
+    assert_eq!(decoder.next_frame().unwrap().unwrap().data.as_ref(), b"abc");
+    assert!(matches!(
+        decoder.next_frame(),
+        Err(GrpcError::FrameTooLarge { len: 4, limit: 3 })
+    ));
+}
+
+#[test]
+fn decode_frames()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let rpc = context
+        .get_service("fish.Fish")
+        .unwrap()
+        .rpc_by_name("Swim")
+        .unwrap();
+    assert!(rpc.arg(Direction::Response).stream);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&b"\x00\x00\x00\x00\x07\x0a\x05Perch"[..]);
+    let request = decoder
+        .next_frame()
+        .unwrap()
+        .unwrap()
+        .decode(rpc, Direction::Request, &context)
+        .unwrap();
+    assert_eq!(request.msg_ref, rpc.input.message);
+    assert_eq!(request.fields[0].value, Value::String("Perch".to_string()));
+
+    let response = Frame::new(Bytes::from_static(b"\x08\xa9\x46"))
+        .decode(rpc, Direction::Response, &context)
+        .unwrap();
+    assert_eq!(response.msg_ref, rpc.output.message);
+    assert_eq!(response.fields[0].value, Value::Int32(9001));
+    assert_eq!(
+        response.encode_frame(&context).unwrap(),
+        b"\x00\x00\x00\x00\x03\x08\xa9\x46".as_ref()
+    );
+
+    let mut compressed = Frame::new(Bytes::from_static(b"\x08\xa9\x46"));
+    compressed.compressed = true;
+    assert!(matches!(
+        compressed.decode(rpc, Direction::Response, &context),
+        Err(GrpcError::Compressed { .. })
+    ));
+    assert_eq!(
+        compressed.encode().unwrap(),
+        b"\x01\x00\x00\x00\x03\x08\xa9\x46".as_ref()
+    );
+
+    // The limits are passed on to the decoder.
+    let mut options = DecodeOptions::default();
+    options.max_string_len = 3;
+    let request = Frame::new(Bytes::from_static(b"\x0a\x05Perch"))
+        .decode_with_options(rpc, Direction::Request, &context, &options)
+        .unwrap();
+    assert!(matches!(
+        request.fields[0].value,
+        Value::LimitExceeded(DecodeLimit::StringLength, ..)
+    ));
+}
+
+#[test]
+fn compressed_frames()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let rpc = context
+        .get_service("fish.Fish")
+        .unwrap()
+        .rpc_by_name("Swim")
+        .unwrap();
+    let response = Frame::new(Bytes::from_static(b"\x08\xa9\x46"))
+        .decode(rpc, Direction::Response, &context)
+        .unwrap();
+
+    for name in ["identity", "gzip", "deflate", "snappy", "zstd"] {
+        let compression = Compression::from_name(name).unwrap();
+        assert_eq!(compression.name(), name);
+
+        let encoded = match response.encode_compressed_frame(compression, &context) {
+            Ok(encoded) => encoded,
+            Err(GrpcError::UnsupportedEncoding { encoding, data }) => {
+                assert!(!compression.is_supported());
+                assert_eq!(encoding, name);
+                assert_eq!(data.as_ref(), b"\x08\xa9\x46");
+                continue;
+            }
+            Err(e) => panic!("Unexpected error: {}", e),
+        };
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(encoded);
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.compressed, compression != Compression::Identity);
+        let decoded = frame
+            .decompress(compression)
+            .unwrap()
+            .decode(rpc, Direction::Response, &context)
+            .unwrap();
+        assert_eq!(decoded, response);
+    }
+
+    assert_eq!(Compression::from_name(" GZip"), Some(Compression::Gzip));
+    assert_eq!(Compression::from_name("br"), None);
+}
+
+#[test]
+#[cfg(all(feature = "gzip", feature = "deflate"))]
+fn decompress_frames()
+{
+    let mut gzip = Frame::new(Bytes::from_static(
+        b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xe3\x58\xe9\x06\x00\xaf\x6b\x28\x11\x03\x00\x00\x00",
+    ));
+    gzip.compressed = true;
+    assert_eq!(
+        gzip.decompress(Compression::Gzip).unwrap(),
+        Frame::new(Bytes::from_static(b"\x08\xa9\x46"))
+    );
+
+    let mut deflate = Frame::new(Bytes::from_static(
+        b"\x78\x9c\xe3\x58\xe9\x06\x00\x01\xb3\x00\xf8",
+    ));
+    deflate.compressed = true;
+    assert_eq!(
+        deflate.decompress(Compression::Deflate).unwrap(),
+        Frame::new(Bytes::from_static(b"\x08\xa9\x46"))
+    );
+
+    // The raw data is available when decompression fails.
+    match deflate.decompress(Compression::Gzip) {
+        Err(GrpcError::InvalidCompressedData { encoding, data, .. }) => {
+            assert_eq!(encoding, "gzip");
+            assert_eq!(data, deflate.data);
+        }
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn decompression_limit()
+{
+    let mut options = DecodeOptions::default();
+    options.max_total_bytes = 1000;
+
+    for compression in [
+        Compression::Gzip,
+        Compression::Deflate,
+        Compression::Snappy,
+        Compression::Zstd,
+    ] {
+        if !compression.is_supported() {
+            continue;
+        }
+
+        // A small frame that expands into a megabyte.
+        let frame = Frame::new(vec![0; 1 << 20].into())
+            .compress(compression)
+            .unwrap();
+        match frame.decompress_with_options(compression, &options) {
+            Err(GrpcError::DecompressedSizeExceeded {
+                encoding,
+                limit,
+                data,
+            }) => {
+                assert_eq!(encoding, compression.name());
+                assert_eq!(limit, 1000);
+                assert_eq!(data, frame.data);
+            }
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // Data up to the limit is decompressed.
+        let frame = Frame::new(vec![0; 1000].into())
+            .compress(compression)
+            .unwrap();
+        assert_eq!(
+            frame
+                .decompress_with_options(compression, &options)
+                .unwrap()
+                .data
+                .len(),
+            1000
+        );
+    }
+}
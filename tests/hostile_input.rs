@@ -0,0 +1,363 @@
+use bytes::Bytes;
+use proptest::prelude::*;
+use protofish::context::Context;
+use protofish::decode::{
+    DecodeOptions, FieldValue, MessageValue, PackedArray, UnknownValue, Value,
+};
+use protofish::raw::{decode_raw, RawValue};
+
+fn context() -> Context
+{
+    Context::parse([include_str!("data/hostile.proto")]).unwrap()
+}
+
+fn decode(ctx: &Context, data: &[u8]) -> Vec<FieldValue>
+{
+    let node = ctx.get_message("hostile.Node").unwrap();
+    let msg = node.decode(data, ctx);
+    assert_eq!(msg.garbage, None);
+    msg.fields
+}
+
+fn encode(ctx: &Context, data: &[u8]) -> Vec<u8>
+{
+    let node = ctx.get_message("hostile.Node").unwrap();
+    node.decode(data, ctx).encode(ctx).to_vec()
+}
+
+#[test]
+fn overlong_varints()
+{
+    let ctx = context();
+
+    // Eleven bytes is longer than any varint.
+    let mut data = b"\x18".to_vec();
+    data.extend_from_slice(&[0xff; 10]);
+    data.push(0x01);
+    assert_eq!(
+        decode(&ctx, &data)[0].value,
+        Value::Incomplete(0, data[1..].to_vec().into())
+    );
+
+    // The tenth byte may only hold the 64th bit.
+    let data = b"\x30\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02";
+    assert_eq!(
+        decode(&ctx, data)[0].value,
+        Value::Incomplete(0, data[1..].to_vec().into())
+    );
+    let data = b"\x30\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01";
+    assert_eq!(decode(&ctx, data)[0].value, Value::UInt64(u64::MAX));
+    assert_eq!(encode(&ctx, data), data);
+
+    // Overlong tags end the message.
+    let mut data = vec![0xff; 11];
+    data.push(0x00);
+    let node = ctx.get_message("hostile.Node").unwrap();
+    assert_eq!(node.decode(&data, &ctx).garbage, Some(data.into()));
+}
+
+#[test]
+fn out_of_range_varints()
+{
+    let ctx = context();
+
+    // Values that don't fit in 32 bits are kept as unknown varints.
+    let cases: &[&[u8]] = &[
+        b"\x18\x80\x80\x80\x80\x10",
+        b"\x28\x80\x80\x80\x80\x10",
+        b"\x38\x80\x80\x80\x80\x10",
+    ];
+    for data in cases {
+        assert_eq!(
+            decode(&ctx, data)[0].value,
+            Value::Unknown(UnknownValue::Varint(1 << 32))
+        );
+        assert_eq!(encode(&ctx, data), *data);
+    }
+
+    // Negative int32 values are sign extended to ten bytes.
+    let data = b"\x18\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01";
+    assert_eq!(decode(&ctx, data)[0].value, Value::Int32(-1));
+    assert_eq!(encode(&ctx, data), data);
+
+    assert_eq!(
+        decode(&ctx, b"\x38\xff\xff\xff\xff\x0f")[0].value,
+        Value::SInt32(i32::MIN)
+    );
+    assert_eq!(
+        decode(&ctx, b"\x68\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01")[0].value,
+        Value::Bool(true)
+    );
+    assert_eq!(
+        decode(&ctx, b"\xca\x01\x03\x00\xac\x02")[0].value,
+        Value::Packed(PackedArray::Bool(vec![false, true]))
+    );
+}
+
+#[test]
+fn malformed_packed_arrays()
+{
+    let ctx = context();
+
+    // The array ends in the middle of a varint or holds an out of range value. Decoding continues
+    // after the array.
+    let cases: &[&[u8]] = &[
+        b"\xaa\x01\x02\x01\x80\x18\x01",
+        b"\xb2\x01\x06\x01\x80\x80\x80\x80\x10\x18\x01",
+    ];
+    for data in cases {
+        let fields = decode(&ctx, data);
+        let array = &data[3..data.len() - 2];
+        assert_eq!(
+            fields[0].value,
+            Value::Unknown(UnknownValue::VariableLength(array.to_vec().into()))
+        );
+        assert_eq!(fields[1].value, Value::Int32(1));
+        assert_eq!(encode(&ctx, data), *data);
+    }
+}
+
+#[test]
+fn invalid_strings()
+{
+    let ctx = context();
+    let node = ctx.get_message("hostile.Node").unwrap();
+
+    // Strings that aren't valid UTF-8 keep their bytes. Map entries with such keys are kept as
+    // messages.
+    let data = b"\x72\x03a\xffb\x72\x02\xc3\xa9\x9a\x01\x05\x0a\x01\x80\x12\x00";
+    let fields = decode(&ctx, data);
+    assert_eq!(
+        fields[0].value,
+        Value::InvalidString(Bytes::from_static(b"a\xffb"))
+    );
+    assert_eq!(fields[1].value, Value::String("\u{e9}".to_string()));
+    match &fields[2].value {
+        Value::Message(entry) => assert_eq!(
+            entry.fields[0].value,
+            Value::InvalidString(Bytes::from_static(b"\x80"))
+        ),
+        other => panic!("Not a message: {:?}", other),
+    }
+    assert_eq!(encode(&ctx, data), data);
+
+    let msg = node.decode(data, &ctx);
+    #[cfg(feature = "json")]
+    {
+        let json = msg.to_json(&ctx);
+        assert_eq!(json["14"], serde_json::json!([{ "invalidString": "Yf9i" }]));
+        assert_eq!(node.decode_json(&json, &ctx).unwrap(), msg);
+    }
+    assert!(msg
+        .to_text_format(&ctx)
+        .starts_with("# 14: invalid UTF-8: \"a\\377b\"\n"));
+}
+
+#[test]
+fn extreme_values()
+{
+    let ctx = context();
+    let node = ctx.get_message("hostile.Node").unwrap();
+
+    let values = vec![
+        (3, Value::Int32(i32::MIN)),
+        (4, Value::Int64(i64::MIN)),
+        (7, Value::SInt32(i32::MIN)),
+        (7, Value::SInt32(i32::MAX)),
+        (8, Value::SInt64(i64::MIN)),
+        (8, Value::SInt64(i64::MAX)),
+        (
+            23,
+            Value::Packed(PackedArray::SInt32(vec![i32::MIN, i32::MAX])),
+        ),
+        (
+            24,
+            Value::Packed(PackedArray::SInt64(vec![i64::MIN, i64::MAX])),
+        ),
+        (100, Value::Int32(i32::MIN)),
+    ];
+    let msg = MessageValue {
+        msg_ref: node.self_ref,
+        fields: values
+            .into_iter()
+            .map(|(number, value)| FieldValue { number, value })
+            .collect(),
+        garbage: None,
+    };
+
+    let encoded = msg.encode(&ctx);
+    assert_eq!(node.decode(&encoded, &ctx), msg);
+}
+
+#[test]
+fn raw_deep_groups()
+{
+    // Groups nested far deeper than the stack could handle if they were all decoded.
+    let depth = 100_000;
+    let mut data = vec![0x0b; depth];
+    data.extend(vec![0x0c; depth]);
+
+    let msg = decode_raw(&data);
+    let mut levels = 0;
+    let mut value = &msg.fields[0].value;
+    while let RawValue::Group(group) = value {
+        levels += 1;
+        value = &group.fields[0].value;
+    }
+    assert_eq!(levels, 64);
+    match value {
+        RawValue::GroupBytes(bytes) => assert_eq!(bytes.len(), 2 * (depth - levels - 1)),
+        v => panic!("Unexpected value: {:?}", v),
+    }
+    assert_eq!(msg.encode(), data);
+}
+
+/// Bytes that look like a field: a tag followed by a payload.
+fn field() -> impl Strategy<Value = Vec<u8>>
+{
+    (
+        prop_oneof![1u64..32, 99u64..103, any::<u64>()],
+        0u64..8,
+        prop_oneof![
+            proptest::collection::vec(any::<u8>(), 0..16),
+            proptest::collection::vec(0x80u8.., 0..12),
+        ],
+        any::<bool>(),
+    )
+        .prop_map(|(number, wire_type, payload, length)| {
+            let mut data = vec![];
+            push_varint(&mut data, number << 3 | wire_type);
+            if length {
+                push_varint(&mut data, payload.len() as u64);
+            }
+            data.extend(payload);
+            data
+        })
+}
+
+fn push_varint(data: &mut Vec<u8>, mut value: u64)
+{
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+/// Fields nested inside length-delimited and group fields.
+fn message() -> impl Strategy<Value = Vec<u8>>
+{
+    let leaf = proptest::collection::vec(field(), 0..8).prop_map(|f| f.concat());
+    leaf.prop_recursive(4, 256, 8, |inner| {
+        proptest::collection::vec(
+            prop_oneof![
+                field(),
+                (
+                    prop::sample::select(vec![17u64, 19, 29, 101]),
+                    inner.clone()
+                )
+                    .prop_map(|(number, body)| {
+                        let mut data = vec![];
+                        push_varint(&mut data, number << 3 | 2);
+                        push_varint(&mut data, body.len() as u64);
+                        data.extend(body);
+                        data
+                    }),
+                inner.prop_map(|body| {
+                    let mut data = vec![0x93, 0x01];
+                    data.extend(body);
+                    data.extend_from_slice(&[0x94, 0x01]);
+                    data
+                }),
+            ],
+            0..8,
+        )
+        .prop_map(|f| f.concat())
+    })
+}
+
+proptest! {
+    #[test]
+    fn decode_arbitrary_bytes(data in proptest::collection::vec(any::<u8>(), 0..512))
+    {
+        let ctx = context();
+        let encoded = encode(&ctx, &data);
+        prop_assert_eq!(encode(&ctx, &encoded), encoded);
+    }
+
+    #[test]
+    fn decode_arbitrary_fields(data in message())
+    {
+        let ctx = context();
+        let encoded = encode(&ctx, &data);
+        prop_assert_eq!(encode(&ctx, &encoded), encoded);
+    }
+
+    #[test]
+    fn decode_with_limits(
+        data in message(),
+        limits in (0usize..4, 0usize..4, 0usize..1024, 0usize..8),
+    )
+    {
+        let ctx = context();
+        let node = ctx.get_message("hostile.Node").unwrap();
+        let mut options = DecodeOptions::default();
+        options.max_depth = limits.0;
+        options.max_repeated = limits.1;
+        options.max_total_bytes = limits.2;
+        options.max_string_len = limits.3;
+
+        // Values that exceed the limits are encoded back as they were.
+        let encoded = encode(&ctx, &data);
+        let limited = node.decode_with_options(&encoded, &ctx, &options);
+        prop_assert_eq!(limited.encode(&ctx).to_vec(), encoded);
+    }
+
+    #[test]
+    fn decode_bytes(data in message(), lazy_messages in any::<bool>())
+    {
+        let ctx = context();
+        let node = ctx.get_message("hostile.Node").unwrap();
+        let mut options = DecodeOptions::default();
+        options.lazy_messages = lazy_messages;
+
+        // Lazy messages are encoded back as they were so the data must be encoded the same way.
+        let encoded = bytes::Bytes::from(encode(&ctx, &data));
+        let msg = node.decode_bytes(&encoded, &ctx, &options);
+        prop_assert_eq!(msg.encode(&ctx), encoded);
+    }
+
+    #[test]
+    fn encode_scalars(
+        int32 in any::<i32>(),
+        int64 in any::<i64>(),
+        uint32 in any::<u32>(),
+        uint64 in any::<u64>(),
+        sint32 in proptest::collection::vec(any::<i32>(), 0..8),
+        sint64 in proptest::collection::vec(any::<i64>(), 0..8),
+    )
+    {
+        let ctx = context();
+        let node = ctx.get_message("hostile.Node").unwrap();
+        let values = vec![
+            (3, Value::Int32(int32)),
+            (4, Value::Int64(int64)),
+            (5, Value::UInt32(uint32)),
+            (6, Value::UInt64(uint64)),
+            (7, Value::SInt32(int32)),
+            (8, Value::SInt64(int64)),
+            (23, Value::Packed(PackedArray::SInt32(sint32))),
+            (24, Value::Packed(PackedArray::SInt64(sint64))),
+        ];
+        let msg = MessageValue {
+            msg_ref: node.self_ref,
+            fields: values
+                .into_iter()
+                .map(|(number, value)| FieldValue { number, value })
+                .collect(),
+            garbage: None,
+        };
+
+        prop_assert_eq!(node.decode(&msg.encode(&ctx), &ctx), msg);
+    }
+}
@@ -0,0 +1,212 @@
+#![cfg(feature = "json")]
+
+use protofish::context::Context;
+use protofish::http::HttpError;
+use serde_json::json;
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+    package library;
+
+    message Book {
+        string name = 1;
+        string title = 2;
+    }
+
+    message GetBookRequest {
+        string name = 1;
+        bool full = 2;
+        repeated string fields = 3;
+        Filter filter = 4;
+    }
+
+    message Filter { int64 min_pages = 1; }
+    message CreateBookRequest { string parent = 1; Book book = 2; }
+    message UpdateBookRequest { Book book = 1; }
+    message MoveBookRequest { string name = 1; string target = 2; }
+
+    service Library {
+        rpc GetBook(GetBookRequest) returns (Book) {
+            option (google.api.http) = {
+                get: "/v1/{name=shelves/*/books/*}"
+                additional_bindings { get: "/v1/books/{name}" }
+            };
+        }
+        rpc CreateBook(CreateBookRequest) returns (Book) {
+            option (google.api.http) = { post: "/v1/{parent=shelves/*}/books" body: "book" };
+        }
+        rpc UpdateBook(UpdateBookRequest) returns (Book) {
+            option (google.api.http) = { patch: "/v1/{book.name=shelves/*/books/*}" body: "*" };
+        }
+        rpc MoveBook(MoveBookRequest) returns (Book) {
+            option (google.api.http).post = "/v1/{name=**}:move";
+            option (google.api.http).body = "*";
+        }
+    }
+"#;
+
+#[test]
+fn http_rules()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let service = context.get_service("library.Library").unwrap();
+
+    let rules = service.rpc_by_name("GetBook").unwrap().http_rules();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[0].method, "GET");
+    assert_eq!(rules[0].path, "/v1/{name=shelves/*/books/*}");
+    assert_eq!(rules[0].body, None);
+    assert_eq!(rules[1].path, "/v1/books/{name}");
+
+    let rules = service.rpc_by_name("MoveBook").unwrap().http_rules();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].method, "POST");
+    assert_eq!(rules[0].body.as_deref(), Some("*"));
+}
+
+#[test]
+fn decode_http_request()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let decode = |method, path, body: Option<serde_json::Value>| {
+        let (service, rpc, msg) = context
+            .decode_http_request(method, path, body.as_ref())
+            .unwrap();
+        assert_eq!(service.full_name, "library.Library");
+        (rpc.name.clone(), msg.to_json(&context))
+    };
+
+    assert_eq!(
+        decode(
+            "GET",
+            "/v1/shelves/1/books/2?full=true&fields=a&fields=b+c&filter.minPages=10",
+            None
+        ),
+        (
+            "GetBook".to_string(),
+            json!({
+                "name": "shelves/1/books/2",
+                "full": true,
+                "fields": ["a", "b c"],
+                "filter": { "minPages": "10" },
+            })
+        )
+    );
+    assert_eq!(
+        decode("get", "/v1/books/a%2Fb", None),
+        ("GetBook".to_string(), json!({ "name": "a/b" }))
+    );
+    assert_eq!(
+        decode(
+            "POST",
+            "/v1/shelves/s1/books",
+            Some(json!({ "title": "Dune" }))
+        ),
+        (
+            "CreateBook".to_string(),
+            json!({ "parent": "shelves/s1", "book": { "title": "Dune" } })
+        )
+    );
+    assert_eq!(
+        decode(
+            "PATCH",
+            "/v1/shelves/1/books/2",
+            Some(json!({ "book": { "title": "Dune" } }))
+        ),
+        (
+            "UpdateBook".to_string(),
+            json!({ "book": { "name": "shelves/1/books/2", "title": "Dune" } })
+        )
+    );
+    assert_eq!(
+        decode("POST", "/v1/a/b/c:move", Some(json!({ "target": "d" }))),
+        (
+            "MoveBook".to_string(),
+            json!({ "name": "a/b/c", "target": "d" })
+        )
+    );
+
+    assert!(matches!(
+        context.decode_http_request("DELETE", "/v1/books/1", None),
+        Err(HttpError::NoMatchingBinding { .. })
+    ));
+    assert!(matches!(
+        context.decode_http_request("GET", "/v1/shelves/1", None),
+        Err(HttpError::NoMatchingBinding { .. })
+    ));
+    assert!(matches!(
+        context.decode_http_request("GET", "/v1/books/1?full=maybe", None),
+        Err(HttpError::InvalidRequest { .. })
+    ));
+}
+
+#[test]
+fn to_http_request()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let service = context.get_service("library.Library").unwrap();
+
+    let get_book = service.rpc_by_name("GetBook").unwrap();
+    let msg = context
+        .decode_json(
+            get_book.input.message,
+            &json!({
+                "name": "shelves/1/books/2",
+                "full": true,
+                "fields": ["a", "b c"],
+                "filter": { "minPages": "10" },
+            }),
+        )
+        .unwrap();
+    let request = msg.to_http_request(get_book, &context).unwrap();
+    assert_eq!(request.method, "GET");
+    assert_eq!(
+        request.path,
+        "/v1/shelves/1/books/2?fields=a&fields=b%20c&filter.minPages=10&full=true"
+    );
+    assert_eq!(request.body, None);
+
+    let (_, rpc, roundtrip) = context
+        .decode_http_request(&request.method, &request.path, None)
+        .unwrap();
+    assert_eq!(rpc.name, "GetBook");
+    assert_eq!(roundtrip, msg);
+
+    // The additional binding is used when the name doesn't match the primary one.
+    let msg = context
+        .decode_json(get_book.input.message, &json!({ "name": "a/b" }))
+        .unwrap();
+    let request = msg.to_http_request(get_book, &context).unwrap();
+    assert_eq!(request.path, "/v1/books/a%2Fb");
+
+    let create_book = service.rpc_by_name("CreateBook").unwrap();
+    let msg = context
+        .decode_json(
+            create_book.input.message,
+            &json!({ "parent": "shelves/s1", "book": { "title": "Dune" } }),
+        )
+        .unwrap();
+    let request = msg.to_http_request(create_book, &context).unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/v1/shelves/s1/books");
+    assert_eq!(request.body, Some(json!({ "title": "Dune" })));
+
+    let move_book = service.rpc_by_name("MoveBook").unwrap();
+    let msg = context
+        .decode_json(
+            move_book.input.message,
+            &json!({ "name": "a/b c", "target": "d" }),
+        )
+        .unwrap();
+    let request = msg.to_http_request(move_book, &context).unwrap();
+    assert_eq!(request.path, "/v1/a/b%20c:move");
+    assert_eq!(request.body, Some(json!({ "target": "d" })));
+
+    let empty = context
+        .decode_json(get_book.input.message, &json!({}))
+        .unwrap();
+    assert!(matches!(
+        empty.to_http_request(get_book, &context),
+        Err(HttpError::NoBinding { .. })
+    ));
+}
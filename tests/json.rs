@@ -0,0 +1,417 @@
+#![cfg(feature = "json")]
+
+use protofish::context::Context;
+use protofish::decode::{DecodeOptions, FieldValue, Value};
+use protofish::json::JsonError;
+use serde_json::json;
+
+const WELL_KNOWN: &str = r#"
+    syntax = "proto3";
+    package google.protobuf;
+
+    message Any { string type_url = 1; bytes value = 2; }
+    message Timestamp { int64 seconds = 1; int32 nanos = 2; }
+    message Duration { int64 seconds = 1; int32 nanos = 2; }
+    message Int64Value { int64 value = 1; }
+    message StringValue { string value = 1; }
+    message FieldMask { repeated string paths = 1; }
+    message Empty {}
+    message Struct { map<string, Value> fields = 1; }
+    message Value {
+        oneof kind {
+            NullValue null_value = 1;
+            double number_value = 2;
+            string string_value = 3;
+            bool bool_value = 4;
+            Struct struct_value = 5;
+            ListValue list_value = 6;
+        }
+    }
+    message ListValue { repeated Value values = 1; }
+    enum NullValue { NULL_VALUE = 0; }
+"#;
+
+#[test]
+fn scalars()
+{
+    let context = Context::parse([r#"
+        syntax = "proto3";
+        message Message {
+            int32 small_int = 1;
+            int64 big_int = 2;
+            fixed64 big_uint = 3;
+            bytes data = 4;
+            Kind kind = 5;
+            Kind other_kind = 6;
+            repeated sint32 numbers = 7;
+            map<int32, string> names = 8;
+            double nan = 9;
+            float ratio = 10;
+            string renamed = 11 [json_name = "custom"];
+            Message child = 12;
+        }
+        enum Kind { NONE = 0; SOME = 1; }
+    "#])
+    .unwrap();
+
+    let msg = context.get_message("Message").unwrap();
+    let value = msg.decode(
+        &[
+            b"\x08\x96\x01".as_ref(),
+            b"\x10\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01",
+            b"\x19\x01\x00\x00\x00\x00\x00\x00\x00",
+            b"\x22\x03abc",
+            b"\x28\x01",
+            b"\x30\x05",
+            b"\x3a\x02\x01\x02",
+            b"\x42\x05\x08\x01\x12\x01a",
+            b"\x49\x00\x00\x00\x00\x00\x00\xf8\x7f",
+            b"\x55\xcd\xcc\xcc\x3d",
+            b"\x5a\x01x",
+            b"\x62\x02\x08\x01",
+        ]
+        .concat(),
+        &context,
+    );
+
+    assert_eq!(
+        value.to_json(&context),
+        json!({
+            "smallInt": 150,
+            "bigInt": "-1",
+            "bigUint": "1",
+            "data": "YWJj",
+            "kind": "SOME",
+            "otherKind": 5,
+            "numbers": [-1, 1],
+            "names": { "1": "a" },
+            "nan": "NaN",
+            "ratio": 0.1,
+            "custom": "x",
+            "child": { "smallInt": 1 },
+        })
+    );
+}
+
+#[test]
+fn recovery_data()
+{
+    let context = Context::parse([r#"
+        syntax = "proto3";
+        message Message { int32 a = 1; string b = 3; }
+    "#])
+    .unwrap();
+
+    let msg = context.get_message("Message").unwrap();
+
+    // Unknown varint, length delimited value in place of a varint and an incomplete value.
+    let value = msg.decode(b"\x10\x05\x0a\x01x\x1a\x05ab", &context);
+    assert_eq!(
+        value.to_json(&context),
+        json!({
+            "2": [{ "varint": "5" }],
+            "1": [{ "bytes": "eA==" }],
+            "3": [{ "incomplete": "BWFi", "wireType": 2 }],
+        })
+    );
+
+    // Invalid wire type.
+    let value = msg.decode(b"\x08\x01\x0f\x01\x02", &context);
+    assert_eq!(
+        value.to_json(&context),
+        json!({
+            "a": 1,
+            "1": [{ "invalid": "AQI=", "wireType": 7 }],
+        })
+    );
+}
+
+#[test]
+fn well_known_types()
+{
+    let context = Context::parse([
+        WELL_KNOWN,
+        r#"
+        syntax = "proto3";
+        message Message {
+            google.protobuf.Timestamp time = 1;
+            google.protobuf.Duration duration = 2;
+            google.protobuf.Int64Value wrapped = 3;
+            google.protobuf.StringValue empty_wrapper = 4;
+            google.protobuf.FieldMask mask = 5;
+            google.protobuf.Empty empty = 6;
+            google.protobuf.Struct data = 7;
+            google.protobuf.Any any = 8;
+            google.protobuf.Any unknown_any = 9;
+            google.protobuf.Duration negative = 10;
+        }
+        "#,
+    ])
+    .unwrap();
+
+    let msg = context.get_message("Message").unwrap();
+    let value = msg.decode(
+        &[
+            // 2009-02-13T23:31:30.120Z
+            b"\x0a\x0b\x08\xd2\x85\xd8\xcc\x04\x10\x80\x9c\x9c\x39".as_ref(),
+            // 1.5s
+            b"\x12\x08\x08\x01\x10\x80\xca\xb5\xee\x01",
+            b"\x1a\x02\x08\x07",
+            b"\x22\x00",
+            b"\x2a\x10\x0a\x08foo_bar.\x0a\x04quux",
+            b"\x32\x00",
+            // {"a": null, "b": [true]}
+            b"\x3a\x16",
+            b"\x0a\x07\x0a\x01a\x12\x02\x08\x00",
+            b"\x0a\x0b\x0a\x01b\x12\x06\x32\x04\x0a\x02\x20\x01",
+            b"\x42\x30",
+            b"\x0a\x2ctype.googleapis.com/google.protobuf.Duration",
+            b"\x12\x00",
+            b"\x4a\x0e\x0a\x0aexample/Xy\x12\x00",
+            // -0.5s
+            b"\x52\x0b\x10\x80\xb6\xca\x91\xfe\xff\xff\xff\xff\x01",
+        ]
+        .concat(),
+        &context,
+    );
+
+    assert_eq!(
+        value.to_json(&context),
+        json!({
+            "time": "2009-02-13T23:31:30.120Z",
+            "duration": "1.500s",
+            "wrapped": "7",
+            "emptyWrapper": "",
+            "mask": "fooBar.,quux",
+            "empty": {},
+            "data": { "a": null, "b": [true] },
+            "any": {
+                "@type": "type.googleapis.com/google.protobuf.Duration",
+                "value": "0s",
+            },
+            "unknownAny": { "@type": "example/Xy", "@value": "" },
+            "negative": "-0.500s",
+        })
+    );
+
+    // Out of range timestamp is rendered as a regular message.
+    let time = context.get_message("google.protobuf.Timestamp").unwrap();
+    let value = time.decode(b"\x10\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01", &context);
+    assert_eq!(value.to_json(&context), json!({ "nanos": -1 }));
+
+    // Garbage prevents the special representation.
+    let value = time.decode(b"\x08\x01\xff", &context);
+    assert_eq!(
+        value.to_json(&context),
+        json!({ "seconds": "1", "@garbage": "/w==" })
+    );
+}
+
+/// `google.protobuf.Any` nested `depth` levels deep in the payloads of other `Any` values.
+fn nested_any(depth: usize) -> Vec<u8>
+{
+    let url = b"type.googleapis.com/google.protobuf.Any";
+
+    // Built backwards to avoid copying the payload for each level.
+    let mut reversed = vec![];
+    for _ in 0..depth {
+        let mut len = reversed.len();
+        let mut header = vec![0x0a, url.len() as u8];
+        header.extend_from_slice(url);
+        header.push(0x12);
+        while len >= 0x80 {
+            header.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        header.push(len as u8);
+        reversed.extend(header.iter().rev());
+    }
+    reversed.reverse();
+    reversed
+}
+
+#[test]
+fn nested_any_depth()
+{
+    let context = Context::parse([WELL_KNOWN]).unwrap();
+    let any = context.get_message("google.protobuf.Any").unwrap();
+    let url = "type.googleapis.com/google.protobuf.Any";
+
+    // The payloads past the depth limit are kept as base64.
+    let mut options = DecodeOptions::default();
+    options.max_depth = 2;
+    let value = any.decode(&nested_any(4), &context);
+    assert_eq!(
+        value.to_json_with_options(&context, &options),
+        json!({
+            "@type": url,
+            "value": {
+                "@type": url,
+                "value": { "@type": url, "@value": "Cid0eXBlLmdvb2dsZWFwaXMuY29tL2dvb2dsZS5wcm90b2J1Zi5BbnkSAA==" },
+            },
+        })
+    );
+
+    // Deeply nested payloads don't exhaust the stack.
+    let value = any.decode(&nested_any(20_000), &context);
+    let mut json = &value.to_json(&context);
+    let mut depth = 0;
+    while let Some(inner) = json.get("value") {
+        json = inner;
+        depth += 1;
+    }
+    assert_eq!(depth, 100);
+    assert!(json.get("@value").is_some());
+}
+
+#[test]
+fn decode_json()
+{
+    let context = Context::parse([r#"
+        syntax = "proto3";
+        message Message {
+            int32 small_int = 1;
+            sint64 big_int = 2;
+            Kind kind = 3;
+            repeated Kind kinds = 4;
+            repeated fixed32 numbers = 5;
+            map<bool, Message> children = 6;
+            string renamed = 7 [json_name = "custom"];
+        }
+        enum Kind { NONE = 0; SOME = 1; }
+    "#])
+    .unwrap();
+
+    let msg = context.get_message("Message").unwrap();
+    let value = msg
+        .decode_json(
+            &json!({
+                "small_int": "150",
+                "bigInt": "-9007199254740993",
+                "kind": "SOME",
+                "kinds": [1, "NONE", 7],
+                "numbers": [1, 2e0],
+                "children": { "true": { "smallInt": 1.0 } },
+                "custom": "x",
+            }),
+            &context,
+        )
+        .unwrap();
+
+    let encoded = value.encode(&context);
+    assert_eq!(msg.decode(&encoded, &context), value);
+    assert_eq!(
+        value.to_json(&context),
+        json!({
+            "smallInt": 150,
+            "bigInt": "-9007199254740993",
+            "kind": "SOME",
+            "kinds": ["SOME", "NONE", 7],
+            "numbers": [1, 2],
+            "children": { "true": { "smallInt": 1 } },
+            "custom": "x",
+        })
+    );
+    assert_eq!(value.fields[0].number, 1);
+    assert_eq!(value.fields[0].value, Value::Int32(150));
+
+    // Null is the same as a missing field.
+    let value = msg.decode_json(&json!({ "kind": null }), &context).unwrap();
+    assert_eq!(value.fields, vec![]);
+
+    let error = |json| match msg.decode_json(&json, &context) {
+        Ok(v) => panic!("Expected an error, got {:?}", v),
+        Err(e) => e,
+    };
+    match error(json!({ "children": { "true": { "nope": 1 } } })) {
+        JsonError::UnknownField { name, path } => {
+            assert_eq!(name, "nope");
+            assert_eq!(path, "$.children.true.nope");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    match error(json!({ "kinds": [0, "MISSING"] })) {
+        JsonError::InvalidValue { path, .. } => assert_eq!(path, "$.kinds[1]"),
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    match error(json!({ "smallInt": "4294967296" })) {
+        JsonError::InvalidValue { path, .. } => assert_eq!(path, "$.smallInt"),
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    match error(json!({ "custom": 5 })) {
+        JsonError::UnexpectedValue { expected, path } => {
+            assert_eq!(expected, "string");
+            assert_eq!(path, "$.custom");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    match error(json!({ "children": { "maybe": {} } })) {
+        JsonError::InvalidValue { path, .. } => assert_eq!(path, "$.children.maybe"),
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn json_roundtrip()
+{
+    let context = Context::parse([
+        WELL_KNOWN,
+        r#"
+        syntax = "proto3";
+        message Message {
+            int32 a = 1;
+            string b = 3;
+            google.protobuf.Timestamp time = 4;
+            google.protobuf.Duration duration = 5;
+            google.protobuf.Int64Value wrapped = 6;
+            google.protobuf.FieldMask mask = 7;
+            google.protobuf.Struct data = 8;
+            google.protobuf.Any any = 9;
+            google.protobuf.Any unknown_any = 10;
+            google.protobuf.Value null = 11;
+        }
+        "#,
+    ])
+    .unwrap();
+
+    let msg = context.get_message("Message").unwrap();
+    let json = json!({
+        "a": 1,
+        "2": [{ "varint": "5" }, { "fixed32": 7 }],
+        "3": [{ "incomplete": "BWFi", "wireType": 2 }],
+        "time": "1972-01-01T10:00:20.021Z",
+        "duration": "-1.000000001s",
+        "wrapped": "7",
+        "mask": "fooBar,baz",
+        "data": { "a": null, "b": [true, 1.5, "x", {}] },
+        "any": {
+            "@type": "type.googleapis.com/Message",
+            "a": 2,
+            "any": {
+                "@type": "type.googleapis.com/google.protobuf.Timestamp",
+                "value": "1970-01-01T00:00:00Z",
+            },
+        },
+        "unknownAny": { "@type": "example/Xy", "@value": "AQI=" },
+        "null": null,
+    });
+
+    let value = msg.decode_json(&json, &context).unwrap();
+    assert_eq!(value.to_json(&context), json);
+    assert_eq!(
+        value.fields[1],
+        FieldValue {
+            number: 2,
+            value: Value::Unknown(protofish::decode::UnknownValue::Varint(5)),
+        }
+    );
+
+    match msg.decode_json(&json!({ "any": { "@type": "example/Xy" } }), &context) {
+        Err(JsonError::InvalidValue { path, .. }) => assert_eq!(path, r#"$.any["@type"]"#),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    match msg.decode_json(&json!({ "time": "2001-02-30T00:00:00Z" }), &context) {
+        Err(JsonError::InvalidValue { path, .. }) => assert_eq!(path, "$.time"),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
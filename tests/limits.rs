@@ -0,0 +1,204 @@
+use bytes::Bytes;
+use protofish::context::Context;
+use protofish::decode::{DecodeLimit, DecodeOptions, FieldValue, PackedArray, Value};
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+
+    message Node {
+        Node child = 1;
+        string name = 2;
+        bytes data = 3;
+        repeated int32 values = 4;
+        repeated string tags = 5;
+    }
+"#;
+
+/// Message nested `depth` levels deep in the `child` field.
+fn nested(depth: usize) -> Vec<u8>
+{
+    let mut data = b"\x10\x01".to_vec();
+    for _ in 0..depth {
+        let mut outer = vec![0x0a];
+        let mut len = data.len();
+        while len >= 0x80 {
+            outer.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        outer.push(len as u8);
+        outer.extend(data);
+        data = outer;
+    }
+    data
+}
+
+fn child(value: &Value) -> &[FieldValue]
+{
+    match value {
+        Value::Message(msg) => &msg.fields,
+        other => panic!("Not a message: {:?}", other),
+    }
+}
+
+#[test]
+fn depth()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    let mut options = DecodeOptions::default();
+    options.max_depth = 2;
+    let data = nested(3);
+    let msg = node.decode_with_options(&data, &context, &options);
+    let inner = child(&child(&msg.fields[0].value)[0].value);
+    assert_eq!(
+        inner[0].value,
+        Value::LimitExceeded(DecodeLimit::Depth, 2, Bytes::from_static(b"\x02\x10\x01"))
+    );
+    assert_eq!(msg.encode(&context), data);
+
+    // The default limit protects the stack.
+    let data = nested(10_000);
+    let msg = context.decode(node.self_ref, &data);
+    assert_eq!(msg.encode(&context), data);
+}
+
+#[test]
+fn repeated()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    let mut options = DecodeOptions::default();
+    options.max_repeated = 3;
+
+    let data = b"\x2a\x01a\x2a\x01b\x2a\x01c\x2a\x01d\x22\x02\x01\x02\x22\x02\x03\x04";
+    let msg = node.decode_with_options(data, &context, &options);
+    assert_eq!(
+        msg.fields.iter().map(|f| &f.value).collect::<Vec<_>>(),
+        vec![
+            &Value::String("a".to_string()),
+            &Value::String("b".to_string()),
+            &Value::String("c".to_string()),
+            &Value::LimitExceeded(DecodeLimit::Repeated, 2, Bytes::from_static(b"\x01d")),
+            &Value::Packed(PackedArray::Int32(vec![1, 2])),
+            &Value::LimitExceeded(
+                DecodeLimit::Repeated,
+                2,
+                Bytes::from_static(b"\x02\x03\x04")
+            ),
+        ]
+    );
+    assert_eq!(msg.encode(&context), &data[..]);
+}
+
+#[test]
+fn string_length()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    let mut options = DecodeOptions::default();
+    options.max_string_len = 4;
+
+    let data = b"\x12\x04fish\x12\x05perch\x1a\x05\x00\x01\x02\x03\x04";
+    let msg = node.decode_with_options(data, &context, &options);
+    assert_eq!(msg.fields[0].value, Value::String("fish".to_string()));
+    assert_eq!(
+        msg.fields[1].value,
+        Value::LimitExceeded(
+            DecodeLimit::StringLength,
+            2,
+            Bytes::from_static(b"\x05perch")
+        )
+    );
+    assert_eq!(
+        msg.fields[2].value,
+        Value::LimitExceeded(
+            DecodeLimit::StringLength,
+            2,
+            Bytes::from_static(b"\x05\x00\x01\x02\x03\x04")
+        )
+    );
+    assert_eq!(msg.encode(&context), &data[..]);
+}
+
+#[test]
+fn total_bytes()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    // The limit is hit in the nested message. The rest of both messages is kept undecoded.
+    let mut options = DecodeOptions::default();
+    options.max_total_bytes = 3 * std::mem::size_of::<FieldValue>() + 6;
+
+    let data = b"\x0a\x0a\x12\x03abc\x12\x03def\x12\x03ghi";
+    let msg = node.decode_with_options(data, &context, &options);
+    let inner = child(&msg.fields[0].value);
+    assert_eq!(inner[0].value, Value::String("abc".to_string()));
+    assert_eq!(
+        inner[1].value,
+        Value::LimitExceeded(DecodeLimit::TotalBytes, 2, Bytes::from_static(b"\x03def"))
+    );
+    assert_eq!(
+        msg.fields[1].value,
+        Value::LimitExceeded(DecodeLimit::TotalBytes, 2, Bytes::from_static(b"\x03ghi"))
+    );
+    assert_eq!(msg.encode(&context), &data[..]);
+}
+
+#[test]
+fn lazy_total_bytes()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    // Lazy messages are charged to the total of the decode call that produced them. Each child
+    // fits the limit alone, but the second one exceeds it after the first one is decoded.
+    let mut options = DecodeOptions::default();
+    options.lazy_messages = true;
+    options.max_total_bytes = 4 * std::mem::size_of::<FieldValue>() + 5;
+
+    let data = Bytes::from_static(b"\x0a\x05\x12\x03abc\x0a\x05\x12\x03def");
+    let msg = node.decode_bytes(&data, &context, &options);
+    let lazy = |value: &Value| match value {
+        Value::LazyMessage(lazy) => lazy.get(&context).fields.clone(),
+        other => panic!("Not a lazy message: {:?}", other),
+    };
+    assert_eq!(
+        lazy(&msg.fields[0].value)[0].value,
+        Value::String("abc".to_string())
+    );
+    assert_eq!(
+        lazy(&msg.fields[1].value)[0].value,
+        Value::LimitExceeded(DecodeLimit::TotalBytes, 2, Bytes::from_static(b"\x03def"))
+    );
+    assert_eq!(msg.encode(&context), data);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn recovery_formats()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    let mut options = DecodeOptions::default();
+    options.max_string_len = 2;
+    let msg = node.decode_with_options(b"\x12\x03abc", &context, &options);
+
+    let json = msg.to_json(&context);
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "2": [{ "limitExceeded": "A2FiYw==", "limit": "max_string_len", "wireType": 2 }]
+        })
+    );
+    assert_eq!(node.decode_json(&json, &context).unwrap(), msg);
+
+    assert_eq!(
+        msg.to_text_format(&context),
+        "# 2: max_string_len exceeded, wire type 2: \"\\003abc\"\n"
+    );
+}
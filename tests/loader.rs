@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use protofish::context::{Context, FileId, FileSystemLoader, ImportKind, ParseError, ValueType};
+
+#[test]
+fn follow_imports()
+{
+    let mut files = HashMap::new();
+    files.insert(
+        "root.proto",
+        r#"
+            syntax = "proto3";
+            package root;
+            import "common/types.proto";
+            import weak "common/weak.proto";
+            message Root {
+                common.Type value = 1;
+                common.Reexported other = 2;
+                common.Weak weak = 3;
+            }
+        "#,
+    );
+    files.insert(
+        "common/types.proto",
+        r#"
+            syntax = "proto3";
+            package common;
+            import public "common/reexported.proto";
+            message Type {}
+        "#,
+    );
+    files.insert(
+        "common/reexported.proto",
+        r#"
+            syntax = "proto3";
+            package common;
+            message Reexported {}
+        "#,
+    );
+    files.insert(
+        "common/weak.proto",
+        r#"
+            syntax = "proto3";
+            package common;
+            message Weak {}
+        "#,
+    );
+
+    let context = Context::parse_with_loader(["root.proto"], &mut files).unwrap();
+
+    let root = context.get_message("root.Root").unwrap();
+    let ty = context.get_message("common.Type").unwrap();
+    let reexported = context.get_message("common.Reexported").unwrap();
+    assert_eq!(
+        root.get_field(1).unwrap().field_type,
+        ValueType::Message(ty.self_ref)
+    );
+    assert_eq!(
+        root.get_field(2).unwrap().field_type,
+        ValueType::Message(reexported.self_ref)
+    );
+    assert!(context.get_message("common.Weak").is_some());
+
+    assert_eq!(root.span.file, FileId(0));
+    let package = match root.parent {
+        protofish::context::TypeParent::Package(p) => context.resolve_package(p),
+        _ => panic!("Root was not a top level message"),
+    };
+    assert_eq!(package.path(), Some("root.proto"));
+    assert_eq!(package.name(), Some("root"));
+    assert_eq!(package.imports().len(), 2);
+    assert_eq!(package.imports()[0].path, "common/types.proto");
+    assert_eq!(package.imports()[0].kind, ImportKind::Default);
+    assert_eq!(package.imports()[1].kind, ImportKind::Weak);
+}
+
+#[test]
+fn import_visibility()
+{
+    let mut files = HashMap::new();
+    files.insert(
+        "root.proto",
+        r#"
+            syntax = "proto3";
+            import "middle.proto";
+            message Root { Leaf leaf = 1; }
+        "#,
+    );
+    files.insert(
+        "middle.proto",
+        r#"
+            syntax = "proto3";
+            import "leaf.proto";
+            message Middle { Leaf leaf = 1; }
+        "#,
+    );
+    files.insert(
+        "leaf.proto",
+        r#"
+            syntax = "proto3";
+            message Leaf {}
+        "#,
+    );
+
+    // Leaf is only visible to the middle.proto since it isn't imported publicly.
+    match Context::parse_with_loader(["root.proto"], &mut files).unwrap_err() {
+        ParseError::TypeNotFound { name, context, .. } => {
+            assert_eq!(name, "Leaf");
+            assert_eq!(context, "Root");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    // Middle alone is fine.
+    Context::parse_with_loader(["middle.proto"], &mut files).unwrap();
+}
+
+#[test]
+fn import_errors()
+{
+    let mut files = HashMap::new();
+    files.insert("a.proto", "syntax = \"proto3\";\nimport \"b.proto\";\n");
+    files.insert("b.proto", "syntax = \"proto3\";\n\nimport \"a.proto\";\n");
+    files.insert(
+        "missing.proto",
+        "syntax = \"proto3\";\nimport \"does-not-exist.proto\";\n",
+    );
+
+    match Context::parse_with_loader(["a.proto"], &mut files).unwrap_err() {
+        ParseError::ImportCycle { path, span } => {
+            assert_eq!(path, "a.proto");
+            assert_eq!(span.file, FileId(1));
+            assert_eq!(span.start.line, 3);
+            assert_eq!(span.path.as_deref(), Some("b.proto"));
+            assert_eq!(span.to_string(), "b.proto, line 3, column 1");
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    match Context::parse_with_loader(["missing.proto"], &mut files).unwrap_err() {
+        ParseError::FileNotFound { path, span } => {
+            assert_eq!(path, "does-not-exist.proto");
+            assert_eq!(span.start.line, 2);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    match Context::parse_with_loader(["nope.proto"], &mut files).unwrap_err() {
+        ParseError::FileNotFound { path, span } => {
+            assert_eq!(path, "nope.proto");
+            assert_eq!(span.path, None);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    files.insert(
+        "broken.proto",
+        "syntax = \"proto3\";\nimport \"c.proto\";\n",
+    );
+    files.insert("c.proto", "syntax = \"proto3\";\nmessage Broken {\n");
+    let err = Context::parse_with_loader(["broken.proto"], &mut files).unwrap_err();
+    match &err {
+        ParseError::SyntaxError { span, .. } => assert_eq!(span.path.as_deref(), Some("c.proto")),
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    assert!(
+        err.to_string().ends_with("(c.proto, line 3, column 1)"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn file_system_loader()
+{
+    let root = std::env::temp_dir().join(format!("protofish-loader-{}", std::process::id()));
+    let first = root.join("first");
+    let second = root.join("second");
+    std::fs::create_dir_all(first.join("pkg")).unwrap();
+    std::fs::create_dir_all(&second).unwrap();
+
+    std::fs::write(
+        first.join("pkg/main.proto"),
+        "syntax = \"proto3\";\nimport \"dep.proto\";\nmessage Main { Dep dep = 1; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        second.join("dep.proto"),
+        "syntax = \"proto3\";\nmessage Dep {}\n",
+    )
+    .unwrap();
+
+    let mut loader = FileSystemLoader::new([&first, &second]);
+    let context = Context::parse_with_loader(["pkg/main.proto"], &mut loader);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    let context = context.unwrap();
+    assert!(context.get_message("Main").is_some());
+    assert!(context.get_message("Dep").is_some());
+}
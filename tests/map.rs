@@ -0,0 +1,219 @@
+#[test]
+fn map()
+{
+    use bytes::BufMut;
+    use protofish::{
+        context::{Context, Multiplicity, ValueType},
+        decode::{FieldValue, MapEntryValue, MessageValue, Value},
+    };
+
+    let context = Context::parse([r#"
+      syntax = "proto3";
+      package Maps;
+      message Message {
+          map<string, int32> counts = 1;
+          map<uint32, Child> children = 2;
+      }
+      message Child {
+          string name = 1;
+      }
+    "#])
+    .unwrap();
+
+    let msg = context.get_message("Maps.Message").unwrap();
+
+    let counts = msg.get_field_by_name("counts").unwrap();
+    assert_eq!(counts.multiplicity, Multiplicity::Map);
+    let counts_entry = match counts.field_type {
+        ValueType::Message(m) => context.resolve_message(m),
+        _ => panic!("Map field type was not a message: {:?}", counts.field_type),
+    };
+    assert_eq!(counts_entry.full_name, "Maps.Message.CountsEntry");
+    assert_eq!(
+        counts_entry.get_field_by_name("key").unwrap().field_type,
+        ValueType::String
+    );
+    assert_eq!(
+        counts_entry.get_field_by_name("value").unwrap().field_type,
+        ValueType::Int32
+    );
+
+    let children = msg.get_field_by_name("children").unwrap();
+    let children_entry = match children.field_type {
+        ValueType::Message(m) => context.resolve_message(m),
+        _ => panic!(
+            "Map field type was not a message: {:?}",
+            children.field_type
+        ),
+    };
+    let child = context.get_message("Maps.Child").unwrap();
+    assert_eq!(
+        children_entry
+            .get_field_by_name("value")
+            .unwrap()
+            .field_type,
+        ValueType::Message(child.self_ref)
+    );
+
+    let mut payload = bytes::BytesMut::new();
+
+    payload.put_u8(1 << 3 | 2); // counts entry
+    payload.put_u8(7);
+    payload.put_slice(b"\x0a\x03foo"); // key
+    payload.put_slice(b"\x10\x2a"); // value
+
+    payload.put_u8(2 << 3 | 2); // children entry
+    payload.put_u8(9);
+    payload.put_slice(b"\x08\x05"); // key
+    payload.put_slice(b"\x12\x05"); // value
+    payload.put_slice(b"\x0a\x03bar");
+
+    payload.put_u8(1 << 3 | 2); // counts entry with an unknown field
+    payload.put_u8(9);
+    payload.put_slice(b"\x0a\x03baz"); // key
+    payload.put_slice(b"\x10\x01"); // value
+    payload.put_slice(b"\x18\x02"); // unknown
+
+    let value = msg.decode(&payload, &context);
+
+    assert_eq!(value.fields.len(), 3);
+    assert_eq!(
+        value.fields[0],
+        FieldValue {
+            number: 1,
+            value: Value::MapEntry(Box::new(MapEntryValue {
+                entry_ref: counts_entry.self_ref,
+                key: Some(Value::String("foo".to_string())),
+                value: Some(Value::Int32(42)),
+                value_first: false,
+            })),
+        }
+    );
+    assert_eq!(
+        value.fields[1],
+        FieldValue {
+            number: 2,
+            value: Value::MapEntry(Box::new(MapEntryValue {
+                entry_ref: children_entry.self_ref,
+                key: Some(Value::UInt32(5)),
+                value: Some(Value::Message(Box::new(MessageValue {
+                    msg_ref: child.self_ref,
+                    garbage: None,
+                    fields: vec![FieldValue {
+                        number: 1,
+                        value: Value::String("bar".to_string()),
+                    }],
+                }))),
+                value_first: false,
+            })),
+        }
+    );
+    match &value.fields[2].value {
+        Value::Message(m) => assert_eq!(m.fields.len(), 3),
+        other => panic!("Malformed entry was decoded as {:?}", other),
+    }
+
+    let encoded = value.encode(&context);
+    assert_eq!(payload, encoded);
+}
+
+#[test]
+fn map_entry_defaults()
+{
+    use protofish::{
+        context::{Context, ValueType},
+        decode::{MapEntryValue, Value},
+    };
+
+    let context = Context::parse([r#"
+      syntax = "proto3";
+      message Message {
+          map<string, int32> counts = 1;
+          map<int32, string> names = 2;
+      }
+    "#])
+    .unwrap();
+
+    let msg = context.get_message("Message").unwrap();
+    let entry = |number| match msg.get_field(number).unwrap().field_type {
+        ValueType::Message(m) => m,
+        ref t => panic!("Map field type was not a message: {:?}", t),
+    };
+
+    // Encoders omit the fields with default values and may write them in any order.
+    let payload = b"\x12\x03\x12\x01a\x0a\x03\x0a\x01b\x0a\x04\x10\x01\x0a\x00\x0a\x00";
+    let value = msg.decode(payload, &context);
+    assert_eq!(
+        value.fields.iter().map(|f| &f.value).collect::<Vec<_>>(),
+        vec![
+            &Value::MapEntry(Box::new(MapEntryValue {
+                entry_ref: entry(2),
+                key: None,
+                value: Some(Value::String("a".to_string())),
+                value_first: false,
+            })),
+            &Value::MapEntry(Box::new(MapEntryValue {
+                entry_ref: entry(1),
+                key: Some(Value::String("b".to_string())),
+                value: None,
+                value_first: false,
+            })),
+            &Value::MapEntry(Box::new(MapEntryValue {
+                entry_ref: entry(1),
+                key: Some(Value::String(String::new())),
+                value: Some(Value::Int32(1)),
+                value_first: true,
+            })),
+            &Value::MapEntry(Box::new(MapEntryValue {
+                entry_ref: entry(1),
+                key: None,
+                value: None,
+                value_first: false,
+            })),
+        ]
+    );
+
+    // The accessors fill in the omitted fields.
+    match &value.fields[0].value {
+        Value::MapEntry(entry) => {
+            assert_eq!(*entry.key_or_default(&context), Value::Int32(0));
+            assert_eq!(
+                *entry.value_or_default(&context),
+                Value::String("a".to_string())
+            );
+        }
+        other => panic!("Not a map entry: {:?}", other),
+    }
+
+    // The entries are encoded back as they were.
+    assert_eq!(value.encode(&context), &payload[..]);
+    assert_eq!(context.decode(msg.self_ref, &value.encode(&context)), value);
+
+    #[cfg(feature = "json")]
+    assert_eq!(
+        value.to_json(&context),
+        serde_json::json!({ "counts": { "b": 0, "": 0 }, "names": { "0": "a" } })
+    );
+}
+
+#[test]
+fn map_entry_garbage()
+{
+    use protofish::{context::Context, decode::Value};
+
+    let context = Context::parse([r#"
+      syntax = "proto3";
+      message Message { map<string, int32> counts = 1; }
+    "#])
+    .unwrap();
+
+    // Entries with garbage are kept as messages and encoded back as they were.
+    let msg = context.get_message("Message").unwrap();
+    let payload = b"\x0a\x04\x0a\x01a\x80";
+    let value = msg.decode(payload, &context);
+    match &value.fields[0].value {
+        Value::Message(entry) => assert_eq!(entry.garbage, Some(b"\x80"[..].into())),
+        other => panic!("Entry with garbage was decoded as {:?}", other),
+    }
+    assert_eq!(value.encode(&context), &payload[..]);
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use protofish::context::{Constant, Context, DESCRIPTOR_PROTO};
+use protofish::decode::{EnumValue, FieldValue, MessageValue, Value};
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+    package rules;
+
+    import "google/protobuf/descriptor.proto";
+
+    message StringRules {
+        optional uint64 min_len = 2;
+        optional string prefix = 3;
+    }
+
+    message FieldRules {
+        optional StringRules string = 14;
+        repeated int32 allowed = 2;
+        bool required = 3;
+    }
+
+    extend google.protobuf.FieldOptions {
+        optional FieldRules rules = 1071;
+    }
+
+    extend google.protobuf.MessageOptions {
+        optional bool disabled = 1072;
+    }
+
+    message Request {
+        option (rules.disabled) = true;
+
+        string name = 1 [
+            (rules.rules).string.min_len = 3,
+            (rules.rules).string.prefix = "x",
+            (rules).required = true,
+            deprecated = true,
+            ctype = CORD
+        ];
+        int32 id = 2 [(rules).allowed = 1, (rules).allowed = 2];
+    }
+"#;
+
+#[test]
+fn typed_options()
+{
+    let context = Context::parse([DESCRIPTOR_PROTO, PROTO]).unwrap();
+    let request = context.get_message("rules.Request").unwrap();
+    let name = request.get_field_by_name("name").unwrap();
+
+    let rules = match name.option_value("rules.rules", &context) {
+        Some(Value::Message(rules)) => rules,
+        v => panic!("Unexpected value: {:?}", v),
+    };
+    let string_rules = context.get_message("rules.StringRules").unwrap();
+    assert_eq!(
+        rules.fields,
+        vec![
+            FieldValue {
+                number: 14,
+                value: Value::Message(Box::new(MessageValue {
+                    msg_ref: string_rules.self_ref,
+                    fields: vec![
+                        FieldValue {
+                            number: 2,
+                            value: Value::UInt64(3),
+                        },
+                        FieldValue {
+                            number: 3,
+                            value: Value::String("x".to_string()),
+                        },
+                    ],
+                    garbage: None,
+                })),
+            },
+            FieldValue {
+                number: 3,
+                value: Value::Bool(true),
+            },
+        ]
+    );
+    assert_eq!(
+        name.option_value("(rules.rules)", &context),
+        Some(Value::Message(rules))
+    );
+
+    assert_eq!(
+        name.option_value("deprecated", &context),
+        Some(Value::Bool(true))
+    );
+    assert!(matches!(
+        name.option_value("ctype", &context),
+        Some(Value::Enum(EnumValue { value: 1, .. }))
+    ));
+    assert_eq!(name.option_value("lazy", &context), None);
+    assert_eq!(name.option_value("unknown", &context), None);
+
+    assert_eq!(
+        request.option_value("rules.disabled", &context),
+        Some(Value::Bool(true))
+    );
+
+    // Repeated options are merged into the same message and packed arrays.
+    let id = request.get_field_by_name("id").unwrap();
+    let options = id.typed_options(&context).unwrap();
+    assert_eq!(
+        options.encode(&context),
+        b"\xfa\x42\x04\x12\x02\x01\x02".as_ref()
+    );
+}
+
+#[test]
+fn out_of_range_options()
+{
+    let context = Context::parse([
+        DESCRIPTOR_PROTO,
+        r#"
+        syntax = "proto3";
+        import "google/protobuf/descriptor.proto";
+        extend google.protobuf.FieldOptions {
+            uint32 limit = 1073;
+            int32 offset = 1074;
+        }
+        message Message {
+            int32 a = 1 [(limit) = -1, (offset) = 3000000000];
+            int32 b = 2 [(limit) = 4294967295, (offset) = -2147483648];
+        }
+    "#,
+    ])
+    .unwrap();
+    let message = context.get_message("Message").unwrap();
+
+    let a = message.get_field(1).unwrap();
+    assert_eq!(a.option_value("limit", &context), None);
+    assert_eq!(a.option_value("offset", &context), None);
+
+    let b = message.get_field(2).unwrap();
+    assert_eq!(
+        b.option_value("limit", &context),
+        Some(Value::UInt32(u32::MAX))
+    );
+    assert_eq!(
+        b.option_value("offset", &context),
+        Some(Value::Int32(i32::MIN))
+    );
+}
+
+#[test]
+fn options_without_descriptor()
+{
+    let context = Context::parse([r#"
+        syntax = "proto3";
+        message Message { int32 value = 1 [deprecated = true]; }
+    "#])
+    .unwrap();
+    let field = context
+        .get_message("Message")
+        .unwrap()
+        .get_field(1)
+        .unwrap();
+    assert_eq!(field.typed_options(&context), None);
+    assert_eq!(field.option_value("deprecated", &context), None);
+}
+
+#[test]
+fn bundled_descriptor_import()
+{
+    let mut files = HashMap::new();
+    files.insert("rules.proto", PROTO);
+    let context = Context::parse_with_loader(["rules.proto"], &mut files).unwrap();
+
+    let request = context.get_message("rules.Request").unwrap();
+    assert_eq!(
+        request.option_value("rules.disabled", &context),
+        Some(Value::Bool(true))
+    );
+}
+
+const HTTP_PROTO: &str = r#"
+    syntax = "proto3";
+    package api;
+
+    import "google/protobuf/descriptor.proto";
+
+    message HttpRule {
+        string get = 2;
+        string post = 4;
+        string body = 7;
+        repeated HttpRule additional_bindings = 11;
+        repeated string tags = 12;
+    }
+
+    extend google.protobuf.MethodOptions {
+        HttpRule http = 72295728;
+    }
+
+    service Library {
+        rpc GetBook(Empty) returns (Empty) {
+            option (api.http) = {
+                get: "/v1/{name=books/*}"
+                additional_bindings { post: "/v1/" "books" body: "*" }
+                additional_bindings < post: '/v2' >,
+                tags: ["a", "b"];
+                tags: "c"
+            };
+        }
+    }
+
+    message Empty {}
+"#;
+
+#[test]
+fn aggregate_options()
+{
+    let context = Context::parse([DESCRIPTOR_PROTO, HTTP_PROTO]).unwrap();
+    let service = context.get_service("api.Library").unwrap();
+    let rpc = service.rpc_by_name("GetBook").unwrap();
+
+    let string = |s: &str| Constant::String(s.as_bytes().to_vec().into());
+    assert_eq!(rpc.options[0].name, "(api.http)");
+    assert_eq!(
+        rpc.options[0].value,
+        Constant::Aggregate(vec![
+            ("get".to_string(), string("/v1/{name=books/*}")),
+            (
+                "additional_bindings".to_string(),
+                Constant::Aggregate(vec![
+                    ("post".to_string(), string("/v1/books")),
+                    ("body".to_string(), string("*")),
+                ])
+            ),
+            (
+                "additional_bindings".to_string(),
+                Constant::Aggregate(vec![("post".to_string(), string("/v2"))])
+            ),
+            ("tags".to_string(), string("a")),
+            ("tags".to_string(), string("b")),
+            ("tags".to_string(), string("c")),
+        ])
+    );
+
+    let http = match rpc.option_value("api.http", &context) {
+        Some(Value::Message(http)) => http,
+        v => panic!("Unexpected value: {:?}", v),
+    };
+    let fields = http.fields.iter().map(|f| f.number).collect::<Vec<_>>();
+    assert_eq!(fields, vec![2, 11, 11, 12, 12, 12]);
+    match &http.fields[1].value {
+        Value::Message(binding) => assert_eq!(
+            binding.fields[0].value,
+            Value::String("/v1/books".to_string())
+        ),
+        v => panic!("Unexpected value: {:?}", v),
+    }
+
+    // Resolved options are stored in the descriptors as extension fields.
+    let set = context
+        .get_message("google.protobuf.FileDescriptorSet")
+        .unwrap()
+        .decode(&context.to_file_descriptor_set(), &context);
+    let field = |msg: &MessageValue, number| -> Vec<MessageValue> {
+        msg.fields
+            .iter()
+            .filter(|f| f.number == number)
+            .filter_map(|f| match &f.value {
+                Value::Message(m) => Some((**m).clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    let file = field(&set, 1)
+        .into_iter()
+        .find(|f| !field(f, 6).is_empty())
+        .unwrap();
+    let method = &field(&field(&file, 6)[0], 2)[0];
+    let options = &field(method, 4)[0];
+    assert_eq!(field(options, 72295728), vec![*http]);
+    assert!(field(options, 999).is_empty());
+}
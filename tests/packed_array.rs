@@ -71,3 +71,81 @@ fn repeated()
     let encoded = value.encode(&context);
     assert_eq!(payload, encoded);
 }
+
+#[test]
+fn packed_and_unpacked()
+{
+    use protofish::{
+        context::Context,
+        decode::{PackedArray, Value},
+    };
+
+    let context = Context::parse([r#"
+      syntax = "proto2";
+      message Message {
+          repeated int32 expanded = 1;
+          repeated fixed64 packed = 2 [packed = true];
+      }
+    "#])
+    .unwrap();
+
+    // Both encodings decode to typed values whatever the `packed` option says.
+    let payload = b"\x08\x01\x0a\x02\x02\x03\x11\x04\0\0\0\0\0\0\0\x12\x08\x05\0\0\0\0\0\0\0";
+    let msg = context.get_message("Message").unwrap();
+    let value = msg.decode(payload, &context);
+    assert_eq!(
+        value.fields.iter().map(|f| &f.value).collect::<Vec<_>>(),
+        vec![
+            &Value::Int32(1),
+            &Value::Packed(PackedArray::Int32(vec![2, 3])),
+            &Value::Fixed64(4),
+            &Value::Packed(PackedArray::Fixed64(vec![5])),
+        ]
+    );
+    assert_eq!(value.encode(&context), &payload[..]);
+}
+
+#[test]
+fn packed_enum()
+{
+    use protofish::{
+        context::{Context, ValueType},
+        decode::{EnumValue, PackedArray, Value},
+    };
+
+    let context = Context::parse([r#"
+      syntax = "proto3";
+      enum Kind { ZERO = 0; ONE = 1; NEGATIVE = -1; }
+      message Message {
+          repeated Kind packed = 1;
+          repeated Kind expanded = 2 [packed = false];
+      }
+    "#])
+    .unwrap();
+    let msg = context.get_message("Message").unwrap();
+    let enum_ref = match msg.get_field(1).unwrap().field_type {
+        ValueType::Enum(e) => e,
+        ref t => panic!("Unexpected type: {:?}", t),
+    };
+    let kind = |value| EnumValue { enum_ref, value };
+
+    // Both encodings decode to enum values whatever the `packed` option says.
+    let payload =
+        b"\x0a\x0c\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01\x00\x10\x01\x12\x02\x00\x01";
+    let value = msg.decode(payload, &context);
+    assert_eq!(
+        value.fields.iter().map(|f| &f.value).collect::<Vec<_>>(),
+        vec![
+            &Value::Packed(PackedArray::Enum(vec![kind(1), kind(-1), kind(0)])),
+            &Value::Enum(kind(1)),
+            &Value::Packed(PackedArray::Enum(vec![kind(0), kind(1)])),
+        ]
+    );
+    assert_eq!(value.encode(&context), &payload[..]);
+
+    let text = value.to_text_format(&context);
+    assert_eq!(
+        text,
+        "packed: ONE\npacked: NEGATIVE\npacked: ZERO\nexpanded: ONE\nexpanded: ZERO\nexpanded: ONE\n"
+    );
+}
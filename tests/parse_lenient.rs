@@ -0,0 +1,140 @@
+#[test]
+fn parse_lenient()
+{
+    use protofish::context::{Context, ParseError, ValueType};
+
+    let (context, errors) = Context::parse_lenient([
+        r#"
+            syntax = "proto3";
+            package Good;
+            message Message { Other other = 1; }
+            message Other { string value = 1; }
+            message Duplicate {}
+        "#,
+        r#"
+            syntax = "proto3";
+            package Broken;
+            message Message { string value = 1 }
+        "#,
+        r#"
+            syntax = "proto3";
+            package Good;
+            message Duplicate {}
+            message Missing { DoesNotExist value = 1; }
+            message DependsOnMissing { Missing value = 1; }
+            service Service {
+                rpc Call( Other ) returns ( Missing );
+            }
+            message Remaining { Other value = 1; }
+        "#,
+    ]);
+
+    assert_eq!(errors.len(), 5, "{:#?}", errors);
+    assert!(matches!(errors[0], ParseError::SyntaxError { .. }));
+    assert!(
+        matches!(&errors[1], ParseError::DuplicateType { name, .. } if name == "Good.Duplicate"),
+        "{:?}",
+        errors[1]
+    );
+    assert!(errors[2..]
+        .iter()
+        .all(|e| matches!(e, ParseError::TypeNotFound { .. })));
+
+    let other = context.get_message("Good.Other").unwrap();
+    let message = context.get_message("Good.Message").unwrap();
+    assert_eq!(
+        message.get_field(1).unwrap().field_type,
+        ValueType::Message(other.self_ref)
+    );
+    assert!(context.get_message("Good.Duplicate").is_some());
+    assert!(context.get_message("Good.Remaining").is_some());
+    assert!(context.get_message("Good.Missing").is_none());
+    assert!(context.get_message("Good.DependsOnMissing").is_none());
+    assert!(context.get_service("Good.Service").is_none());
+    assert!(context.get_message("Broken.Message").is_none());
+}
+
+#[test]
+fn parse_lenient_dependents()
+{
+    use protofish::context::{Context, ParseError};
+
+    let (context, errors) = Context::parse_lenient([r#"
+        syntax = "proto2";
+        message A { optional Missing value = 1; }
+        message B { optional A a = 1; }
+        message C { optional B b = 1; }
+        message D { extensions 100 to 200; }
+        extend D { optional A a = 100; }
+        extend D { optional string first = 101; }
+        message E { extend D { optional string second = 101; } }
+        message F { optional E e = 1; }
+    "#]);
+
+    let names: Vec<_> = errors
+        .iter()
+        .map(|e| match e {
+            ParseError::TypeNotFound { name, context, .. } => format!("{} in {}", name, context),
+            ParseError::DuplicateExtension { name, .. } => format!("duplicate {}", name),
+            e => panic!("Unexpected error: {:?}", e),
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "Missing in A",
+            "A in a",
+            "A in B",
+            "B in C",
+            "duplicate E.second",
+            "E in F",
+        ]
+    );
+
+    assert!(context.get_message("D").is_some());
+    assert!(context.get_extension("first").is_some());
+    for name in ["A", "B", "C", "E", "F"] {
+        assert!(context.get_message(name).is_none(), "{}", name);
+    }
+}
+
+#[test]
+fn parse_lenient_no_errors()
+{
+    use protofish::context::Context;
+
+    let files = [r#"
+        syntax = "proto3";
+        message Message { string value = 1; }
+    "#];
+
+    let (context, errors) = Context::parse_lenient(files);
+    assert!(errors.is_empty());
+    assert_eq!(context, Context::parse(files).unwrap());
+}
+
+#[test]
+fn parse_lenient_service_field()
+{
+    use protofish::context::{Context, ItemType, ParseError};
+
+    let (context, errors) = Context::parse_lenient([r#"
+        syntax = "proto3";
+        service S {}
+        message M { S f = 1; }
+        message Ok {}
+    "#]);
+
+    match &errors[..] {
+        [ParseError::InvalidTypeKind {
+            type_name, actual, ..
+        }] => {
+            assert_eq!(type_name, "S");
+            assert_eq!(*actual, ItemType::Service);
+        }
+        e => panic!("Unexpected errors: {:?}", e),
+    }
+    assert!(context.get_message("M").is_none());
+    assert!(context.get_message("Ok").is_some());
+    assert!(context.get_service("S").is_some());
+}
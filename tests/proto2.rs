@@ -0,0 +1,162 @@
+use protofish::context::{Context, Multiplicity, Syntax, TypeParent, ValueType};
+use protofish::decode::{EnumValue, FieldValue, Value};
+
+const PROTO: &str = r#"
+    syntax = "proto2";
+    package legacy;
+
+    message Request {
+        required string name = 1;
+        optional int32 count = 2 [default = -5];
+        optional double ratio = 3 [default = 1.5];
+        optional Kind kind = 4 [default = SOME];
+        optional bytes data = 5 [default = "\001x"];
+        optional float limit = 6 [default = inf];
+        repeated int32 ids = 7;
+        repeated int32 packed_ids = 8 [packed = true];
+
+        repeated group Item = 9 {
+            required uint64 id = 1;
+            optional string label = 2 [default = "none"];
+        }
+
+        extensions 100 to 199, 500, 1000 to max;
+    }
+
+    enum Kind { NONE = 0; SOME = 1; }
+"#;
+
+#[test]
+fn parse_proto2()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let request = context.get_message("legacy.Request").unwrap();
+
+    let package = match request.parent {
+        TypeParent::Package(p) => context.resolve_package(p),
+        p => panic!("Unexpected parent: {:?}", p),
+    };
+    assert_eq!(package.syntax(), Syntax::Proto2);
+
+    let field = |name| request.get_field_by_name(name).unwrap();
+    assert_eq!(field("name").multiplicity, Multiplicity::Required);
+    assert_eq!(field("count").multiplicity, Multiplicity::Optional);
+    assert_eq!(field("ids").multiplicity, Multiplicity::Repeated);
+    assert_eq!(
+        field("packed_ids").multiplicity,
+        Multiplicity::RepeatedPacked
+    );
+
+    let item = context.get_message("legacy.Request.Item").unwrap();
+    assert_eq!(field("item").number, 9);
+    assert_eq!(field("item").multiplicity, Multiplicity::Repeated);
+    assert_eq!(field("item").field_type, ValueType::Group(item.self_ref));
+    assert_eq!(
+        item.get_field(1).unwrap().multiplicity,
+        Multiplicity::Required
+    );
+
+    assert_eq!(
+        request.extension_ranges,
+        vec![100..=199, 500..=500, 1000..=536_870_911]
+    );
+}
+
+#[test]
+fn default_values()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let request = context.get_message("legacy.Request").unwrap();
+    let kind = context.get_type("legacy.Kind").unwrap();
+
+    let default = |name| {
+        request
+            .get_field_by_name(name)
+            .unwrap()
+            .default_value(&context)
+    };
+    assert_eq!(default("name"), None);
+    assert_eq!(default("count"), Some(Value::Int32(-5)));
+    assert_eq!(default("ratio"), Some(Value::Double(1.5)));
+    assert_eq!(default("limit"), Some(Value::Float(f32::INFINITY)));
+    assert_eq!(
+        default("data"),
+        Some(Value::Bytes(b"\x01x".as_ref().into()))
+    );
+    match default("kind") {
+        Some(Value::Enum(EnumValue { enum_ref, value })) => {
+            assert_eq!(context.resolve_enum(enum_ref).full_name, kind.full_name());
+            assert_eq!(value, 1);
+        }
+        v => panic!("Unexpected default: {:?}", v),
+    }
+
+    let item = context.get_message("legacy.Request.Item").unwrap();
+    assert_eq!(
+        item.get_field(2).unwrap().default_value(&context),
+        Some(Value::String("none".to_string()))
+    );
+}
+
+#[test]
+fn decode_proto2()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let request = context.get_message("legacy.Request").unwrap();
+
+    let data = b"\x0a\x01a\x38\x01\x38\x02\x42\x02\x03\x04\x4b\x08\x07\x4c";
+    let value = request.decode(data, &context);
+    assert_eq!(value.fields[1].value, Value::Int32(1));
+    assert_eq!(value.fields[2].value, Value::Int32(2));
+    match &value.fields[4].value {
+        Value::Group(item) => assert_eq!(
+            item.fields,
+            vec![FieldValue {
+                number: 1,
+                value: Value::UInt64(7),
+            }]
+        ),
+        v => panic!("Unexpected value: {:?}", v),
+    }
+    assert_eq!(value.encode(&context), data.as_ref());
+}
+
+#[test]
+fn missing_syntax_is_proto2()
+{
+    let context = Context::parse([r#"
+        message Message { repeated int32 values = 1; }
+    "#])
+    .unwrap();
+    let msg = context.get_message("Message").unwrap();
+    assert_eq!(
+        msg.get_field(1).unwrap().multiplicity,
+        Multiplicity::Repeated
+    );
+}
+
+#[test]
+fn proto2_descriptor_roundtrip()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let roundtrip = Context::from_file_descriptor_set(&context.to_file_descriptor_set()).unwrap();
+    let original = context.get_message("legacy.Request").unwrap();
+    let request = roundtrip.get_message("legacy.Request").unwrap();
+    match request.parent {
+        TypeParent::Package(p) => assert_eq!(roundtrip.resolve_package(p).syntax(), Syntax::Proto2),
+        p => panic!("Unexpected parent: {:?}", p),
+    }
+    assert_eq!(request.extension_ranges, original.extension_ranges);
+    for (field, original) in request.iter_fields().zip(original.iter_fields()) {
+        assert_eq!(field.name, original.name);
+        assert_eq!(field.multiplicity, original.multiplicity);
+        assert_eq!(field.options, original.options);
+    }
+    assert_eq!(
+        request
+            .get_field_by_name("data")
+            .unwrap()
+            .default_value(&roundtrip),
+        Some(Value::Bytes(b"\x01x".as_ref().into()))
+    );
+}
@@ -0,0 +1,104 @@
+use protofish::raw::{decode_raw, RawField, RawMessage, RawValue};
+
+fn field(number: u64, wire_type: u8, value: RawValue) -> RawField
+{
+    RawField {
+        number,
+        wire_type,
+        value,
+    }
+}
+
+#[test]
+fn decode_raw_heuristics()
+{
+    let data = [
+        b"\x08\x96\x01".as_ref(),
+        b"\x11\x01\x00\x00\x00\x00\x00\x00\x00",
+        b"\x1d\x02\x00\x00\x00",
+        // String.
+        b"\x22\x05hello",
+        // Nested message with a string and a packed array.
+        b"\x2a\x09\x0a\x02hi\x12\x03\x01\x96\x01",
+        // Bytes.
+        b"\x32\x02\x00\xff",
+        // Empty value.
+        b"\x3a\x00",
+    ]
+    .concat();
+
+    let msg = decode_raw(&data);
+    assert_eq!(
+        msg,
+        RawMessage {
+            fields: vec![
+                field(1, 0, RawValue::Varint(150)),
+                field(2, 1, RawValue::Fixed64(1)),
+                field(3, 5, RawValue::Fixed32(2)),
+                field(4, 2, RawValue::String("hello".to_string())),
+                field(
+                    5,
+                    2,
+                    RawValue::Message(Box::new(RawMessage {
+                        fields: vec![
+                            field(1, 2, RawValue::String("hi".to_string())),
+                            field(2, 2, RawValue::Packed(vec![1, 150])),
+                        ],
+                        garbage: None,
+                    }))
+                ),
+                field(6, 2, RawValue::Bytes(b"\x00\xff".as_ref().into())),
+                field(7, 2, RawValue::String(String::new())),
+            ],
+            garbage: None,
+        }
+    );
+    assert_eq!(msg.encode(), data);
+}
+
+#[test]
+fn decode_raw_recovery()
+{
+    // Incomplete value.
+    let data = b"\x08\x01\x12\x05abc";
+    let msg = decode_raw(data);
+    assert_eq!(
+        msg.fields,
+        vec![
+            field(1, 0, RawValue::Varint(1)),
+            field(2, 2, RawValue::Incomplete(b"\x05abc".as_ref().into())),
+        ]
+    );
+    assert_eq!(msg.encode(), data.as_ref());
+
+    // Invalid wire type.
+    let data = b"\x08\x01\x16\x01\x02";
+    let msg = decode_raw(data);
+    assert_eq!(
+        msg.fields,
+        vec![
+            field(1, 0, RawValue::Varint(1)),
+            field(2, 6, RawValue::Invalid(b"\x01\x02".as_ref().into())),
+        ]
+    );
+    assert_eq!(msg.encode(), data.as_ref());
+
+    // Garbage.
+    let data = b"\x08\x01\x80";
+    let msg = decode_raw(data);
+    assert_eq!(msg.fields, vec![field(1, 0, RawValue::Varint(1))]);
+    assert_eq!(msg.garbage, Some(b"\x80".as_ref().into()));
+    assert_eq!(msg.encode(), data.as_ref());
+
+    // Nested values that do not decode cleanly are kept as bytes.
+    let data = b"\x0a\x03\x08\x01\x80\x12\x02\x80\x00";
+    let msg = decode_raw(data);
+    assert_eq!(
+        msg.fields,
+        vec![
+            field(1, 2, RawValue::Bytes(b"\x08\x01\x80".as_ref().into())),
+            field(2, 2, RawValue::Bytes(b"\x80\x00".as_ref().into())),
+        ]
+    );
+    assert_eq!(msg.encode(), data.as_ref());
+}
@@ -0,0 +1,139 @@
+use protofish::context::{Context, FileId, ParseError, Position, TypeInfo};
+
+#[test]
+fn item_spans()
+{
+    let context = Context::parse([
+        "syntax = \"proto3\";\n",
+        r#"syntax = "proto3";
+package Spans;
+
+message Message {
+    string value = 1;
+    Kind kind = 2;
+}
+
+enum Kind {
+    A = 0;
+}
+
+service Service {
+    rpc Call( Message ) returns ( Message );
+}
+"#,
+    ])
+    .unwrap();
+
+    let msg = context.get_message("Spans.Message").unwrap();
+    assert_eq!(msg.span.file, FileId(1));
+    assert_eq!(msg.span.start, Position { line: 4, column: 1 });
+    assert_eq!(msg.span.end, Position { line: 7, column: 2 });
+
+    let field = msg.get_field_by_name("kind").unwrap();
+    assert_eq!(field.span.file, FileId(1));
+    assert_eq!(field.span.start, Position { line: 6, column: 5 });
+    assert_eq!(
+        field.span.end,
+        Position {
+            line: 6,
+            column: 19
+        }
+    );
+
+    let kind = match context.get_type("Spans.Kind").unwrap() {
+        TypeInfo::Enum(e) => e,
+        _ => panic!("Kind was not an enum"),
+    };
+    assert_eq!(kind.span.start, Position { line: 9, column: 1 });
+
+    let service = context.get_service("Spans.Service").unwrap();
+    assert_eq!(
+        service.span.start,
+        Position {
+            line: 13,
+            column: 1
+        }
+    );
+    let rpc = service.rpc_by_name("Call").unwrap();
+    assert_eq!(rpc.span.file, FileId(1));
+    assert_eq!(
+        rpc.span.start,
+        Position {
+            line: 14,
+            column: 5
+        }
+    );
+}
+
+#[test]
+fn error_spans()
+{
+    let err = Context::parse([
+        "syntax = \"proto3\";\n",
+        "syntax = \"proto3\";\nmessage Message {\n    Missing value = 1;\n}\n",
+    ])
+    .unwrap_err();
+    match err {
+        ParseError::TypeNotFound { span, .. } => {
+            assert_eq!(span.file, FileId(1));
+            assert_eq!(span.start, Position { line: 3, column: 5 });
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    let err = Context::parse([
+        "syntax = \"proto3\";\nmessage Message {}\n",
+        "syntax = \"proto3\";\n\nmessage Message {}\n",
+    ])
+    .unwrap_err();
+    match err {
+        ParseError::DuplicateType { span, .. } => {
+            assert_eq!(span.file, FileId(1));
+            assert_eq!(span.start, Position { line: 3, column: 1 });
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+
+    let err = Context::parse(["syntax = \"proto3\";\nmessage Message {\n    string value;\n}\n"])
+        .unwrap_err();
+    match &err {
+        ParseError::SyntaxError { span, .. } => {
+            assert_eq!(span.file, FileId(0));
+            assert_eq!(span.start.line, 3);
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    assert!(err.to_string().contains("Parsing error"));
+
+    let err = Context::parse([
+        "syntax = \"proto3\";\nenum Kind { A = 0; }\nservice Service {\n  rpc Call( Kind ) returns ( Kind );\n}\n",
+    ])
+    .unwrap_err();
+    match &err {
+        ParseError::InvalidTypeKind { span, .. } => {
+            assert_eq!(
+                span.start,
+                Position {
+                    line: 4,
+                    column: 13
+                }
+            );
+        }
+        e => panic!("Unexpected error: {:?}", e),
+    }
+    assert!(err.to_string().contains("file 0, line 4, column 13"));
+}
+
+#[test]
+fn compare_spans()
+{
+    let compact = "syntax = \"proto3\"; message Message { string value = 1; }";
+    let spaced = "syntax = \"proto3\";\n\nmessage Message {\n    string value = 1;\n}\n";
+
+    // The spans differ but are left out when comparing the items.
+    let first = Context::parse([compact]).unwrap();
+    let second = Context::parse([spaced]).unwrap();
+    let span = |ctx: &Context| ctx.get_message("Message").unwrap().span;
+    assert_ne!(span(&first), span(&second));
+    assert_eq!(first, second);
+}
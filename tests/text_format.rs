@@ -0,0 +1,247 @@
+use protofish::context::Context;
+use protofish::decode::{FieldValue, PackedArray, UnknownValue, Value};
+use protofish::text_format::TextFormatError;
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+    package test;
+
+    message Message {
+        string name = 1;
+        repeated int32 ids = 2;
+        Kind kind = 3;
+        map<string, Inner> inners = 4;
+        bytes data = 5;
+        double ratio = 6;
+        repeated Kind kinds = 7;
+        Inner inner = 8;
+        google.protobuf.Any any = 9;
+    }
+    message Inner { sint64 value = 1; }
+    enum Kind { NONE = 0; SOME = 1; }
+"#;
+
+const ANY: &str = r#"
+    syntax = "proto3";
+    package google.protobuf;
+    message Any { string type_url = 1; bytes value = 2; }
+"#;
+
+#[test]
+fn print()
+{
+    let context = Context::parse([PROTO, ANY]).unwrap();
+    let msg = context.get_message("test.Message").unwrap();
+
+    let value = msg.decode(
+        &[
+            b"\x0a\x07a\"b\n\xc3\xa4!".as_ref(),
+            b"\x12\x02\x01\x02",
+            b"\x18\x05",
+            b"\x22\x07\x0a\x01k\x12\x02\x08\x03",
+            b"\x2a\x02\x00\xff",
+            b"\x31\x00\x00\x00\x00\x00\x00\xf8\x3f",
+            // Unknown fields.
+            b"\x50\x96\x01",
+            b"\x5d\x01\x00\x00\x00",
+            b"\x61\x02\x00\x00\x00\x00\x00\x00\x00",
+            b"\x6a\x04\x08\x01\x10\x02",
+            b"\x72\x03abc",
+            // Incomplete value.
+            b"\x42\x05\x08",
+        ]
+        .concat(),
+        &context,
+    );
+
+    assert_eq!(
+        value.to_text_format(&context),
+        r#"name: "a\"b\n\303\244!"
+ids: 1
+ids: 2
+kind: 5
+inners {
+  key: "k"
+  value {
+    value: -2
+  }
+}
+data: "\000\377"
+ratio: 1.5
+10: 150
+11: 0x00000001
+12: 0x0000000000000002
+13 {
+  1: 1
+  2: 2
+}
+14: "abc"
+# 8: incomplete wire type 2: "\005\010"
+"#
+    );
+}
+
+#[test]
+fn parse()
+{
+    let context = Context::parse([PROTO, ANY]).unwrap();
+    let msg = context.get_message("test.Message").unwrap();
+
+    let value = msg
+        .decode_text_format(
+            r#"
+            # Comment.
+            name: "a\"b" 'c\x41\101ä'
+            ids: [1, 0x2]; ids: -3
+            kind: SOME
+            kinds: [NONE, 1]
+            inners { key: "k" value < value: 5 > }
+            inners { key: "empty" }
+            inner {}
+            ratio: -inf
+            data: "\377"
+            10: 150
+            11: 0x00000001
+            13 { 1: 1 2: "x" }
+            any {
+                [type.googleapis.com/test.Inner] { value: -1 }
+            }
+            "#,
+            &context,
+        )
+        .unwrap();
+
+    let roundtrip = msg.decode(&value.encode(&context), &context);
+    assert_eq!(roundtrip, value);
+
+    assert_eq!(
+        value.fields[0],
+        FieldValue {
+            number: 1,
+            value: Value::String("a\"bcAAä".to_string()),
+        }
+    );
+    assert_eq!(
+        value.fields[1],
+        FieldValue {
+            number: 2,
+            value: Value::Packed(PackedArray::Int32(vec![1, 2, -3])),
+        }
+    );
+    assert_eq!(
+        value.fields[10],
+        FieldValue {
+            number: 10,
+            value: Value::Unknown(UnknownValue::Varint(150)),
+        }
+    );
+    assert_eq!(
+        value.fields[11],
+        FieldValue {
+            number: 11,
+            value: Value::Unknown(UnknownValue::Fixed32(1)),
+        }
+    );
+    assert_eq!(
+        value.fields[12],
+        FieldValue {
+            number: 13,
+            value: Value::Unknown(UnknownValue::VariableLength(
+                b"\x08\x01\x12\x01x".as_ref().into()
+            )),
+        }
+    );
+
+    let any = match &value.fields[13].value {
+        Value::Message(any) => any,
+        v => panic!("Unexpected value: {:?}", v),
+    };
+    assert_eq!(
+        any.fields[0].value,
+        Value::String("type.googleapis.com/test.Inner".to_string())
+    );
+    assert_eq!(
+        any.fields[1].value,
+        Value::Bytes(b"\x08\x01".as_ref().into())
+    );
+
+    // Printing and parsing again gives the same value.
+    let text = value.to_text_format(&context);
+    assert_eq!(msg.decode_text_format(&text, &context).unwrap(), value);
+    assert!(text.contains("inners {\n  key: \"empty\"\n}\n"));
+}
+
+#[test]
+fn print_deep_unknown_fields()
+{
+    let context = Context::parse([PROTO, ANY]).unwrap();
+    let msg = context.get_message("test.Message").unwrap();
+
+    // Unknown groups nested far deeper than the stack could handle if they were all printed.
+    let depth = 100_000;
+    let mut data = vec![0x7b; depth];
+    data.extend(vec![0x7c; depth]);
+    let text = msg.decode(&data, &context).to_text_format(&context);
+
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.iter().filter(|l| l.ends_with("15 {")).count(), 64);
+    assert!(lines[64].trim_start().starts_with("# 15: group: \"{{"));
+    assert!(lines[64].ends_with("||\""));
+
+    // Variable length values past the limit are printed as strings.
+    let mut data = b"\x08\x01".to_vec();
+    for _ in 0..70 {
+        let mut outer = b"\x52".to_vec();
+        let len = data.len();
+        match len < 0x80 {
+            true => outer.push(len as u8),
+            false => outer.extend([len as u8 | 0x80, (len >> 7) as u8]),
+        }
+        outer.extend(data);
+        data = outer;
+    }
+    let text = msg.decode(&data, &context).to_text_format(&context);
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.iter().filter(|l| l.ends_with("10 {")).count(), 64);
+    assert!(lines[64].trim_start().starts_with("10: \"R"));
+}
+
+#[test]
+fn parse_errors()
+{
+    let context = Context::parse([PROTO, ANY]).unwrap();
+    let msg = context.get_message("test.Message").unwrap();
+
+    match msg.decode_text_format("name: \"x\"\nnope: 1", &context) {
+        Err(TextFormatError::UnknownField { name, position }) => {
+            assert_eq!(name, "nope");
+            assert_eq!((position.line, position.column), (2, 1));
+        }
+        r => panic!("Unexpected result: {:?}", r),
+    }
+
+    match msg.decode_text_format("inner { value: 1.5 }", &context) {
+        Err(TextFormatError::InvalidValue {
+            field, position, ..
+        }) => {
+            assert_eq!(field, "value");
+            assert_eq!((position.line, position.column), (1, 16));
+        }
+        r => panic!("Unexpected result: {:?}", r),
+    }
+
+    match msg.decode_text_format("kind: OTHER", &context) {
+        Err(TextFormatError::InvalidValue { field, .. }) => assert_eq!(field, "kind"),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+
+    match msg.decode_text_format("name: [\"a\"]", &context) {
+        Err(TextFormatError::InvalidValue { field, .. }) => assert_eq!(field, "name"),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+
+    match msg.decode_text_format("name: \"a", &context) {
+        Err(TextFormatError::SyntaxError { position, .. }) => assert_eq!(position.line, 1),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
@@ -0,0 +1,101 @@
+use bytes::Bytes;
+use protofish::context::Context;
+use protofish::decode::{DecodeOptions, UnknownValue, Value};
+
+const PROTO: &str = r#"
+    syntax = "proto3";
+
+    message Node {
+        Node child = 1;
+        bytes data = 2;
+        map<string, Node> children = 3;
+    }
+"#;
+
+/// True, if `value` shares the memory of `input`.
+fn is_slice_of(value: &Bytes, input: &Bytes) -> bool
+{
+    let range = input.as_ptr() as usize..input.as_ptr() as usize + input.len();
+    range.contains(&(value.as_ptr() as usize))
+}
+
+#[test]
+fn decode_bytes()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    let input = Bytes::from_static(b"\x12\x03abc\x0a\x05\x12\x03def\x22\x02xy\x12\x05gh");
+    let msg = node.decode_bytes(&input, &context, &DecodeOptions::default());
+    assert_eq!(msg, node.decode(&input, &context));
+
+    let values = match &msg.fields[1].value {
+        Value::Message(child) => vec![&msg.fields[0].value, &child.fields[0].value],
+        other => panic!("Not a message: {:?}", other),
+    };
+    for value in values {
+        match value {
+            Value::Bytes(b) => assert!(is_slice_of(b, &input)),
+            other => panic!("Not bytes: {:?}", other),
+        }
+    }
+    match &msg.fields[2].value {
+        Value::Unknown(UnknownValue::VariableLength(b)) => assert!(is_slice_of(b, &input)),
+        other => panic!("Not unknown: {:?}", other),
+    }
+    match &msg.fields[3].value {
+        Value::Incomplete(2, b) => assert!(is_slice_of(b, &input)),
+        other => panic!("Not incomplete: {:?}", other),
+    }
+}
+
+#[test]
+fn lazy_messages()
+{
+    let context = Context::parse([PROTO]).unwrap();
+    let node = context.get_message("Node").unwrap();
+
+    let mut options = DecodeOptions::default();
+    options.lazy_messages = true;
+
+    let input = Bytes::from_static(b"\x0a\x07\x0a\x05\x12\x03abc\x1a\x07\x0a\x01k\x12\x02\x12\x00");
+    let msg = node.decode_bytes(&input, &context, &options);
+    let eager = node.decode(&input, &context);
+    assert_eq!(msg.encode(&context), input);
+
+    // The nested messages are decoded when they are accessed. Map entries are decoded right away,
+    // but the messages in them are lazy.
+    let lazy = match &msg.fields[0].value {
+        Value::LazyMessage(lazy) => lazy,
+        other => panic!("Not a lazy message: {:?}", other),
+    };
+    assert!(is_slice_of(&lazy.data, &input));
+    let child = match &lazy.get(&context).fields[0].value {
+        Value::LazyMessage(child) => child,
+        other => panic!("Not a lazy message: {:?}", other),
+    };
+    let eager_child = match &eager.fields[0].value {
+        Value::Message(msg) => &msg.fields[0].value,
+        other => panic!("Not a message: {:?}", other),
+    };
+    assert_eq!(
+        Value::Message(Box::new(child.get(&context).clone())),
+        *eager_child
+    );
+    assert_eq!(
+        Value::Message(Box::new(child.clone().into_message(&context))),
+        *eager_child
+    );
+
+    match &msg.fields[1].value {
+        Value::MapEntry(entry) => {
+            assert_eq!(entry.key, Some(Value::String("k".to_string())));
+            assert!(matches!(entry.value, Some(Value::LazyMessage(..))));
+        }
+        other => panic!("Not a map entry: {:?}", other),
+    }
+
+    #[cfg(feature = "json")]
+    assert_eq!(msg.to_json(&context), eager.to_json(&context));
+    assert_eq!(msg.to_text_format(&context), eager.to_text_format(&context));
+}